@@ -187,14 +187,14 @@ info "2. Public read endpoints"
 RESP=$(api_req GET /blog/get/home_blogs)
 assert_status "GET /blog/get/home_blogs" 200 "$RESP" > /dev/null
 
-RESP=$(api_req GET /blog/get/1/by_page)
-assert_status "GET /blog/get/1/by_page" 200 "$RESP" > /dev/null
+RESP=$(api_req GET "/blog/get/all?limit=2")
+assert_status "GET /blog/get/all" 200 "$RESP" > /dev/null
 
 RESP=$(api_req GET /book/get/home_books)
 assert_status "GET /book/get/home_books" 200 "$RESP" > /dev/null
 
-RESP=$(api_req GET /book/get/1/by_page)
-assert_status "GET /book/get/1/by_page" 200 "$RESP" > /dev/null
+RESP=$(api_req GET "/book/get/all?limit=2")
+assert_status "GET /book/get/all" 200 "$RESP" > /dev/null
 
 if [ -n "$USER1_ID" ]; then
     RESP=$(api_req GET "/blog/get/${USER1_ID}/user_blogs")
@@ -0,0 +1,114 @@
+use crate::error::AppError;
+use crate::search::index::DocKind;
+use crate::trash::restore_doc;
+use crate::utils::doc::TagTable;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct ModerateDoc {
+    doc_id: i32,
+}
+
+/// Soft-delete a document and its live nodes regardless of owner. Nodes get
+/// the document's `deleted_at`, which is what `restore` keys on.
+async fn moderate_delete(
+    pool: &AppState,
+    table: TagTable,
+    kind: DocKind,
+    admin_id: i32,
+    doc_id: i32,
+) -> Result<serde_json::Value, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let current_time = Local::now();
+    let state1 = conn
+        .prepare(&format!(
+            "UPDATE {} SET deleted_at=$1 WHERE uid=$2 AND deleted_at IS NULL",
+            table.doc_table()
+        ))
+        .await?;
+    let state2 = conn
+        .prepare(&format!(
+            "UPDATE {} SET deleted_at=$1 WHERE doc_id=$2 AND deleted_at IS NULL",
+            table.node_table()
+        ))
+        .await?;
+    let transaction = conn.transaction().await?;
+    let deleted = transaction.execute(&state1, &[&current_time, &doc_id]).await?;
+    if deleted == 0 {
+        return Err(AppError::NotFound(format!("No live document with uid {doc_id}")));
+    }
+    transaction.execute(&state2, &[&current_time, &doc_id]).await?;
+    transaction.commit().await?;
+
+    pool.search().remove_doc(kind, doc_id);
+    tracing::info!(admin_id, doc_id, doc_type = table.doc_type(), "document soft-deleted by moderator");
+
+    Ok(json!({ "doc_id": doc_id, "deleted_at": current_time }))
+}
+
+/// Undo a soft-delete on any owner's document; see `trash::restore_doc`.
+async fn moderate_restore(
+    pool: &AppState,
+    table: TagTable,
+    admin_id: i32,
+    doc_id: i32,
+) -> Result<serde_json::Value, AppError> {
+    let nodes = restore_doc(pool, table, doc_id, None).await?;
+    tracing::info!(admin_id, doc_id, doc_type = table.doc_type(), "document restored by moderator");
+
+    Ok(json!({ "doc_id": doc_id, "restored_nodes": nodes }))
+}
+
+fn ok(body: serde_json::Value) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(body),
+    )
+}
+
+pub async fn moderate_delete_blog(
+    Extension(UserId(admin_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<ModerateDoc>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = moderate_delete(&pool, TagTable::Blog, DocKind::Blog, admin_id, body.doc_id).await?;
+    Ok(ok(result))
+}
+
+pub async fn moderate_restore_blog(
+    Extension(UserId(admin_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<ModerateDoc>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = moderate_restore(&pool, TagTable::Blog, admin_id, body.doc_id).await?;
+    Ok(ok(result))
+}
+
+pub async fn moderate_delete_book(
+    Extension(UserId(admin_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<ModerateDoc>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = moderate_delete(&pool, TagTable::Book, DocKind::Book, admin_id, body.doc_id).await?;
+    Ok(ok(result))
+}
+
+pub async fn moderate_restore_book(
+    Extension(UserId(admin_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<ModerateDoc>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = moderate_restore(&pool, TagTable::Book, admin_id, body.doc_id).await?;
+    Ok(ok(result))
+}
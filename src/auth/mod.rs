@@ -1,3 +1,5 @@
+pub mod permissions;
+
 use axum::http;
 use std::str::FromStr;
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
@@ -0,0 +1,140 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use crate::error::AppError;
+use crate::types::Claims;
+
+/// Permission codes from docs/permissions.md. `Moderate` is not part of the
+/// content codes; it gates acting on other users' documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read = 1,
+    Write = 2,
+    Update = 3,
+    Delete = 4,
+    Moderate = 5,
+}
+
+/// Bitmask of granted permissions; bit `n` is set for permission code `n`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PermissionSet(u32);
+
+impl PermissionSet {
+    pub const fn of(permissions: &[Permission]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < permissions.len() {
+            bits |= 1 << permissions[i] as u32;
+            i += 1;
+        }
+        PermissionSet(bits)
+    }
+
+    pub fn contains(&self, permission: Permission) -> bool {
+        self.0 & (1 << permission as u32) != 0
+    }
+
+    pub fn granted(&self) -> Vec<Permission> {
+        use Permission::*;
+        [Read, Write, Update, Delete, Moderate]
+            .into_iter()
+            .filter(|p| self.contains(*p))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    User,
+    Reader,
+}
+
+impl Role {
+    /// Tokens without a role, or with one we do not know, keep the regular
+    /// user rights every authenticated account had before roles existed.
+    pub fn from_claim(role: Option<&str>) -> Self {
+        match role.map(str::to_lowercase).as_deref() {
+            Some("admin") => Role::Admin,
+            Some("reader") => Role::Reader,
+            _ => Role::User,
+        }
+    }
+
+    pub fn permissions(&self) -> PermissionSet {
+        use Permission::*;
+        match self {
+            Role::Admin => PermissionSet::of(&[Read, Write, Update, Delete, Moderate]),
+            Role::User => PermissionSet::of(&[Read, Write, Update, Delete]),
+            Role::Reader => PermissionSet::of(&[Read]),
+        }
+    }
+}
+
+/// Role and permission set of the caller, injected alongside `UserId` by the
+/// auth middleware. Use as an extractor on any authenticated handler.
+#[derive(Clone, Copy, Debug)]
+pub struct Permissions {
+    pub role: Role,
+    pub set: PermissionSet,
+}
+
+impl Permissions {
+    pub fn from_claims(claims: &Claims) -> Self {
+        let role = Role::from_claim(claims.role.as_deref());
+        Permissions { role, set: role.permissions() }
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.set.contains(permission)
+    }
+
+    pub fn require(&self, permission: Permission) -> Result<(), AppError> {
+        if self.has(permission) {
+            Ok(())
+        } else {
+            Err(AppError::Error((
+                StatusCode::FORBIDDEN,
+                format!("{:?} permission required", permission),
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Permissions {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Permissions>()
+            .copied()
+            .ok_or_else(|| AppError::Error((StatusCode::UNAUTHORIZED, "Unauthorized".into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        let admin = Role::from_claim(Some("Admin")).permissions();
+        assert!(admin.contains(Permission::Moderate));
+        assert!(admin.contains(Permission::Delete));
+
+        let user = Role::from_claim(None).permissions();
+        assert!(user.contains(Permission::Write));
+        assert!(!user.contains(Permission::Moderate));
+        assert_eq!(Role::from_claim(Some("author")), Role::User);
+
+        let reader = Role::from_claim(Some("reader")).permissions();
+        assert!(reader.contains(Permission::Read));
+        assert!(!reader.contains(Permission::Write));
+        assert_eq!(reader, PermissionSet(0b10));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::AppError;
+use crate::search::index::DocKind;
 use crate::AppState;
 use axum::{
     extract::State,
@@ -37,7 +38,7 @@ pub async fn delete_blog_node(
         .prepare("UPDATE blog SET parent_id=$1 WHERE uid=$2 AND user_id=$3")
         .await?;
     let transaction = conn.transaction().await?;
-    transaction
+    let deleted = transaction
         .execute(&state1, &[&current_time, &body.delete_node.uid, &user_id])
         .await?;
     if let Some(update_node) = &body.update_node {
@@ -47,6 +48,10 @@ pub async fn delete_blog_node(
     }
     transaction.commit().await?;
 
+    if deleted > 0 {
+        pool.search().remove_nodes(DocKind::Blog, &[body.delete_node.uid]);
+    }
+
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
@@ -70,16 +75,20 @@ pub async fn delete_blog(
         .prepare("UPDATE blogs SET deleted_at=$1 WHERE uid=$2 AND user_id=$3")
         .await?;
     let state2 = conn
-        .prepare("UPDATE blog SET deleted_at=$1 WHERE doc_id=$2 AND user_id=$3")
+        .prepare("UPDATE blog SET deleted_at=$1 WHERE doc_id=$2 AND user_id=$3 AND deleted_at IS NULL")
         .await?;
     let transaction = conn.transaction().await?;
-    transaction
+    let deleted = transaction
         .execute(&state1, &[&current_time, &body.doc_id, &user_id])
         .await?;
     transaction
         .execute(&state2, &[&current_time, &body.doc_id, &user_id])
         .await?;
     transaction.commit().await?;
+
+    if deleted > 0 {
+        pool.search().remove_doc(DocKind::Blog, body.doc_id);
+    }
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
@@ -1,7 +1,13 @@
-use crate::{error::AppError, fetch_home_blogs, fetch_blogs_by_user_id};
+use crate::{error::AppError, fetch_home_blogs, fetch_blogs_by_tag, fetch_blogs_by_user_id};
+use crate::utils::content::node_html;
+use crate::utils::doc::{fetch_tags, normalize_tag, tag_cloud, TagCloudRequest, TagTable};
+use crate::utils::page::{PageRequest, DEFAULT_PAGE_SIZE, HOME_PAGE_SIZE};
+use crate::utils::UserId;
+use crate::recommend::record_view;
+use crate::lifecycle::ensure_visible;
 use crate::AppState;
 use axum::{
-    extract::{Path as AxumPath, Query, State},
+    extract::{Extension, Path as AxumPath, Query, State},
     http::{header, StatusCode},
     response::IntoResponse,
     Json,
@@ -18,20 +24,29 @@ pub struct GetAllBlogs {
     content: String,
     images: String,
     created_at: DateTime<Utc>,
+    status: String,
+    like_count: i32,
+    bookmark_count: i32,
     doc_type: u8,
 }
 
-pub async fn get_all_blogs_by_page_no(
+/// Rows newer than the cursor come first; `(created_at, uid)` breaks ties so
+/// pages never overlap or skip rows when new blogs land between requests.
+pub async fn get_all_blogs(
     State(pool): State<AppState>,
-    AxumPath(page_no): AxumPath<i64>,
+    query: Query<PageRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
     let conn = pool.pg_pool.conn.get().await?;
-    let limit: i64 = 2;
-    let offset: i64 = (page_no - 1) * limit;
     let rows = conn
         .query(
-            "SELECT uid, title, content, images, created_at FROM blogs where deleted_at is null LIMIT $1 OFFSET $2",
-            &[&limit, &offset],
+            &format!("SELECT uid, title, content, images, created_at, status, like_count, bookmark_count FROM blogs where deleted_at is null AND status = 'published' \
+             AND {after} \
+             {order} LIMIT $3",
+                after = keyset.after("blogs", "", 1),
+                order = keyset.order_by(""),
+            ),
+            &[&keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
         )
         .await?;
 
@@ -44,6 +59,9 @@ pub async fn get_all_blogs_by_page_no(
             content: rows[index].get(2),
             images: rows[index].get(3),
             created_at: rows[index].get(4),
+            status: rows[index].get(5),
+            like_count: rows[index].get(6),
+            bookmark_count: rows[index].get(7),
             doc_type: 1,
         });
     }
@@ -51,19 +69,28 @@ pub async fn get_all_blogs_by_page_no(
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(blogs),
+        Json(keyset.page(blogs, |b| (b.created_at, b.uid))),
     ))
 }
 
 pub async fn get_all_blogs_by_user_id(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
     let conn = pool.pg_pool.conn.get().await?;
     let rows = conn
         .query(
-            "SELECT uid, title, content, images, created_at FROM blogs where deleted_at is null and user_id=$1",
-            &[&user_id],
+            &format!("SELECT uid, title, content, images, created_at, status, like_count, bookmark_count FROM blogs where deleted_at is null and user_id=$1 \
+             AND (status = 'published' OR user_id = $5::int4) \
+             AND {after} \
+             {order} LIMIT $4",
+                after = keyset.after("blogs", "", 2),
+                order = keyset.order_by(""),
+            ),
+            &[&user_id, &keyset.created_at, &keyset.uid, &keyset.fetch_limit(), &user.map(|Extension(UserId(id))| id)],
         )
         .await?;
 
@@ -76,6 +103,9 @@ pub async fn get_all_blogs_by_user_id(
             content: rows[index].get(2),
             images: rows[index].get(3),
             created_at: rows[index].get(4),
+            status: rows[index].get(5),
+            like_count: rows[index].get(6),
+            bookmark_count: rows[index].get(7),
             doc_type: 1,
         });
     }
@@ -83,7 +113,7 @@ pub async fn get_all_blogs_by_user_id(
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(blogs),
+        Json(keyset.page(blogs, |b| (b.created_at, b.uid))),
     ))
 }
 
@@ -114,12 +144,15 @@ pub struct BlogInfo {
 }
 
 pub async fn get_all_blog_nodes(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     query: Query<BlogNodesRequestById>,
 ) -> Result<impl IntoResponse, AppError> {
     let blog_request: BlogNodesRequestById = query.0;
+    let viewer = user.map(|Extension(UserId(id))| id);
 
     let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, TagTable::Blog, blog_request.doc_id, viewer).await?;
     let rows = conn
         .query(
             "SELECT uid, parent_id, title, content, images, created_at FROM blog where doc_id=$1 and deleted_at is null and parent_id is not null",
@@ -141,6 +174,10 @@ pub async fn get_all_blog_nodes(
         images: blog_row.get(4),
         created_at: blog_row.get(5),
     };
+    let tags = fetch_tags(&conn, TagTable::Blog, blog_request.doc_id).await?;
+    if let Some(user_id) = viewer {
+        record_view(&conn, TagTable::Blog, user_id, blog_request.doc_id).await;
+    }
 
     let mut child_nodes: Vec<BlogNode> = Vec::new();
 
@@ -161,17 +198,22 @@ pub async fn get_all_blog_nodes(
         [(header::CONTENT_TYPE, "application/json")],
         Json(json!({
             "child_nodes": child_nodes,
-            "main_node": main_node
+            "main_node": main_node,
+            "tags": tags
         })),
     ))
 }
 
 pub async fn get_users_blog(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
     let conn = pool.pg_pool.conn.get().await?;
-    let blogs = fetch_blogs_by_user_id!(&conn, user_id)?;
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let blogs = fetch_blogs_by_user_id!(&conn, user_id, viewer, keyset)?;
     // let doc_ids_user_tags_query = "SELECT doc_id FROM blog_tags where user_id=$1";
     // let doc_id_rows = conn.query(doc_ids_user_tags_query, &[&user_id]).await?;
 
@@ -208,18 +250,65 @@ pub async fn get_users_blog(
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(blogs),
+        Json(keyset.page(blogs, |b| (b.created_at, b.uid))),
     ))
 }
 
 pub async fn get_home_blogs(
     State(pool): State<AppState>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(HOME_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let blogs = fetch_home_blogs!(&conn, keyset)?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(keyset.page(blogs, |b| (b.created_at, b.uid))),
+    ))
+}
+pub async fn get_blogs_by_tag(
+    State(pool): State<AppState>,
+    AxumPath(tag): AxumPath<String>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let tag = normalize_tag(&tag)?;
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let blogs = fetch_blogs_by_tag!(&conn, tag, keyset)?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(keyset.page(blogs, |b| (b.created_at, b.uid))),
+    ))
+}
+
+pub async fn get_blog_tag_cloud(
+    State(pool): State<AppState>,
+    query: Query<TagCloudRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    let tags = tag_cloud(&conn, TagTable::Blog, query.limit()).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(tags),
+    ))
+}
+
+/// GET /blog/node/:uid/html — the node rendered to sanitised HTML for its
+/// content type.
+pub async fn get_blog_node_html(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(uid): AxumPath<i32>,
 ) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
     let conn = pool.pg_pool.conn.get().await?;
-    let blogs = fetch_home_blogs!(&conn)?;
+    let node = node_html(&conn, TagTable::Blog, uid, viewer).await?;
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(blogs),
+        Json(node),
     ))
-}
\ No newline at end of file
+}
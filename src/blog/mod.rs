@@ -1,9 +1,16 @@
 pub mod delete;
 pub mod get;
-mod utils;
+pub mod upload;
+pub(crate) mod utils;
 
 use crate::error::AppError;
+use crate::lifecycle::Lifecycle;
+use crate::revision::{record_revision, snapshot_original};
+use crate::search::index::{DocKind, IndexNode};
 use crate::traits::{move_images_to_s3, Images};
+use crate::utils::chain::{move_doc_node, MoveNode};
+use crate::utils::content::ContentType;
+use crate::utils::doc::{insert_tags, normalize_tags, TagTable};
 use crate::AppState;
 use axum::{
     extract::State,
@@ -11,6 +18,7 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -20,6 +28,9 @@ pub struct CreateBlog {
     content: String,
     images: Vec<Images>,
     tags: Vec<String>,
+    status: Option<String>,
+    publish_at: Option<DateTime<Utc>>,
+    content_type: Option<ContentType>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -29,6 +40,7 @@ pub struct EditBlog {
     title: String,
     content: String,
     images: Vec<Images>,
+    content_type: Option<ContentType>,
 }
 
 pub async fn create_blog(
@@ -37,14 +49,18 @@ pub async fn create_blog(
     Json(body): Json<CreateBlog>,
 ) -> Result<impl IntoResponse, AppError> {
     let images = &serde_json::to_string(&body.images).unwrap();
+    let tags = normalize_tags(&body.tags);
+    let lifecycle = Lifecycle::resolve(body.status.as_deref(), body.publish_at, Utc::now())?;
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
 
     let mut conn = pool.pg_pool.conn.get().await?;
 
     let insert_blogs_query = conn
-        .prepare("INSERT INTO blogs(user_id, title, content, images) VALUES($1, $2, $3, $4) RETURNING uid")
+        .prepare("INSERT INTO blogs(user_id, title, content, images, status, publish_at) VALUES($1, $2, $3, $4, $5, $6) RETURNING uid")
         .await?;
     let insert_blog_query = conn
-        .prepare("INSERT INTO blog(user_id, doc_id, title, content, images) VALUES($1, $2, $3, $4, $5) RETURNING uid")
+        .prepare("INSERT INTO blog(user_id, doc_id, title, content, images, content_type) VALUES($1, $2, $3, $4, $5, $6) RETURNING uid")
         .await?;
 
     let transaction = conn.transaction().await?;
@@ -52,34 +68,40 @@ pub async fn create_blog(
     let row = transaction
         .query_one(
             &insert_blogs_query,
-            &[&user_id, &body.title, &body.content, &images],
+            &[
+                &user_id,
+                &body.title,
+                &body.content,
+                &images,
+                &lifecycle.status.as_str(),
+                &lifecycle.publish_at,
+            ],
         )
         .await?;
 
     let doc_id: i32 = row.get(0);
 
-    transaction
-        .execute(
+    let row = transaction
+        .query_one(
             &insert_blog_query,
-            &[&user_id, &doc_id, &body.title, &body.content, &images],
+            &[&user_id, &doc_id, &body.title, &body.content, &images, &content_type.code()],
         )
         .await?;
+    let main_uid: i32 = row.get(0);
 
-    // let score: i32 = 1;
+    insert_tags(&transaction, TagTable::Blog, doc_id, user_id, &tags).await?;
 
     transaction.commit().await?;
 
-    // let mut all_tags: Vec<(i32, i32, &str, i32)> = Vec::new();
-    // let tags = body.tags;
-    // tags.iter().for_each(|x| {
-    //     all_tags.push((doc_id, user_id, x, score));
-    // });
-
-    // conn.query(
-    //     &insert_tags("blog_tags", "(doc_id, user_id, tag, score)", all_tags),
-    //     &[],
-    // )
-    // .await?;
+    pool.search().upsert(&[IndexNode {
+        kind: DocKind::Blog,
+        uid: main_uid,
+        doc_id,
+        user_id,
+        identity: None,
+        title: &body.title,
+        content: &body.content,
+    }]);
 
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "blog", user_id, doc_id).await;
 
@@ -88,7 +110,11 @@ pub async fn create_blog(
         "title": &body.title,
         "content": &body.content,
         "images": &images,
-        "user_id": &user_id
+        "content_type": content_type,
+        "user_id": &user_id,
+        "tags": tags,
+        "status": lifecycle.status,
+        "publish_at": lifecycle.publish_at
     });
 
     Ok((
@@ -101,8 +127,11 @@ pub async fn create_blog(
 pub async fn edit_blog(
     axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(pool): State<AppState>,
-    Json(body): Json<EditBlog>,
+    Json(mut body): Json<EditBlog>,
 ) -> Result<impl IntoResponse, AppError> {
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
+    body.content_type = Some(content_type);
 
     let mut conn = pool.pg_pool.conn.get().await?;
     let images = &serde_json::to_string(&body.images).unwrap();
@@ -111,19 +140,39 @@ pub async fn edit_blog(
         .prepare("UPDATE blogs SET title=$1, content=$2, images=$3 WHERE uid=$4 AND user_id=$5")
         .await?;
     let state_2 = conn
-        .prepare("UPDATE blog SET title=$1, content=$2, images=$3 WHERE uid=$4 AND user_id=$5")
+        .prepare("UPDATE blog SET title=$1, content=$2, images=$3, content_type=$6 WHERE uid=$4 AND user_id=$5")
         .await?;
     let transaction = conn.transaction().await?;
+    snapshot_original(&transaction, TagTable::Blog, body.uid).await?;
     transaction
         .execute(
             &state_1,
             &[&body.title, &body.content, &images, &body.doc_id, &user_id],
         )
         .await?;
-    transaction
-        .execute(&state_2, &[&body.title, &body.content, &images, &body.uid, &user_id])
+    let updated = transaction
+        .execute(
+            &state_2,
+            &[&body.title, &body.content, &images, &body.uid, &user_id, &content_type.code()],
+        )
         .await?;
-    transaction.commit().await?;
+    // Nothing of ours was updated: drop the transaction so no revision is kept.
+    if updated > 0 {
+        record_revision(&transaction, TagTable::Blog, body.uid, user_id, None).await?;
+        transaction.commit().await?;
+    }
+
+    if updated > 0 {
+        pool.search().upsert(&[IndexNode {
+            kind: DocKind::Blog,
+            uid: body.uid,
+            doc_id: body.doc_id,
+            user_id,
+            identity: None,
+            title: &body.title,
+            content: &body.content,
+        }]);
+    }
 
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "blog", user_id, body.doc_id).await;
     Ok((
@@ -141,6 +190,7 @@ pub struct AddBlogNode {
     images: Vec<Images>,
     parent_id: i32,
     tags: Option<Vec<String>>,
+    content_type: Option<ContentType>,
 }
 
 pub async fn append_blog_node(
@@ -148,6 +198,8 @@ pub async fn append_blog_node(
     State(pool): State<AppState>,
     Json(body): Json<AddBlogNode>,
 ) -> Result<impl IntoResponse, AppError> {
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
     let mut conn = pool.pg_pool.conn.get().await?;
 
     let update_row = conn
@@ -157,10 +209,11 @@ pub async fn append_blog_node(
         )
         .await;
     let images = &serde_json::to_string(&body.images).unwrap();
+    let tags = body.tags.as_deref().map(normalize_tags);
 
     let insert_statement = conn
         .prepare(
-            "INSERT INTO blog(user_id, doc_id, parent_id, title, content, images) values($1, $2, $3, $4, $5, $6) returning uid"
+            "INSERT INTO blog(user_id, doc_id, parent_id, title, content, images, content_type) values($1, $2, $3, $4, $5, $6, $7) returning uid"
         )
         .await?;
 
@@ -178,7 +231,8 @@ pub async fn append_blog_node(
                 &body.parent_id,
                 &body.title,
                 &body.content,
-                &images
+                &images,
+                &content_type.code(),
             ],
         )
         .await?;
@@ -187,35 +241,35 @@ pub async fn append_blog_node(
     
     // let mut update_row_uid: Option<i32> = None;
     let mut update_response: Option<UpdateNode> = None;
-    if let Ok(update_row) = update_row {
-        if !update_row.is_empty() {
-            let update_row_uid: i32 = update_row.get(0);
-            transaction
-                .execute(&update_statement, &[&new_node_uid, &update_row_uid, &user_id])
-                .await?;
-            update_response = Some(UpdateNode {
-                uid: update_row_uid,
-                parent_id: new_node_uid,
-            })
-        }
+    if let Ok(update_row) = update_row
+        && !update_row.is_empty()
+    {
+        let update_row_uid: i32 = update_row.get(0);
+        transaction
+            .execute(&update_statement, &[&new_node_uid, &update_row_uid, &user_id])
+            .await?;
+        update_response = Some(UpdateNode {
+            uid: update_row_uid,
+            parent_id: new_node_uid,
+        })
+    }
+
+    if let Some(tags) = &tags {
+        insert_tags(&transaction, TagTable::Blog, body.doc_id, user_id, tags).await?;
     }
 
     transaction.commit().await?;
 
-    // if let Some(tags) = &body.tags {
-    //     let score: i32 = 1;
-    //     let mut all_tags: Vec<(i32, i32, &str, i32)> = Vec::new();
-    //     tags.iter().for_each(|x| {
-    //         all_tags.push((body.doc_id, user_id, x, score));
-    //     });
-    
-    //     conn.query(
-    //         &insert_tags("blog_tags", "(doc_id, user_id, tag, score)", all_tags),
-    //         &[],
-    //     )
-    //     .await?;
-    // }
-    
+    pool.search().upsert(&[IndexNode {
+        kind: DocKind::Blog,
+        uid: new_node_uid,
+        doc_id: body.doc_id,
+        user_id,
+        identity: None,
+        title: &body.title,
+        content: &body.content,
+    }]);
+
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "blog", user_id, body.doc_id).await;
 
     Ok((
@@ -228,7 +282,8 @@ pub async fn append_blog_node(
                 "title": &body.title,
                 "content": &body.content,
                 "images": &images,
-                "tags": &body.tags
+                "content_type": content_type,
+                "tags": tags
             },
             "update_node": update_response
         })),
@@ -250,24 +305,47 @@ pub struct EditBlogNode {
     content: String,
     doc_id: i32,
     images: Vec<Images>,
+    content_type: Option<ContentType>,
 }
 
 pub async fn edit_blog_node(
     axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(pool): State<AppState>,
-    Json(body): Json<EditBlogNode>,
+    Json(mut body): Json<EditBlogNode>,
 ) -> Result<impl IntoResponse, AppError> {
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
+    body.content_type = Some(content_type);
     let mut conn = pool.pg_pool.conn.get().await?;
     let images = &serde_json::to_string(&body.images).unwrap();
     let state1 = conn
-        .prepare("UPDATE blog SET title=$1, content=$2, images=$3 WHERE uid=$4 AND user_id=$5")
+        .prepare("UPDATE blog SET title=$1, content=$2, images=$3, content_type=$6 WHERE uid=$4 AND user_id=$5")
         .await?;
     let transaction = conn.transaction().await?;
+    snapshot_original(&transaction, TagTable::Blog, body.uid).await?;
 
-    transaction
-        .execute(&state1, &[&body.title, &body.content, &images, &body.uid, &user_id])
+    let updated = transaction
+        .execute(
+            &state1,
+            &[&body.title, &body.content, &images, &body.uid, &user_id, &content_type.code()],
+        )
         .await?;
-    transaction.commit().await?;
+    if updated > 0 {
+        record_revision(&transaction, TagTable::Blog, body.uid, user_id, None).await?;
+        transaction.commit().await?;
+    }
+
+    if updated > 0 {
+        pool.search().upsert(&[IndexNode {
+            kind: DocKind::Blog,
+            uid: body.uid,
+            doc_id: body.doc_id,
+            user_id,
+            identity: None,
+            title: &body.title,
+            content: &body.content,
+        }]);
+    }
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "blog", user_id, body.doc_id).await;
 
     Ok((
@@ -276,3 +354,17 @@ pub async fn edit_blog_node(
         Json(body),
     ))
 }
+
+/// Move a node to follow another node of the same blog.
+pub async fn move_blog_node(
+    axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<MoveNode>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = move_doc_node(&pool, TagTable::Blog, user_id, &body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
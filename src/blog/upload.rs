@@ -0,0 +1,382 @@
+//! `POST /blog/upload`: import a markdown file, or a ZIP holding one markdown
+//! file and its images, as a blog. The text before the first split heading
+//! becomes the main node and every split heading starts a child node, chained
+//! through `parent_id` in document order.
+
+use crate::book::upload::{
+    check_content, has_single_root, image_extension, images_json, link_images_in, split_title_body,
+    store_images, title_from_name, ZipImage,
+};
+use crate::error::AppError;
+use crate::file::is_valid_image;
+use crate::lifecycle::DocStatus;
+use crate::search::index::{DocKind, IndexNode};
+use crate::utils::content::ContentType;
+use crate::utils::doc::{insert_tags, normalize_tags, TagTable};
+use crate::utils::front_matter;
+use crate::AppState;
+use axum::{
+    extract::{Multipart, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+#[derive(Debug, Default, PartialEq)]
+struct BlogNodeData {
+    title: String,
+    content: String,
+    images: Vec<String>,
+}
+
+/// A parsed upload: `nodes[0]` is the main node, the rest its chain.
+#[derive(Default)]
+struct BlogStructure {
+    draft: bool,
+    tags: Vec<String>,
+    nodes: Vec<BlogNodeData>,
+    /// Folder of the markdown file inside the ZIP, for relative image links.
+    dir: String,
+    images: BTreeMap<String, ZipImage>,
+}
+
+/// POST /blog/upload
+///
+/// Multipart body with one field: either a `.md` file, or a `.zip` holding a
+/// single `.md` file plus the images it links to. Front matter `title`,
+/// `tags` and `draft` apply to the blog; a leading `# Heading` is the title
+/// when front matter has none, otherwise the file name is. The body is split
+/// on its highest remaining heading level, ignoring fenced code blocks.
+pub async fn upload_blog(
+    axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<
+        crate::utils::UserId,
+    >,
+    State(pool): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let field = multipart
+        .next_field()
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No file field in multipart body".into()))?;
+    let file_name = field.file_name().unwrap_or_default().to_string();
+    let bytes = field.bytes().await?;
+
+    let mut blog = if bytes.starts_with(b"PK\x03\x04") || file_name.to_lowercase().ends_with(".zip") {
+        parse_zip(&bytes)?
+    } else if file_name.is_empty() || is_markdown(&file_name) {
+        let text = String::from_utf8(bytes.to_vec())
+            .map_err(|_| AppError::BadRequest(format!("{file_name} is not valid UTF-8")))?;
+        let name = if file_name.is_empty() { "upload.md" } else { &file_name };
+        parse_markdown(name, &text)?
+    } else {
+        return Err(AppError::BadRequest("Expected a .md file or a .zip archive".into()));
+    };
+
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let tx = conn.transaction().await?;
+
+    // The summary row first, so image links can point at the new doc_id
+    let status = if blog.draft { DocStatus::Draft } else { DocStatus::Published };
+    let title = blog.nodes[0].title.clone();
+    let row = tx
+        .query_one(
+            "INSERT INTO blogs(user_id, title, content, images, status) \
+             VALUES($1, $2, '', '[]', $3) RETURNING uid",
+            &[&user_id, &title, &status.as_str()],
+        )
+        .await?;
+    let doc_id: i32 = row.get(0);
+    insert_tags(&tx, TagTable::Blog, doc_id, user_id, &blog.tags).await?;
+
+    let url_base = format!("/file/blog/{doc_id}");
+    let mut used_images: BTreeSet<String> = BTreeSet::new();
+    for node in blog.nodes.iter_mut() {
+        link_images_in(&mut node.content, &blog.dir, &blog.images, &url_base, &mut node.images);
+        used_images.extend(node.images.iter().cloned());
+    }
+
+    let main = &blog.nodes[0];
+    let main_images = images_json(&main.images);
+    tx.execute(
+        "UPDATE blogs SET content=$1, images=$2 WHERE uid=$3",
+        &[&main.content, &main_images, &doc_id],
+    )
+    .await?;
+
+    let row = tx
+        .query_one(
+            "INSERT INTO blog(user_id, doc_id, title, content, images, content_type) \
+             VALUES($1, $2, $3, $4, $5, $6) RETURNING uid",
+            &[
+                &user_id,
+                &doc_id,
+                &main.title,
+                &main.content,
+                &main_images,
+                &ContentType::resolve(None, &main.content).code(),
+            ],
+        )
+        .await?;
+    let mut prev_uid: i32 = row.get(0);
+    let mut indexed: Vec<IndexNode> = vec![IndexNode {
+        kind: DocKind::Blog,
+        uid: prev_uid,
+        doc_id,
+        user_id,
+        identity: None,
+        title: &main.title,
+        content: &main.content,
+    }];
+
+    let insert_node = tx
+        .prepare(
+            "INSERT INTO blog(user_id, doc_id, parent_id, title, content, images, content_type) \
+             VALUES($1, $2, $3, $4, $5, $6, $7) RETURNING uid",
+        )
+        .await?;
+    for node in &blog.nodes[1..] {
+        let row = tx
+            .query_one(
+                &insert_node,
+                &[
+                    &user_id,
+                    &doc_id,
+                    &prev_uid, // parent_id = previous node (or main for the first)
+                    &node.title,
+                    &node.content,
+                    &images_json(&node.images),
+                    &ContentType::resolve(None, &node.content).code(),
+                ],
+            )
+            .await?;
+        prev_uid = row.get(0);
+        indexed.push(IndexNode {
+            kind: DocKind::Blog,
+            uid: prev_uid,
+            doc_id,
+            user_id,
+            identity: None,
+            title: &node.title,
+            content: &node.content,
+        });
+    }
+
+    // Store images before committing so a bad image leaves no blog behind
+    store_images(&pool, "blog", &blog.images, &used_images, doc_id, user_id).await?;
+
+    tx.commit().await?;
+
+    pool.search().upsert(&indexed);
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "doc_id": doc_id,
+            "title": title,
+            "nodes": blog.nodes.len(),
+            "images": used_images.len(),
+            "status": status,
+        })),
+    ))
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// Read the single markdown file and the images out of a ZIP.
+fn parse_zip(bytes: &[u8]) -> Result<BlogStructure, AppError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| AppError::BadRequest(format!("Invalid ZIP file: {e}")))?;
+
+    let mut markdown: Vec<(Vec<String>, String)> = Vec::new();
+    let mut images: Vec<(Vec<String>, String, Vec<u8>)> = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| AppError::BadRequest(format!("Cannot read ZIP entry {i}: {e}")))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let raw_name = entry.name().to_string();
+        let parts: Vec<String> = raw_name
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+
+        if let Some(extension) = image_extension(&raw_name) {
+            let mut bytes = Vec::new();
+            entry
+                .read_to_end(&mut bytes)
+                .map_err(|e| AppError::BadRequest(format!("Cannot read {raw_name}: {e}")))?;
+            if !is_valid_image(&bytes) {
+                return Err(AppError::BadRequest(format!("{raw_name} is not a recognised image")));
+            }
+            images.push((parts, extension, bytes));
+        } else if is_markdown(&raw_name) {
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|e| AppError::BadRequest(format!("Cannot read {raw_name}: {e}")))?;
+            markdown.push((parts, content));
+        }
+    }
+
+    if markdown.len() != 1 {
+        return Err(AppError::BadRequest(format!(
+            "ZIP must contain exactly one .md file, found {}",
+            markdown.len()
+        )));
+    }
+
+    // Strip common root folder (many ZIP tools wrap everything in one folder)
+    let paths: Vec<&[String]> = markdown
+        .iter()
+        .map(|(parts, _)| parts.as_slice())
+        .chain(images.iter().map(|(parts, _, _)| parts.as_slice()))
+        .collect();
+    let start = if has_single_root(&paths) { 1 } else { 0 };
+
+    let (parts, content) = markdown.remove(0);
+    let mut blog = parse_markdown(&parts.join("/"), &content)?;
+    blog.dir = parts[start..parts.len() - 1].join("/");
+    for (parts, extension, bytes) in images {
+        let path = parts[start..].join("/");
+        blog.images.insert(path.clone(), ZipImage::new(path, extension, bytes));
+    }
+    Ok(blog)
+}
+
+/// Split one markdown file into the main node and its chain. `file` names the
+/// file in errors and supplies the title when nothing else does.
+fn parse_markdown(file: &str, text: &str) -> Result<BlogStructure, AppError> {
+    let (front, body) = front_matter::split(file, text)?;
+    let name = file.rsplit('/').next().unwrap_or(file);
+    let default_title = title_from_name(name.strip_suffix(".markdown").unwrap_or(name));
+
+    let lines: Vec<&str> = body.lines().collect();
+    let mut headings = headings(&lines);
+    // A leading `# Title` names the blog rather than starting a node
+    let first_line = lines.iter().position(|line| !line.trim().is_empty());
+    if matches!(headings.first(), Some(&(at, 1, _)) if Some(at) == first_line) {
+        headings.remove(0);
+    }
+    let level = headings.iter().map(|&(_, level, _)| level).min();
+    let splits: Vec<&(usize, usize, String)> =
+        headings.iter().filter(|&&(_, l, _)| Some(l) == level).collect();
+
+    let end = splits.first().map_or(lines.len(), |&&(at, _, _)| at);
+    let (title, content) = split_title_body(&lines[..end].join("\n"), &default_title);
+    let mut nodes = vec![BlogNodeData {
+        title: front.title.unwrap_or(title),
+        content,
+        images: Vec::new(),
+    }];
+    for (i, (at, _, heading)) in splits.iter().enumerate() {
+        let end = splits.get(i + 1).map_or(lines.len(), |&&(next, _, _)| next);
+        let (title, content) = split_title_body(&lines[at + 1..end].join("\n"), heading);
+        nodes.push(BlogNodeData { title, content, images: Vec::new() });
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        let part = if i == 0 { file.to_string() } else { format!("{file} ({})", node.title) };
+        check_content(&part, &node.content).map_err(AppError::BadRequest)?;
+    }
+
+    Ok(BlogStructure {
+        draft: front.draft,
+        tags: normalize_tags(&front.tags),
+        nodes,
+        ..Default::default()
+    })
+}
+
+/// `(line, level, text)` of every ATX heading outside fenced code blocks.
+fn headings(lines: &[&str]) -> Vec<(usize, usize, String)> {
+    let mut found = Vec::new();
+    let mut fence: Option<&str> = None;
+    for (at, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(open) = fence {
+            if trimmed.starts_with(open) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        let level = line.chars().take_while(|&c| c == '#').count();
+        if !(1..=6).contains(&level) {
+            continue;
+        }
+        let rest = &line[level..];
+        if !rest.starts_with([' ', '\t']) {
+            continue;
+        }
+        let text = rest.trim().trim_end_matches('#').trim();
+        if !text.is_empty() {
+            found.push((at, level, text.to_string()));
+        }
+    }
+    found
+}
+
+fn is_markdown(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".md") || name.ends_with(".markdown")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(title: &str, content: &str) -> BlogNodeData {
+        BlogNodeData { title: title.into(), content: content.into(), images: Vec::new() }
+    }
+
+    #[test]
+    fn test_parse_markdown() {
+        let blog = parse_markdown(
+            "posts/my-trip.md",
+            "---\ntags: [Travel]\ndraft: true\n---\n# A Trip\n\nIntro.\n\n## Day One\n\nWalked.\n\n### Lunch\n\nSoup.\n\n```sh\n## not a heading\n```\n\n## Day Two ##\n\nRested.\n",
+        )
+        .unwrap();
+        assert!(blog.draft);
+        assert_eq!(blog.tags, vec!["travel".to_string()]);
+        assert_eq!(
+            blog.nodes,
+            vec![
+                node("A Trip", "<basic> Intro."),
+                node(
+                    "Day One",
+                    "<basic> Walked.\n\n### Lunch\n\nSoup.\n\n```sh\n## not a heading\n```"
+                ),
+                node("Day Two", "<basic> Rested."),
+            ]
+        );
+
+        // No H1: the file name is the title and top-level headings split.
+        let blog = parse_markdown("notes.md", "Lead.\n# One\nA\n# Two\n").unwrap();
+        assert_eq!(
+            blog.nodes,
+            vec![node("Notes", "<basic> Lead."), node("One", "<basic> A"), node("Two", "")]
+        );
+
+        // Front matter title wins; no headings means a single node.
+        let blog = parse_markdown("x.md", "---\ntitle: Named\n---\n# Ignored\nBody").unwrap();
+        assert_eq!(blog.nodes, vec![node("Named", "<basic> Body")]);
+
+        let big = format!("# Big\n\nLead.\n\n## Huge\n\n{}", "a".repeat(crate::utils::content::MAX_CONTENT_BYTES));
+        match parse_markdown("big.md", &big) {
+            Err(AppError::BadRequest(message)) => assert!(message.starts_with("big.md (Huge): content is")),
+            _ => panic!("oversized node accepted"),
+        }
+    }
+}
@@ -1,4 +1,3 @@
-#[allow(dead_code)]
 const STOP_WORDS: [&str; 177] = [
     "a",
     "about",
@@ -179,7 +178,6 @@ const STOP_WORDS: [&str; 177] = [
     "name",
 ];
 
-#[allow(dead_code)]
 pub fn remove_stopwords(input: &str) -> Vec<String> {
     // Split input into phrases (using whitespace or punctuation as delimiters)
     let phrases: Vec<&str> = input.split_whitespace().collect();
@@ -202,7 +200,7 @@ pub fn remove_stopwords(input: &str) -> Vec<String> {
 mod tests {
 
     use super::*;
-    use crate::utils::doc::insert_tags;
+    use crate::utils::doc::normalize_tags;
 
     #[test]
     fn test_remove_stopwords() {
@@ -212,17 +210,14 @@ mod tests {
     }
 
     #[test]
-    fn test_query() {
-        let res = insert_tags(
-            "tags",
-            "(doc_id, user_id, name, score)",
-            vec![(1, 1, "Sankar", 1), (1, 1, "Boro", 1)],
-        );
-        assert_eq!(
-            res,
-            String::from(
-                "INSERT INTO tags (doc_id, user_id, name, score) VALUES (1, 1, 'Sankar', 1), (1, 1, 'Boro', 1)"
-            )
-        );
+    fn test_normalize_tags() {
+        let tags = vec![
+            String::from("The Rust Book"),
+            String::from("rust book"),
+            String::from("Robert'); DROP TABLE blog_tags;--"),
+            String::from("the"),
+        ];
+        let res = normalize_tags(&tags);
+        assert_eq!(res, Vec::from(["rust book", "robert' drop table blog_tags--"]));
     }
 }
@@ -0,0 +1,263 @@
+use crate::error::AppError;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::Client;
+
+/// What a user may do with a book, weakest first so roles compare with `>=`.
+/// The owner is `books.user_id`; everyone else needs an accepted row in
+/// `book_collaborators`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollaboratorRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl CollaboratorRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CollaboratorRole::Viewer => "viewer",
+            CollaboratorRole::Editor => "editor",
+            CollaboratorRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "viewer" => Some(CollaboratorRole::Viewer),
+            "editor" => Some(CollaboratorRole::Editor),
+            "owner" => Some(CollaboratorRole::Owner),
+            _ => None,
+        }
+    }
+}
+
+/// The caller's role on a live book, or `None` if they are not a member.
+/// Errors with `NotFound` when the book does not exist or is deleted.
+pub async fn book_role(
+    conn: &Client,
+    doc_id: i32,
+    user_id: i32,
+) -> Result<Option<CollaboratorRole>, AppError> {
+    let row = conn
+        .query_opt(
+            "SELECT b.user_id, c.role FROM books b \
+             LEFT JOIN book_collaborators c ON c.doc_id = b.uid AND c.user_id = $2 AND c.accepted_at IS NOT NULL \
+             WHERE b.uid = $1 AND b.deleted_at IS NULL",
+            &[&doc_id, &user_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Book {doc_id} not found")))?;
+
+    let owner: i32 = row.get(0);
+    if owner == user_id {
+        return Ok(Some(CollaboratorRole::Owner));
+    }
+    let role: Option<String> = row.get(1);
+    Ok(role.as_deref().and_then(CollaboratorRole::parse))
+}
+
+/// Guard for book write handlers: the caller must hold at least `needed`.
+pub async fn require_book_role(
+    conn: &Client,
+    doc_id: i32,
+    user_id: i32,
+    needed: CollaboratorRole,
+) -> Result<CollaboratorRole, AppError> {
+    match book_role(conn, doc_id, user_id).await? {
+        Some(role) if role >= needed => Ok(role),
+        _ => Err(AppError::Error((
+            StatusCode::FORBIDDEN,
+            format!("{} access to book {doc_id} required", needed.as_str()),
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct InviteCollaborator {
+    user_id: i32,
+    role: CollaboratorRole,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeCollaborator {
+    user_id: i32,
+}
+
+#[derive(Serialize)]
+pub struct Collaborator {
+    user_id: i32,
+    role: String,
+    invited_by: i32,
+    created_at: DateTime<Utc>,
+    accepted_at: Option<DateTime<Utc>>,
+}
+
+/// POST /book/:doc_id/collaborators/invite
+///
+/// Owner only. Re-inviting an existing collaborator changes their role and
+/// keeps their acceptance.
+pub async fn invite_collaborator(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+    Json(body): Json<InviteCollaborator>,
+) -> Result<impl IntoResponse, AppError> {
+    if body.role == CollaboratorRole::Owner {
+        return Err(AppError::BadRequest("Role must be `editor` or `viewer`".into()));
+    }
+    if body.user_id == user_id {
+        return Err(AppError::BadRequest("Cannot invite yourself".into()));
+    }
+    let conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, doc_id, user_id, CollaboratorRole::Owner).await?;
+
+    let row = conn
+        .query_one(
+            "INSERT INTO book_collaborators(doc_id, user_id, role, invited_by) VALUES($1, $2, $3, $4) \
+             ON CONFLICT (doc_id, user_id) DO UPDATE SET role = EXCLUDED.role \
+             RETURNING accepted_at",
+            &[&doc_id, &body.user_id, &body.role.as_str(), &user_id],
+        )
+        .await?;
+    let accepted_at: Option<DateTime<Utc>> = row.get(0);
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "doc_id": doc_id,
+            "user_id": body.user_id,
+            "role": body.role,
+            "accepted_at": accepted_at
+        })),
+    ))
+}
+
+/// POST /book/:doc_id/collaborators/accept — the invitee accepts a pending invite.
+pub async fn accept_invitation(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    let row = conn
+        .query_opt(
+            "UPDATE book_collaborators SET accepted_at = now() \
+             WHERE doc_id = $1 AND user_id = $2 AND accepted_at IS NULL RETURNING role",
+            &[&doc_id, &user_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No pending invitation to book {doc_id}")))?;
+    let role: String = row.get(0);
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "doc_id": doc_id,
+            "user_id": user_id,
+            "role": role
+        })),
+    ))
+}
+
+/// POST /book/:doc_id/collaborators/revoke
+///
+/// The owner can remove anyone; a collaborator can only remove themselves
+/// (declining an invite or leaving the book).
+pub async fn revoke_collaborator(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+    Json(body): Json<RevokeCollaborator>,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    if body.user_id != user_id {
+        require_book_role(&conn, doc_id, user_id, CollaboratorRole::Owner).await?;
+    }
+    let removed = conn
+        .execute(
+            "DELETE FROM book_collaborators WHERE doc_id = $1 AND user_id = $2",
+            &[&doc_id, &body.user_id],
+        )
+        .await?;
+    if removed == 0 {
+        return Err(AppError::NotFound(format!(
+            "User {} is not a collaborator on book {doc_id}",
+            body.user_id
+        )));
+    }
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "doc_id": doc_id,
+            "user_id": body.user_id,
+            "revoked": true
+        })),
+    ))
+}
+
+/// GET /book/:doc_id/collaborators — visible to any member of the book.
+pub async fn get_collaborators(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, doc_id, user_id, CollaboratorRole::Viewer).await?;
+
+    let rows = conn
+        .query(
+            "SELECT user_id, role, invited_by, created_at, accepted_at FROM book_collaborators \
+             WHERE doc_id = $1 ORDER BY created_at, user_id",
+            &[&doc_id],
+        )
+        .await?;
+    let collaborators: Vec<Collaborator> = rows
+        .iter()
+        .map(|row| Collaborator {
+            user_id: row.get(0),
+            role: row.get(1),
+            invited_by: row.get(2),
+            created_at: row.get(3),
+            accepted_at: row.get(4),
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "doc_id": doc_id,
+            "collaborators": collaborators
+        })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_are_ordered_by_rights() {
+        assert!(CollaboratorRole::Owner > CollaboratorRole::Editor);
+        assert!(CollaboratorRole::Editor > CollaboratorRole::Viewer);
+        for role in [CollaboratorRole::Viewer, CollaboratorRole::Editor, CollaboratorRole::Owner] {
+            assert_eq!(CollaboratorRole::parse(role.as_str()), Some(role));
+        }
+        assert_eq!(CollaboratorRole::parse("admin"), None);
+    }
+}
@@ -1,5 +1,10 @@
+use crate::book::collaborators::{require_book_role, CollaboratorRole};
 use crate::error::AppError;
+use crate::lifecycle::Lifecycle;
+use crate::search::index::{DocKind, IndexNode};
 use crate::traits::{move_images_to_s3, Images};
+use crate::utils::content::ContentType;
+use crate::utils::doc::{insert_tags, normalize_tags, TagTable};
 use crate::AppState;
 use axum::{
     extract::State,
@@ -7,6 +12,7 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
@@ -16,6 +22,9 @@ pub struct CreateBook {
     content: String,
     images: Vec<Images>,
     tags: Option<Vec<String>>,
+    status: Option<String>,
+    publish_at: Option<DateTime<Utc>>,
+    content_type: Option<ContentType>,
 }
 
 // @Create
@@ -27,14 +36,18 @@ pub async fn create_book(
 ) -> Result<impl IntoResponse, AppError> {
     let identity: i16 = 100;
     let images = &serde_json::to_string(&body.images).unwrap();
+    let tags = body.tags.as_deref().map(normalize_tags).unwrap_or_default();
+    let lifecycle = Lifecycle::resolve(body.status.as_deref(), body.publish_at, Utc::now())?;
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
 
     let mut conn = pool.pg_pool.conn.get().await?;
 
     let insert_books_query = conn
-        .prepare("INSERT INTO books(user_id, title, content, images) VALUES($1, $2, $3, $4) RETURNING uid")
+        .prepare("INSERT INTO books(user_id, title, content, images, status, publish_at) VALUES($1, $2, $3, $4, $5, $6) RETURNING uid")
         .await?;
     let insert_book_query = conn
-        .prepare("INSERT INTO book(user_id, doc_id, title, content, identity, images) VALUES($1, $2, $3, $4, $5, $6) RETURNING uid")
+        .prepare("INSERT INTO book(user_id, doc_id, title, content, identity, images, content_type) VALUES($1, $2, $3, $4, $5, $6, $7) RETURNING uid")
         .await?;
 
     let transaction = conn.transaction().await?;
@@ -42,14 +55,21 @@ pub async fn create_book(
     let row = transaction
         .query_one(
             &insert_books_query,
-            &[&user_id, &body.title, &body.content, &images],
+            &[
+                &user_id,
+                &body.title,
+                &body.content,
+                &images,
+                &lifecycle.status.as_str(),
+                &lifecycle.publish_at,
+            ],
         )
         .await?;
 
     let doc_id: i32 = row.get(0);
 
-    transaction
-        .execute(
+    let row = transaction
+        .query_one(
             &insert_book_query,
             &[
                 &user_id,
@@ -58,24 +78,25 @@ pub async fn create_book(
                 &body.content,
                 &identity,
                 &images,
+                &content_type.code(),
             ],
         )
         .await?;
+    let main_uid: i32 = row.get(0);
+
+    insert_tags(&transaction, TagTable::Book, doc_id, user_id, &tags).await?;
 
     transaction.commit().await?;
 
-    // if let Some(tags) = body.tags {
-    //     let mut all_tags: Vec<(i32, i32, &str, i32)> = Vec::new();
-    //     tags.iter().for_each(|__tag| {
-    //         all_tags.push((doc_id, user_id, __tag, 1));
-    //     });
-    
-    //     conn.query(
-    //         &insert_tags("book_tags", "(doc_id, user_id, tag, score)", all_tags),
-    //         &[],
-    //     )
-    //     .await?;
-    // }
+    pool.search().upsert(&[IndexNode {
+        kind: DocKind::Book,
+        uid: main_uid,
+        doc_id,
+        user_id,
+        identity: Some(identity),
+        title: &body.title,
+        content: &body.content,
+    }]);
 
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "book", user_id, doc_id).await;
 
@@ -85,7 +106,11 @@ pub async fn create_book(
         "title": &body.title,
         "content": &body.content,
         "identity": &identity,
-        "images": &images
+        "images": &images,
+        "content_type": content_type,
+        "tags": tags,
+        "status": lifecycle.status,
+        "publish_at": lifecycle.publish_at
     });
 
     Ok((
@@ -106,6 +131,7 @@ pub struct AddBookNode {
     identity: i16,
     tags: Option<Vec<String>>,
     parent_identity: i16,
+    content_type: Option<ContentType>,
 }
 
 pub async fn append_book_node(
@@ -116,24 +142,28 @@ pub async fn append_book_node(
     if body.parent_identity == 101 && body.identity == 103 {
         return Err(AppError::InternalServerError(String::from("Not Allowed")));
     }
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
     let mut conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, body.doc_id, user_id, CollaboratorRole::Editor).await?;
 
     let images = &serde_json::to_string(&body.images).unwrap();
+    let tags = body.tags.as_deref().map(normalize_tags);
 
     let has_row_update = conn
         .query_one(
-            "SELECT uid, parent_id, identity from book where parent_id=$1 AND identity=$2 AND user_id=$3 AND deleted_at is NULL",
-            &[&body.parent_id, &body.identity, &user_id],
+            "SELECT uid, parent_id, identity from book where parent_id=$1 AND identity=$2 AND doc_id=$3 AND deleted_at is NULL",
+            &[&body.parent_id, &body.identity, &body.doc_id],
         )
         .await;
 
     let state1 = conn
     .prepare(
-        "INSERT INTO book(user_id, doc_id, page_id, parent_id, title, content, identity, images) values($1, $2, $3, $4, $5, $6, $7, $8) returning uid",
+        "INSERT INTO book(user_id, doc_id, page_id, parent_id, title, content, identity, images, content_type) values($1, $2, $3, $4, $5, $6, $7, $8, $9) returning uid",
     )
     .await?;
     let state2 = conn
-        .prepare("UPDATE book SET parent_id=$1 WHERE uid=$2 AND user_id=$3 RETURNING uid")
+        .prepare("UPDATE book SET parent_id=$1 WHERE uid=$2 AND doc_id=$3 RETURNING uid")
         .await?;
 
     let transaction = conn.transaction().await?;
@@ -149,6 +179,7 @@ pub async fn append_book_node(
                 &body.content,
                 &body.identity,
                 &images,
+                &content_type.code(),
             ],
         )
         .await?;
@@ -157,36 +188,36 @@ pub async fn append_book_node(
 
     let mut update_node: Option<Value> = None;
 
-    if let Ok(update_row) = has_row_update {
-        if !update_row.is_empty() {
-            let update_row_id: i32 = update_row.get(0);
-            let identity: Option<i16> = update_row.get(2);
-
-            if &body.identity >= &identity.unwrap() {
-                transaction
-                    .execute(&state2, &[&new_node_id, &update_row_id, &user_id])
-                    .await?;
-                update_node = Some(json!({
-                    "uid": update_row_id,
-                    "parent_id": new_node_id
-                }))
-            }
+    if let Ok(update_row) = has_row_update
+        && !update_row.is_empty()
+    {
+        let update_row_id: i32 = update_row.get(0);
+        let identity: Option<i16> = update_row.get(2);
+
+        if body.identity >= identity.unwrap() {
+            transaction
+                .execute(&state2, &[&new_node_id, &update_row_id, &body.doc_id])
+                .await?;
+            update_node = Some(json!({
+                "uid": update_row_id,
+                "parent_id": new_node_id
+            }))
         }
     }
+    if let Some(tags) = &tags {
+        insert_tags(&transaction, TagTable::Book, body.doc_id, user_id, tags).await?;
+    }
     transaction.commit().await?;
 
-    // if let Some(tags) = body.tags {
-    //     let mut all_tags: Vec<(i32, i32, &str, i32)> = Vec::new();
-    //     tags.iter().for_each(|__tag| {
-    //         all_tags.push((doc_id, user_id, __tag, 1));
-    //     });
-    
-    //     conn.query(
-    //         &insert_tags("book_tags", "(doc_id, user_id, tag, score)", all_tags),
-    //         &[],
-    //     )
-    //     .await?;
-    // }
+    pool.search().upsert(&[IndexNode {
+        kind: DocKind::Book,
+        uid: new_node_id,
+        doc_id: body.doc_id,
+        user_id,
+        identity: Some(body.identity),
+        title: &body.title,
+        content: &body.content,
+    }]);
 
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "book", user_id, body.doc_id).await;
 
@@ -201,7 +232,9 @@ pub async fn append_book_node(
                 "content": &body.content,
                 "images": &images,
                 "identity": &body.identity,
-                "page_id": &body.page_id
+                "page_id": &body.page_id,
+                "content_type": content_type,
+                "tags": tags
             },
             "update_node": update_node
         })),
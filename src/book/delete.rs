@@ -1,4 +1,6 @@
+use crate::book::collaborators::{require_book_role, CollaboratorRole};
 use crate::error::AppError;
+use crate::search::index::DocKind;
 use crate::AppState;
 use axum::{
     extract::State,
@@ -35,23 +37,28 @@ pub async fn delete_book(
     Json(body): Json<DeleteBook>,
 ) -> Result<impl IntoResponse, AppError> {
     let mut conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, body.doc_id, user_id, CollaboratorRole::Owner).await?;
     let current_time = Local::now();
 
     let state1 = conn
-        .prepare("UPDATE book SET deleted_at=$1 WHERE doc_id=$2 AND user_id=$3")
+        .prepare("UPDATE book SET deleted_at=$1 WHERE doc_id=$2 AND deleted_at IS NULL")
         .await?;
     let state2 = conn
-        .prepare("UPDATE books SET deleted_at=$1 WHERE uid=$2 AND user_id=$3")
+        .prepare("UPDATE books SET deleted_at=$1 WHERE uid=$2")
         .await?;
     let transaction = conn.transaction().await?;
     transaction
-        .execute(&state1, &[&current_time, &body.doc_id, &user_id])
+        .execute(&state1, &[&current_time, &body.doc_id])
         .await?;
-    transaction
-        .execute(&state2, &[&current_time, &body.doc_id, &user_id])
+    let deleted = transaction
+        .execute(&state2, &[&current_time, &body.doc_id])
         .await?;
     transaction.commit().await?;
 
+    if deleted > 0 {
+        pool.search().remove_doc(DocKind::Book, body.doc_id);
+    }
+
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
@@ -68,16 +75,26 @@ pub async fn delete_book_node(
 ) -> Result<impl IntoResponse, AppError> {
     let mut conn = pool.pg_pool.conn.get().await?;
 
+    let doc_id: i32 = conn
+        .query_opt(
+            "SELECT doc_id FROM book WHERE uid=$1 AND deleted_at IS NULL",
+            &[&body.delete_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Node {} not found", body.delete_id)))?
+        .get(0);
+    require_book_role(&conn, doc_id, user_id, CollaboratorRole::Editor).await?;
+
     // Prepare to find ids to delete
     // Applies only for nodes where identity is 101, 102
     let mut delete_row_ids: Vec<i32> = Vec::new();
     delete_row_ids.push(body.delete_id);
 
     let delete_rows = conn
-        .query("SELECT uid FROM book where page_id=$1", &[&body.delete_id])
+        .query("SELECT uid FROM book where page_id=$1 AND doc_id=$2", &[&body.delete_id, &doc_id])
         .await?;
 
-    if delete_rows.len() > 0 {
+    if !delete_rows.is_empty() {
         for row in delete_rows.iter() {
             let uid = row.get(0);
             delete_row_ids.push(uid);
@@ -87,12 +104,12 @@ pub async fn delete_book_node(
     if body.identity == 101 {
         let sub_section_nodes = conn
             .query(
-                "SELECT uid FROM book where page_id=ANY($1)",
-                &[&delete_row_ids],
+                "SELECT uid FROM book where page_id=ANY($1) AND doc_id=$2",
+                &[&delete_row_ids, &doc_id],
             )
             .await?;
 
-        if sub_section_nodes.len() > 0 {
+        if !sub_section_nodes.is_empty() {
             for sub_section in sub_section_nodes.iter() {
                 let uid2 = sub_section.get(0);
                 delete_row_ids.push(uid2);
@@ -102,37 +119,41 @@ pub async fn delete_book_node(
     // Check if there is a node to update
     let update_row_exist = conn
         .query_opt(
-            "SELECT uid, parent_id from book where parent_id=$1 AND identity=$2 AND deleted_at IS NULL",
-            &[&body.delete_id, &body.identity],
+            "SELECT uid, parent_id from book where parent_id=$1 AND identity=$2 AND doc_id=$3 AND deleted_at IS NULL",
+            &[&body.delete_id, &body.identity, &doc_id],
         )
         .await?;
     let current_time = Local::now();
     let state1 = conn
-        .prepare("UPDATE book SET deleted_at=$1 WHERE uid=ANY($2) AND user_id=$3")
+        .prepare("UPDATE book SET deleted_at=$1 WHERE uid=ANY($2) AND doc_id=$3")
         .await?;
     let update_bot_node_query = conn
-        .prepare("UPDATE book SET parent_id=$1 WHERE uid=$2 AND user_id=$3")
+        .prepare("UPDATE book SET parent_id=$1 WHERE uid=$2 AND doc_id=$3")
         .await?;
     let transaction = conn.transaction().await?;
     let num_deleted_rows = transaction
-        .execute(&state1, &[&current_time, &delete_row_ids, &user_id])
+        .execute(&state1, &[&current_time, &delete_row_ids, &doc_id])
         .await?;
 
     let mut update_response: Option<UpdateNode> = None;
-    if let Some(update_row) = update_row_exist {
-        if !update_row.is_empty() {
-            let update_id: i32 = update_row.get(0);
-            transaction
-            .execute(&update_bot_node_query, &[&body.parent_id, &update_id, &user_id])
+    if let Some(update_row) = update_row_exist
+        && !update_row.is_empty()
+    {
+        let update_id: i32 = update_row.get(0);
+        transaction
+            .execute(&update_bot_node_query, &[&body.parent_id, &update_id, &doc_id])
             .await?;
-            update_response = Some(UpdateNode {
-                uid: update_id,
-                parent_id: body.parent_id,
-            })
-        }
+        update_response = Some(UpdateNode {
+            uid: update_id,
+            parent_id: body.parent_id,
+        })
     }
     transaction.commit().await?;
 
+    if num_deleted_rows > 0 {
+        pool.search().remove_nodes(DocKind::Book, &delete_row_ids);
+    }
+
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
@@ -1,4 +1,10 @@
+use crate::book::collaborators::{require_book_role, CollaboratorRole};
 use crate::error::AppError;
+use crate::revision::{record_revision, snapshot_original};
+use crate::utils::chain::{move_doc_node, MoveNode};
+use crate::utils::content::ContentType;
+use crate::utils::doc::TagTable;
+use crate::search::index::{DocKind, IndexNode};
 use crate::traits::{move_images_to_s3, Images};
 use crate::AppState;
 use axum::{
@@ -16,7 +22,8 @@ pub struct EditBook {
     uid: i32,
     title: String,
     content: String,
-    images: Vec<Images>
+    images: Vec<Images>,
+    content_type: Option<ContentType>,
 }
 
 // @Edit
@@ -25,34 +32,55 @@ pub async fn edit_book(
     State(pool): State<AppState>,
     Json(body): Json<EditBook>,
 ) -> Result<impl IntoResponse, AppError> {
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
     let mut conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, body.doc_id, user_id, CollaboratorRole::Editor).await?;
     let images = &serde_json::to_string(&body.images).unwrap();
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "book", user_id, body.doc_id).await;
     let state1 = conn
-        .prepare("UPDATE books SET title=$1, content=$2, images=$3 WHERE uid=$4 AND user_id=$5")
+        .prepare("UPDATE books SET title=$1, content=$2, images=$3 WHERE uid=$4")
         .await?;
     let state2 = conn
-        .prepare("UPDATE book SET title=$1, content=$2, images=$3 WHERE uid=$4 AND user_id=$5")
+        .prepare("UPDATE book SET title=$1, content=$2, images=$3, updated_by=$4, content_type=$7 WHERE uid=$5 AND doc_id=$6 RETURNING user_id")
         .await?;
     let transaction = conn.transaction().await?;
+    snapshot_original(&transaction, TagTable::Book, body.uid).await?;
     transaction
         .execute(
             &state1,
-            &[&body.title, &body.content, &images, &body.doc_id, &user_id],
+            &[&body.title, &body.content, &images, &body.doc_id],
         )
         .await?;
-    transaction
-        .execute(
+    let updated = transaction
+        .query_opt(
             &state2,
-            &[&body.title, &body.content, &images, &body.uid, &user_id],
+            &[&body.title, &body.content, &images, &user_id, &body.uid, &body.doc_id, &content_type.code()],
         )
         .await?;
-    transaction.commit().await?;
+    // Node not in this book: drop the transaction so no revision is kept.
+    if updated.is_some() {
+        record_revision(&transaction, TagTable::Book, body.uid, user_id, None).await?;
+        transaction.commit().await?;
+    }
+
+    if let Some(row) = updated {
+        pool.search().upsert(&[IndexNode {
+            kind: DocKind::Book,
+            uid: body.uid,
+            doc_id: body.doc_id,
+            user_id: row.get(0),
+            identity: Some(100),
+            title: &body.title,
+            content: &body.content,
+        }]);
+    }
 
     let edit_book = json!({
         "doc_id": &body.doc_id,
         "title": &body.title,
         "content": &body.content,
+        "content_type": content_type,
         "uid": &body.uid
     });
 
@@ -71,33 +99,52 @@ pub struct EditBookNode {
     identity: i16,
     doc_id: i32,
     images: Vec<Images>,
+    content_type: Option<ContentType>,
 }
 
 /// # Returns
 pub async fn edit_book_node(
     axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(pool): State<AppState>,
-    Json(body): Json<EditBookNode>,
+    Json(mut body): Json<EditBookNode>,
 ) -> Result<impl IntoResponse, AppError> {
-    let conn = pool.pg_pool.conn.get().await?;
+    let content_type = ContentType::resolve(body.content_type, &body.content);
+    content_type.validate(&body.content)?;
+    body.content_type = Some(content_type);
+    let mut conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, body.doc_id, user_id, CollaboratorRole::Editor).await?;
     let images = &serde_json::to_string(&body.images).unwrap();
     let _ = move_images_to_s3(&body.images, pool.s3(), "tmp", "book", user_id, body.doc_id).await;
-    if body.images.len() > 0 {
-        let _ = conn
-            .execute(
-                "UPDATE book SET title=$1, content=$2, images=$3 WHERE uid=$4 AND user_id=$5",
-                &[&body.title, &body.content, &images, &body.uid, &user_id],
-            )
-            .await?;
+    let transaction = conn.transaction().await?;
+    snapshot_original(&transaction, TagTable::Book, body.uid).await?;
+    let updated = if !body.images.is_empty() {
+        transaction.query_opt(
+            "UPDATE book SET title=$1, content=$2, images=$3, updated_by=$4, content_type=$7 WHERE uid=$5 AND doc_id=$6 RETURNING user_id",
+            &[&body.title, &body.content, &images, &user_id, &body.uid, &body.doc_id, &content_type.code()],
+        )
+        .await?
+    } else {
+        transaction.query_opt(
+            "UPDATE book SET title=$1, content=$2, updated_by=$3, content_type=$6 WHERE uid=$4 AND doc_id=$5 RETURNING user_id",
+            &[&body.title, &body.content, &user_id, &body.uid, &body.doc_id, &content_type.code()],
+        )
+        .await?
+    };
+    if updated.is_some() {
+        record_revision(&transaction, TagTable::Book, body.uid, user_id, None).await?;
+        transaction.commit().await?;
     }
 
-    if body.images.len() == 0 {
-        let _ = conn
-            .execute(
-                "UPDATE book SET title=$1, content=$2 WHERE uid=$3 AND user_id=$4",
-                &[&body.title, &body.content, &body.uid, &user_id],
-            )
-            .await?;
+    if let Some(row) = updated {
+        pool.search().upsert(&[IndexNode {
+            kind: DocKind::Book,
+            uid: body.uid,
+            doc_id: body.doc_id,
+            user_id: row.get(0),
+            identity: Some(body.identity),
+            title: &body.title,
+            content: &body.content,
+        }]);
     }
 
     Ok((
@@ -107,4 +154,19 @@ pub async fn edit_book_node(
     ))
 }
 
+/// Move a chapter, section or sub-section to follow another node, optionally
+/// into a different chapter or section (`page_id`).
+pub async fn move_book_node(
+    axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<MoveNode>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = move_doc_node(&pool, TagTable::Book, user_id, &body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
 // @End Edit
@@ -0,0 +1,473 @@
+use crate::book::tree::{build_tree, fetch_sub_sections, TreeNode};
+use crate::book::upload::IMAGE_LINK;
+use crate::error::AppError;
+use crate::fetch_book_pages;
+use crate::lifecycle::ensure_visible;
+use crate::traits::image_names;
+use crate::types::{BookParentNode, NavNodes};
+use crate::utils::doc::TagTable;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use crate::file::mime_for_extension;
+use crate::traits::IMAGE_SIZES;
+use crate::utils::markdown::{escape_html, render_html_with, strip_renderer_prefix};
+use std::collections::BTreeSet;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Image width written to the ZIP archive; the largest variant we keep.
+const ZIP_IMAGE_SIZE: u32 = 1420;
+/// Image width embedded in EPUBs, sized for e-readers.
+const EPUB_IMAGE_SIZE: u32 = 720;
+
+enum ExportFormat {
+    Zip,
+    Epub,
+}
+
+impl ExportFormat {
+    /// `12` and `12.zip` are ZIP exports, `12.epub` an EPUB.
+    fn parse(file: &str) -> Option<(i32, Self)> {
+        let (doc_id, format) = match file.rsplit_once('.') {
+            Some((doc_id, "zip")) => (doc_id, ExportFormat::Zip),
+            Some((doc_id, "epub")) => (doc_id, ExportFormat::Epub),
+            Some(_) => return None,
+            None => (file, ExportFormat::Zip),
+        };
+        Some((doc_id.parse().ok()?, format))
+    }
+}
+
+/// GET /book/export/:doc_id (ZIP) and GET /book/export/:doc_id.epub
+///
+/// The ZIP uses the layout `upload_book` reads (see docs/book-upload.md), so
+/// that exporting and uploading again yields the same chapters, sections,
+/// sub-sections, titles and content. The EPUB is for offline reading.
+pub async fn export_book(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(file): AxumPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let (doc_id, format) = ExportFormat::parse(&file)
+        .ok_or_else(|| AppError::NotFound(format!("No export named {file}")))?;
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, TagTable::Book, doc_id, viewer).await?;
+    let (mut nodes, main_node) = fetch_book_pages!(&conn, doc_id)?;
+    nodes.extend(fetch_sub_sections(&*conn, doc_id).await?);
+    drop(conn);
+
+    let mut names: BTreeSet<String> = image_names(main_node.images.as_deref()).into_iter().collect();
+    names.extend(nodes.iter().flat_map(|node| image_names(node.images.as_deref())));
+    let (chapters, _) = build_tree(main_node.uid, nodes);
+
+    let size = match format {
+        ExportFormat::Zip => ZIP_IMAGE_SIZE,
+        ExportFormat::Epub => EPUB_IMAGE_SIZE,
+    };
+    let mut images: Vec<(String, Vec<u8>)> = Vec::new();
+    for name in names {
+        let key = format!("{}/{}-{}", doc_id, size, name);
+        match pool.s3().get("book", &key).await {
+            Ok((bytes, _)) => images.push((name, bytes)),
+            Err(e) => tracing::warn!(doc_id, %key, error = %e, "export skipped a missing image"),
+        }
+    }
+
+    let root = slugify(&main_node.title);
+    let (bytes, content_type, extension) = match format {
+        ExportFormat::Zip => (write_zip(&root, &main_node, &chapters, &images)?, "application/zip", "zip"),
+        ExportFormat::Epub => (
+            write_epub(doc_id, &main_node, &chapters, &images, Utc::now())?,
+            "application/epub+zip",
+            "epub",
+        ),
+    };
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{root}.{extension}\"")),
+        ],
+        bytes,
+    ))
+}
+
+/// Lay the book out as `<root>/cover.md`, `<root>/NN-chapter/_index.md`,
+/// `<root>/NN-chapter/NN-section/_index.md` and
+/// `<root>/NN-chapter/NN-section/NN-sub-section.md`, with images under
+/// `<root>/images/`. Chapters and sections always get an `_index.md` so that
+/// empty ones survive the round trip.
+pub(super) fn write_zip(
+    root: &str,
+    main_node: &BookParentNode,
+    chapters: &[TreeNode],
+    images: &[(String, Vec<u8>)],
+) -> Result<Vec<u8>, AppError> {
+    let zip_error = |e: zip::result::ZipError| AppError::InternalServerError(e.to_string());
+    let text = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let binary = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    let bundled: BTreeSet<&str> = images.iter().map(|(name, _)| name.as_str()).collect();
+    let add = |zip: &mut ZipWriter<Cursor<Vec<u8>>>, path: String, body: String| -> Result<(), AppError> {
+        zip.start_file(path, text).map_err(zip_error)?;
+        zip.write_all(body.as_bytes())?;
+        Ok(())
+    };
+    // `depth` is how many folders below `<root>` the file sits.
+    let markdown = |title: &str, content: Option<&str>, depth: usize| {
+        markdown(title, &relative_image_links(content.unwrap_or_default(), depth, &bundled))
+    };
+
+    add(&mut zip, format!("{root}/cover.md"), markdown(&main_node.title, Some(&main_node.content), 0))?;
+    for (c, chapter) in chapters.iter().enumerate() {
+        let chapter_dir = format!("{root}/{:02}-{}", c + 1, slugify(&chapter.title));
+        add(&mut zip, format!("{chapter_dir}/_index.md"), markdown(&chapter.title, chapter.content.as_deref(), 1))?;
+        for (s, section) in chapter.children.iter().enumerate() {
+            let section_dir = format!("{chapter_dir}/{:02}-{}", s + 1, slugify(&section.title));
+            add(&mut zip, format!("{section_dir}/_index.md"), markdown(&section.title, section.content.as_deref(), 2))?;
+            for (n, sub) in section.children.iter().enumerate() {
+                add(
+                    &mut zip,
+                    format!("{section_dir}/{:02}-{}.md", n + 1, slugify(&sub.title)),
+                    markdown(&sub.title, sub.content.as_deref(), 2),
+                )?;
+            }
+        }
+    }
+    for (name, bytes) in images {
+        zip.start_file(format!("{root}/images/{name}"), binary).map_err(zip_error)?;
+        zip.write_all(bytes)?;
+    }
+
+    Ok(zip.finish().map_err(zip_error)?.into_inner())
+}
+
+/// Build an EPUB 3 package: the cover, then every chapter, section and
+/// sub-section as its own XHTML page in reading order, a nav document with
+/// the same nesting as `get_book_chapters_and_sections`, and the images.
+pub(super) fn write_epub(
+    doc_id: i32,
+    main_node: &BookParentNode,
+    chapters: &[TreeNode],
+    images: &[(String, Vec<u8>)],
+    modified: DateTime<Utc>,
+) -> Result<Vec<u8>, AppError> {
+    let zip_error = |e: zip::result::ZipError| AppError::InternalServerError(e.to_string());
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut add = |path: &str, bytes: &[u8], options: SimpleFileOptions| -> Result<(), AppError> {
+        zip.start_file(path, options).map_err(zip_error)?;
+        zip.write_all(bytes)?;
+        Ok(())
+    };
+
+    // `mimetype` must come first and uncompressed.
+    add("mimetype", b"application/epub+zip", stored)?;
+    add("META-INF/container.xml", EPUB_CONTAINER.as_bytes(), deflated)?;
+
+    let bundled: BTreeSet<&str> = images.iter().map(|(name, _)| name.as_str()).collect();
+    let image_src = |src: &str| epub_image_src(src, &bundled);
+
+    let mut pages: Vec<(String, String)> = vec![("cover".into(), main_node.title.clone())];
+    add(
+        "OEBPS/cover.xhtml",
+        xhtml_page(&main_node.title, 1, &main_node.content, &image_src).as_bytes(),
+        deflated,
+    )?;
+    let mut stack: Vec<(&TreeNode, usize)> = chapters.iter().rev().map(|node| (node, 1)).collect();
+    while let Some((node, level)) = stack.pop() {
+        let id = format!("node-{}", node.uid);
+        let content = node.content.as_deref().unwrap_or_default();
+        add(
+            &format!("OEBPS/{id}.xhtml"),
+            xhtml_page(&node.title, level, content, &image_src).as_bytes(),
+            deflated,
+        )?;
+        pages.push((id, node.title.clone()));
+        stack.extend(node.children.iter().rev().map(|child| (child, level + 1)));
+    }
+
+    let mut nav = format!(
+        "<li><a href=\"cover.xhtml\">{}</a></li>\n",
+        escape_html(&main_node.title)
+    );
+    nav_items(chapters, &mut nav);
+    add(
+        "OEBPS/nav.xhtml",
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+             <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"en\" xml:lang=\"en\">\n\
+             <head><title>{title}</title></head>\n<body>\n\
+             <nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{nav}</ol>\n</nav>\n</body>\n</html>\n",
+            title = escape_html(&main_node.title)
+        )
+        .as_bytes(),
+        deflated,
+    )?;
+
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    let mut spine = String::new();
+    for (id, _) in &pages {
+        manifest.push_str(&format!(
+            "<item id=\"{id}\" href=\"{id}.xhtml\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        spine.push_str(&format!("<itemref idref=\"{id}\"/>\n"));
+    }
+    for (n, (name, bytes)) in images.iter().enumerate() {
+        let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+        manifest.push_str(&format!(
+            "<item id=\"img-{}\" href=\"images/{}\" media-type=\"{}\"/>\n",
+            n + 1,
+            escape_html(name),
+            mime_for_extension(&extension)
+        ));
+        add(&format!("OEBPS/images/{name}"), bytes, stored)?;
+    }
+    add(
+        "OEBPS/content.opf",
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+             <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+             <dc:identifier id=\"book-id\">urn:loony:book:{doc_id}</dc:identifier>\n\
+             <dc:title>{title}</dc:title>\n\
+             <dc:language>en</dc:language>\n\
+             <meta property=\"dcterms:modified\">{modified}</meta>\n\
+             </metadata>\n<manifest>\n{manifest}</manifest>\n<spine>\n{spine}</spine>\n</package>\n",
+            title = escape_html(&main_node.title),
+            modified = modified.format("%Y-%m-%dT%H:%M:%SZ"),
+        )
+        .as_bytes(),
+        deflated,
+    )?;
+
+    Ok(zip.finish().map_err(zip_error)?.into_inner())
+}
+
+const EPUB_CONTAINER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+<rootfiles>\n\
+<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+</rootfiles>\n\
+</container>\n";
+
+fn nav_items(nodes: &[TreeNode], out: &mut String) {
+    for node in nodes {
+        out.push_str(&format!(
+            "<li><a href=\"node-{}.xhtml\">{}</a>",
+            node.uid,
+            escape_html(&node.title)
+        ));
+        if !node.children.is_empty() {
+            out.push_str("\n<ol>\n");
+            nav_items(&node.children, out);
+            out.push_str("</ol>\n");
+        }
+        out.push_str("</li>\n");
+    }
+}
+
+fn xhtml_page(title: &str, level: usize, content: &str, image_src: &dyn Fn(&str) -> String) -> String {
+    let level = level.min(6);
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" lang=\"en\" xml:lang=\"en\">\n\
+         <head><title>{title}</title></head>\n<body>\n<h{level}>{title}</h{level}>\n{body}</body>\n</html>\n",
+        title = escape_html(title),
+        body = render_html_with(strip_renderer_prefix(content), image_src),
+    )
+}
+
+/// The packed image an image link points at: its file name after any
+/// `/file/book/:uid/:size/` path and `NNN-` size prefix, if we packed it.
+fn bundled_name<'a>(src: &'a str, bundled: &BTreeSet<&str>) -> Option<&'a str> {
+    let file = src.rsplit('/').next().unwrap_or(src);
+    let name = IMAGE_SIZES
+        .iter()
+        .find_map(|size| file.strip_prefix(&format!("{size}-")))
+        .unwrap_or(file);
+    bundled.contains(name).then_some(name)
+}
+
+/// Point image links at the bundled copy.
+fn epub_image_src(src: &str, bundled: &BTreeSet<&str>) -> String {
+    match bundled_name(src, bundled) {
+        Some(name) => format!("images/{name}"),
+        None => src.to_string(),
+    }
+}
+
+/// Point markdown image links at the bundled copy under `<root>/images/`, from
+/// a file `depth` folders below `<root>`, so that the upload finds them again.
+fn relative_image_links(content: &str, depth: usize, bundled: &BTreeSet<&str>) -> String {
+    IMAGE_LINK
+        .replace_all(content, |caps: &regex::Captures| match bundled_name(&caps[2], bundled) {
+            Some(name) => {
+                let title = caps.get(3).map_or("", |m| m.as_str());
+                format!("![{}]({}images/{name}{title})", &caps[1], "../".repeat(depth))
+            }
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// `# Title` followed by the body without its `<basic>` renderer prefix,
+/// which the importer adds back.
+fn markdown(title: &str, content: &str) -> String {
+    let body = content.strip_prefix("<basic>").map(str::trim_start).unwrap_or(content);
+    if body.is_empty() {
+        format!("# {title}\n")
+    } else {
+        format!("# {title}\n\n{body}\n")
+    }
+}
+
+/// `The Complete Guide!` → `the-complete-guide`. Used for file and folder
+/// names only; titles travel in each file's H1.
+pub(super) fn slugify(title: &str) -> String {
+    let slug = title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() { "untitled".into() } else { slug }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::upload::parse_zip;
+    use chrono::Utc;
+
+    fn nav(uid: i32, parent_id: i32, identity: i16, page_id: i32, title: &str, content: &str) -> NavNodes {
+        NavNodes {
+            uid,
+            parent_id: Some(parent_id),
+            title: title.into(),
+            content: Some(content.into()),
+            identity,
+            page_id: Some(page_id),
+            images: None,
+        }
+    }
+
+    fn sample_book() -> (BookParentNode, Vec<TreeNode>) {
+        let main_node = BookParentNode {
+            uid: 1,
+            user_id: 7,
+            doc_id: 3,
+            title: "PostgreSQL: The Guide".into(),
+            content: "<basic> A book.".into(),
+            images: None,
+            created_at: Utc::now(),
+        };
+        let nodes = vec![
+            nav(10, 1, 101, 1, "Getting Started", "<basic> Intro\n\nMore."),
+            nav(11, 10, 101, 1, "Empty chapter", ""),
+            nav(20, 10, 102, 10, "Installing on Linux", ""),
+            nav(30, 20, 103, 20, "apt", "<basic> sudo apt install postgresql"),
+            nav(31, 30, 103, 20, "Source builds", "<basic> ./configure"),
+        ];
+        let (chapters, warnings) = build_tree(main_node.uid, nodes);
+        assert!(warnings.is_empty());
+        (main_node, chapters)
+    }
+
+    #[test]
+    fn test_export_round_trips_through_upload() {
+        let (mut main_node, mut chapters) = sample_book();
+        main_node.content = "<basic> A book.\n\n![logo](/file/book/3/720/logo.png)".into();
+        chapters[0].content = Some("<basic> Intro\n\n![logo](/file/book/3/720/logo.png \"Logo\")".into());
+        chapters[0].children[0].children[1].content =
+            Some("<basic> ./configure ![logo](/file/book/3/340/logo.png) ![web](https://x.dev/a.png)".into());
+        let images = vec![("logo.png".to_string(), b"\x89PNG\r\n\x1a\n....".to_vec())];
+
+        let bytes = write_zip("postgresql-the-guide", &main_node, &chapters, &images).unwrap();
+        let mut book = parse_zip(&bytes).unwrap();
+        book.link_images(9);
+        let name = book.images["images/logo.png"].name.clone();
+        let link = format!("/file/book/9/720/{name}");
+
+        assert_eq!(book.title, "PostgreSQL: The Guide");
+        assert_eq!(book.content, format!("<basic> A book.\n\n![logo]({link})"));
+        assert_eq!(book.cover_images, vec![name.clone()]);
+        let imported: Vec<(&str, &str)> =
+            book.chapters.values().map(|c| (c.title.as_str(), c.content.as_str())).collect();
+        assert_eq!(
+            imported,
+            vec![
+                ("Getting Started", format!("<basic> Intro\n\n![logo]({link} \"Logo\")").as_str()),
+                ("Empty chapter", "")
+            ]
+        );
+        let section = book.chapters.values().next().unwrap().sections.values().next().unwrap();
+        assert_eq!((section.title.as_str(), section.content.as_str()), ("Installing on Linux", ""));
+        let subs: Vec<(&str, &str)> =
+            section.subsections.values().map(|s| (s.title.as_str(), s.content.as_str())).collect();
+        assert_eq!(
+            subs,
+            vec![
+                ("apt", "<basic> sudo apt install postgresql"),
+                (
+                    "Source builds",
+                    format!("<basic> ./configure ![logo]({link}) ![web](https://x.dev/a.png)").as_str()
+                )
+            ]
+        );
+        let sub = section.subsections.values().nth(1).unwrap();
+        assert_eq!(sub.images, vec![name]);
+    }
+
+    #[test]
+    fn test_epub_package() {
+        use std::io::Read;
+
+        let (main_node, chapters) = sample_book();
+        let images = vec![("fig.png".to_string(), vec![0u8; 4])];
+        let bytes = write_epub(3, &main_node, &chapters, &images, Utc::now()).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let first = archive.by_index(0).unwrap();
+        assert_eq!(first.name(), "mimetype");
+        assert_eq!(first.compression(), CompressionMethod::Stored);
+        drop(first);
+
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+        let opf = read("OEBPS/content.opf");
+        let spine: Vec<&str> = opf.lines().filter(|line| line.starts_with("<itemref")).collect();
+        assert_eq!(
+            spine,
+            vec![
+                "<itemref idref=\"cover\"/>",
+                "<itemref idref=\"node-10\"/>",
+                "<itemref idref=\"node-20\"/>",
+                "<itemref idref=\"node-30\"/>",
+                "<itemref idref=\"node-31\"/>",
+                "<itemref idref=\"node-11\"/>",
+            ]
+        );
+        assert!(opf.contains("href=\"images/fig.png\" media-type=\"image/png\""));
+
+        let nav = read("OEBPS/nav.xhtml");
+        assert!(nav.contains("<li><a href=\"node-20.xhtml\">Installing on Linux</a>\n<ol>\n<li><a href=\"node-30.xhtml\">apt</a></li>"));
+        assert!(read("OEBPS/node-30.xhtml").contains("<h3>apt</h3>\n<p>sudo apt install postgresql</p>"));
+        assert_eq!(read("OEBPS/cover.xhtml").matches("<h1>").count(), 1);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::error::AppError;
 use crate::{fetch_book_pages, fetch_home_books, AppState};
 use axum::{
-    extract::{Path as AxumPath, Query, State},
+    extract::{Extension, Path as AxumPath, Query, State},
     http::{header, StatusCode},
     response::IntoResponse,
     Json,
@@ -9,20 +9,32 @@ use axum::{
 use serde::Deserialize;
 use serde_json::json;
 use crate::types::{Book, BookParentNode, NavNodes, ChildNode};
-use crate::{fetch_books_by_user_id};
+use crate::{fetch_books_by_tag, fetch_books_by_user_id};
+use crate::utils::content::node_html;
+use crate::utils::doc::{fetch_tags, normalize_tag, tag_cloud, TagCloudRequest, TagTable};
+use crate::utils::page::{PageRequest, DEFAULT_PAGE_SIZE, HOME_PAGE_SIZE};
+use crate::utils::UserId;
+use crate::recommend::record_view;
+use crate::progress::{completed_nodes, mark_completed};
+use crate::lifecycle::ensure_visible;
+use crate::book::tree::{build_tree, fetch_sub_sections};
 
 
-pub async fn get_all_books_by_page_no(
+pub async fn get_all_books(
     State(pool): State<AppState>,
-    AxumPath(page_no): AxumPath<i64>,
+    query: Query<PageRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
     let conn = pool.pg_pool.conn.get().await?;
-    let limit: i64 = 2;
-    let offset: i64 = (page_no - 1) * limit;
     let rows = conn
         .query(
-            "SELECT uid, user_id, title, images, created_at FROM books where deleted_at is NULL ORDER BY created_at DESC LIMIT $1 OFFSET $2",
-            &[&limit, &offset],
+            &format!("SELECT uid, user_id, title, images, created_at, status, like_count, bookmark_count FROM books where deleted_at is NULL AND status = 'published' \
+             AND {after} \
+             {order} LIMIT $3",
+                after = keyset.after("books", "", 1),
+                order = keyset.order_by(""),
+            ),
+            &[&keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
         )
         .await?;
 
@@ -35,21 +47,27 @@ pub async fn get_all_books_by_page_no(
             title: rows[index].get(2),
             images: rows[index].get(3),
             created_at: rows[index].get(4),
+            status: rows[index].get(5),
+            like_count: rows[index].get(6),
+            bookmark_count: rows[index].get(7),
         })
     }
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(books),
+        Json(keyset.page(books, |b| (b.created_at, b.uid))),
     ))
 }
 
 pub async fn get_all_books_by_user_id(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
     let conn = pool.pg_pool.conn.get().await?;
-    let books = fetch_books_by_user_id!(&conn, user_id)?;
+    let books = fetch_books_by_user_id!(&conn, user_id, user.map(|Extension(UserId(id))| id), keyset)?;
 
     // let doc_ids_user_tags_query = "SELECT doc_id FROM book_tags where user_id=$1";
     // let mut seen: HashSet<i32> = HashSet::new();
@@ -74,33 +92,36 @@ pub async fn get_all_books_by_user_id(
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(books),
+        Json(keyset.page(books, |b| (b.created_at, b.uid))),
     ))
 }
 
 #[derive(Deserialize)]
 pub struct ChaptersByBookIdRequest {
     doc_id: i32,
+    /// `flat` (default) or `tree`.
+    format: Option<String>,
 }
 
 #[derive(Deserialize)]
-#[allow(dead_code)]
 pub struct Chapter {
     doc_id: i32,
     page_id: i32
 }
 
 pub async fn get_chapter_details(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     query: Query<Chapter>,
 ) -> Result<impl IntoResponse, AppError> {
     let book_request: Chapter = query.0;
 
     let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, TagTable::Book, book_request.doc_id, user.map(|Extension(UserId(id))| id)).await?;
     let rows = conn
         .query(
-            "SELECT uid, parent_id, title, content, images, identity, page_id FROM book where uid=$1 OR parent_id=$2 AND identity=103 AND deleted_at is null",
-            &[&book_request.page_id, &book_request.page_id],
+            "SELECT uid, parent_id, title, content, images, identity, page_id FROM book where doc_id=$3 AND (uid=$1 OR parent_id=$2 AND identity=103) AND deleted_at is null",
+            &[&book_request.page_id, &book_request.page_id, &book_request.doc_id],
         )
         .await?;
 
@@ -127,23 +148,24 @@ pub async fn get_chapter_details(
 }
 
 #[derive(Deserialize)]
-#[allow(dead_code)]
 pub struct Section {
     doc_id: i32,
     page_id: i32
 }
 
 pub async fn get_section_details(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     query: Query<Section>,
 ) -> Result<impl IntoResponse, AppError> {
     let book_request: Section = query.0;
 
     let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, TagTable::Book, book_request.doc_id, user.map(|Extension(UserId(id))| id)).await?;
     let rows = conn
         .query(
-            "SELECT uid, parent_id, title, content, images, identity, page_id FROM book where uid=$1 OR page_id=$2 AND identity=103 AND deleted_at is null",
-            &[&book_request.page_id, &book_request.page_id],
+            "SELECT uid, parent_id, title, content, images, identity, page_id FROM book where doc_id=$3 AND (uid=$1 OR page_id=$2 AND identity=103) AND deleted_at is null",
+            &[&book_request.page_id, &book_request.page_id, &book_request.doc_id],
         )
         .await?;
 
@@ -170,20 +192,51 @@ pub async fn get_section_details(
 }
 
 pub async fn get_book_chapters_and_sections(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     query: Query<ChaptersByBookIdRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     let doc_id: i32 = query.doc_id;
+    let tree = match query.format.as_deref() {
+        None | Some("flat") => false,
+        Some("tree") => true,
+        Some(other) => return Err(AppError::BadRequest(format!("Unknown format `{other}`"))),
+    };
+    let viewer = user.map(|Extension(UserId(id))| id);
     let conn = pool.pg_pool.conn.get().await?;
-    let (books, main_node) = fetch_book_pages!(&conn, doc_id)?;
+    ensure_visible(&conn, TagTable::Book, doc_id, viewer).await?;
+    let (mut books, main_node) = fetch_book_pages!(&conn, doc_id)?;
+    let tags = fetch_tags(&conn, TagTable::Book, doc_id).await?;
+    if let Some(user_id) = viewer {
+        record_view(&conn, TagTable::Book, user_id, doc_id).await;
+    }
+
+    let mut body = if tree {
+        books.extend(fetch_sub_sections(&*conn, doc_id).await?);
+        let (chapters, warnings) = build_tree(main_node.uid, books);
+        json!({
+            "main_node": main_node,
+            "chapters": chapters,
+            "warnings": warnings,
+            "tags": tags
+        })
+    } else {
+        json!({
+            "main_node": main_node,
+            "child_nodes": books,
+            "tags": tags
+        })
+    };
+    if let Some(user_id) = viewer {
+        let done = completed_nodes(&conn, user_id, doc_id).await?;
+        let nodes = if tree { "chapters" } else { "child_nodes" };
+        mark_completed(&mut body[nodes], &done);
+    }
 
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(json!({
-            "main_node": main_node,
-            "child_nodes": books
-        })),
+        Json(body),
     ))
 }
 
@@ -191,28 +244,79 @@ pub async fn get_book_chapters_and_sections(
 // @End Get
 
 pub async fn get_users_book(
+    user: Option<Extension<UserId>>,
     State(pool): State<AppState>,
     AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
     let conn = pool.pg_pool.conn.get().await?;
 
-    let books = fetch_books_by_user_id!(&conn, user_id)?;
+    let books = fetch_books_by_user_id!(&conn, user_id, user.map(|Extension(UserId(id))| id), keyset)?;
 
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(books),
+        Json(keyset.page(books, |b| (b.created_at, b.uid))),
     ))
 }
 
 pub async fn get_home_books(
     State(pool): State<AppState>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(HOME_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let books = fetch_home_books!(&conn, keyset)?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(keyset.page(books, |b| (b.created_at, b.uid))),
+    ))
+}
+
+pub async fn get_books_by_tag(
+    State(pool): State<AppState>,
+    AxumPath(tag): AxumPath<String>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let tag = normalize_tag(&tag)?;
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let books = fetch_books_by_tag!(&conn, tag, keyset)?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(keyset.page(books, |b| (b.created_at, b.uid))),
+    ))
+}
+
+pub async fn get_book_tag_cloud(
+    State(pool): State<AppState>,
+    query: Query<TagCloudRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    let tags = tag_cloud(&conn, TagTable::Book, query.limit()).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(tags),
+    ))
+}
+
+/// GET /book/node/:uid/html — the node rendered to sanitised HTML for its
+/// content type.
+pub async fn get_book_node_html(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(uid): AxumPath<i32>,
 ) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
     let conn = pool.pg_pool.conn.get().await?;
-    let books = fetch_home_books!(&conn)?;
+    let node = node_html(&conn, TagTable::Book, uid, viewer).await?;
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(books),
+        Json(node),
     ))
 }
@@ -0,0 +1,205 @@
+//! Background import jobs for `POST /book/upload`. The handler only reads the
+//! ZIP and queues it; a fixed pool of workers parses and inserts each book
+//! while clients poll `GET /book/import/:job_id`. Jobs live in memory and
+//! are forgotten an hour after they finish.
+
+use crate::book::upload::{error_message, insert_book, parse_book, FileError};
+use crate::error::AppError;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// ZIPs waiting for a worker; uploads beyond this are turned away.
+const QUEUE_CAPACITY: usize = 16;
+/// How long a finished job can still be polled.
+const FINISHED_JOB_TTL_MINUTES: i64 = 60;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub(crate) enum JobState {
+    Queued,
+    Parsing,
+    Inserting { done: usize, total: usize },
+    /// `result` is `{doc_id, title, chapters, images}`.
+    Done { result: serde_json::Value },
+    Failed { errors: Vec<FileError> },
+}
+
+impl JobState {
+    fn is_finished(&self) -> bool {
+        matches!(self, JobState::Done { .. } | JobState::Failed { .. })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ImportJob {
+    job_id: String,
+    #[serde(skip)]
+    user_id: i32,
+    #[serde(flatten)]
+    state: JobState,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+struct Task {
+    job_id: String,
+    user_id: i32,
+    zip_bytes: Vec<u8>,
+}
+
+/// The job table and the queue feeding the workers.
+#[derive(Clone)]
+pub(crate) struct ImportJobs {
+    jobs: Arc<Mutex<HashMap<String, ImportJob>>>,
+    sender: mpsc::Sender<Task>,
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<Task>>>,
+}
+
+impl ImportJobs {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        ImportJobs {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            sender,
+            receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+        }
+    }
+
+    /// Queue a ZIP for import. `503` when the queue is full.
+    pub fn enqueue(&self, user_id: i32, zip_bytes: Vec<u8>) -> Result<ImportJob, AppError> {
+        let now = Utc::now();
+        let job = ImportJob {
+            job_id: Uuid::new_v4().to_string(),
+            user_id,
+            state: JobState::Queued,
+            created_at: now,
+            updated_at: now,
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let expired = now - Duration::minutes(FINISHED_JOB_TTL_MINUTES);
+            jobs.retain(|_, job| !job.state.is_finished() || job.updated_at > expired);
+            jobs.insert(job.job_id.clone(), job.clone());
+        }
+        let task = Task { job_id: job.job_id.clone(), user_id, zip_bytes };
+        if self.sender.try_send(task).is_err() {
+            self.jobs.lock().unwrap().remove(&job.job_id);
+            return Err(AppError::Error((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many imports in progress, try again shortly".into(),
+            )));
+        }
+        Ok(job)
+    }
+
+    /// The job, if it exists and belongs to `user_id`.
+    pub fn get(&self, job_id: &str, user_id: i32) -> Option<ImportJob> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(job_id).filter(|job| job.user_id == user_id).cloned()
+    }
+
+    fn set(&self, job_id: &str, state: JobState) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(job_id) {
+            job.state = state;
+            job.updated_at = Utc::now();
+        }
+    }
+}
+
+/// Start `config.app.import_workers` workers pulling from the queue.
+pub fn spawn_workers(state: AppState) {
+    for _ in 0..state.config.app.import_workers.max(1) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let task = state.imports().receiver.lock().await.recv().await;
+                let Some(task) = task else { break };
+                run(&state, task).await;
+            }
+        });
+    }
+}
+
+async fn run(state: &AppState, task: Task) {
+    let jobs = state.imports();
+    let Task { job_id, user_id, zip_bytes } = task;
+    jobs.set(&job_id, JobState::Parsing);
+
+    // Parsing and image checks are CPU-bound; keep them off the async workers.
+    let book = match tokio::task::spawn_blocking(move || parse_book(&zip_bytes)).await {
+        Ok(Ok(book)) => book,
+        Ok(Err(errors)) => {
+            jobs.set(&job_id, JobState::Failed { errors });
+            return;
+        }
+        Err(e) => {
+            tracing::error!(job_id, error = %e, "book import parser panicked");
+            jobs.set(&job_id, JobState::Failed { errors: vec![FileError::archive("Internal server error")] });
+            return;
+        }
+    };
+
+    let total = book.node_count();
+    jobs.set(&job_id, JobState::Inserting { done: 0, total });
+    let progress = |done: usize| jobs.set(&job_id, JobState::Inserting { done, total });
+    let state_after = match insert_book(state, user_id, book, &progress).await {
+        Ok(result) => JobState::Done { result },
+        Err(e) => JobState::Failed { errors: vec![FileError::archive(error_message(e))] },
+    };
+    jobs.set(&job_id, state_after);
+}
+
+/// GET /book/import/:job_id
+pub async fn get_import_job(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(job_id): AxumPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = pool
+        .imports()
+        .get(&job_id, user_id)
+        .ok_or_else(|| AppError::NotFound(format!("Import job {job_id} not found")))?;
+    Ok((StatusCode::OK, [(header::CONTENT_TYPE, "application/json")], Json(job)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enqueue_and_poll() {
+        let imports = ImportJobs::new();
+        let job = imports.enqueue(7, vec![1, 2, 3]).unwrap();
+        assert_eq!(imports.get(&job.job_id, 7).unwrap().state, JobState::Queued);
+        // Other users cannot see the job.
+        assert!(imports.get(&job.job_id, 8).is_none());
+
+        imports.set(&job.job_id, JobState::Inserting { done: 2, total: 5 });
+        let polled = serde_json::to_value(imports.get(&job.job_id, 7).unwrap()).unwrap();
+        assert_eq!(polled["state"], "inserting");
+        assert_eq!(polled["done"], 2);
+        assert_eq!(polled["total"], 5);
+        assert!(polled.get("user_id").is_none());
+
+        // Nothing drains the queue here, so it fills up.
+        for _ in 1..QUEUE_CAPACITY {
+            imports.enqueue(7, Vec::new()).unwrap();
+        }
+        assert!(matches!(
+            imports.enqueue(7, Vec::new()),
+            Err(AppError::Error((StatusCode::SERVICE_UNAVAILABLE, _)))
+        ));
+    }
+}
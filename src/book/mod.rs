@@ -1,5 +1,10 @@
+pub mod collaborators;
 pub mod create;
 pub mod delete;
 pub mod edit;
+pub mod export;
+pub mod reupload;
 pub mod get;
+pub mod import;
+pub mod tree;
 pub mod upload;
\ No newline at end of file
@@ -0,0 +1,499 @@
+use crate::book::collaborators::{require_book_role, CollaboratorRole};
+use crate::book::export::slugify;
+use crate::book::tree::{build_tree, fetch_sub_sections, TreeNode};
+use crate::book::upload::{images_json, read_book_zip, store_images, BookStructure};
+use crate::error::AppError;
+use crate::fetch_book_pages;
+use crate::revision::{record_revision, snapshot_original};
+use crate::search::index::{DocKind, IndexNode};
+use crate::types::{BookParentNode, NavNodes};
+use crate::utils::content::ContentType;
+use crate::utils::doc::{insert_tags, TagTable};
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Multipart, Path as AxumPath, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::Transaction;
+
+/// A chapter, section or sub-section as read from the ZIP.
+struct Incoming<'a> {
+    title: &'a str,
+    content: &'a str,
+    images: &'a [String],
+    slug: Option<&'a str>,
+    children: Vec<Incoming<'a>>,
+}
+
+fn incoming(book: &BookStructure) -> Vec<Incoming<'_>> {
+    book.chapters
+        .values()
+        .map(|chapter| Incoming {
+            title: &chapter.title,
+            content: &chapter.content,
+            images: &chapter.images,
+            slug: chapter.slug.as_deref(),
+            children: chapter
+                .sections
+                .values()
+                .map(|section| Incoming {
+                    title: &section.title,
+                    content: &section.content,
+                    images: &section.images,
+                    slug: section.slug.as_deref(),
+                    children: section
+                        .subsections
+                        .values()
+                        .map(|sub| Incoming {
+                            title: &sub.title,
+                            content: &sub.content,
+                            images: &sub.images,
+                            slug: sub.slug.as_deref(),
+                            children: Vec::new(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Where an incoming node ends up: an existing node it replaces, or a new one.
+struct PlannedNode<'a> {
+    existing: Option<&'a TreeNode>,
+    title: &'a str,
+    content: &'a str,
+    images: &'a [String],
+    changed: bool,
+    children: Vec<PlannedNode<'a>>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PlannedChange {
+    /// `insert`, `update`, `move` or `delete`.
+    action: &'static str,
+    identity: i16,
+    uid: Option<i32>,
+    path: String,
+}
+
+struct Plan<'a> {
+    cover_changed: bool,
+    nodes: Vec<PlannedNode<'a>>,
+    deletes: Vec<&'a TreeNode>,
+    changes: Vec<PlannedChange>,
+}
+
+/// Pair incoming nodes with existing siblings: same title first, then same
+/// slug (so a change of case or punctuation keeps the node). A front matter
+/// `slug` stands in for the incoming title's slug, so a renamed node can keep
+/// its uid. Each existing node is used at most once.
+fn match_siblings(existing: &[TreeNode], incoming: &[Incoming]) -> Vec<Option<usize>> {
+    let mut used = vec![false; existing.len()];
+    let mut matches = vec![None; incoming.len()];
+    for by_slug in [false, true] {
+        let same = |e: &TreeNode, i: &Incoming| {
+            if by_slug {
+                let slug = i.slug.map_or_else(|| slugify(i.title), str::to_string);
+                slugify(&e.title) == slug
+            } else {
+                e.title == i.title
+            }
+        };
+        for (n, node) in incoming.iter().enumerate() {
+            if matches[n].is_some() {
+                continue;
+            }
+            if let Some(found) = (0..existing.len()).find(|&e| !used[e] && same(&existing[e], node)) {
+                used[found] = true;
+                matches[n] = Some(found);
+            }
+        }
+    }
+    matches
+}
+
+fn plan_level<'a>(
+    existing: &'a [TreeNode],
+    page_uid: Option<i32>,
+    incoming: &'a [Incoming<'a>],
+    identity: i16,
+    path: &str,
+    plan: &mut Plan<'a>,
+) -> Vec<PlannedNode<'a>> {
+    let matches = match_siblings(existing, incoming);
+    let mut prev = page_uid;
+    let mut planned = Vec::new();
+    for (node, found) in incoming.iter().zip(matches.iter()) {
+        let node_path = if path.is_empty() { node.title.to_string() } else { format!("{path} / {}", node.title) };
+        match found.map(|e| &existing[e]) {
+            Some(current) => {
+                let changed = current.title != node.title
+                    || current.content.as_deref().unwrap_or_default() != node.content;
+                if changed {
+                    plan.changes.push(PlannedChange { action: "update", identity, uid: Some(current.uid), path: node_path.clone() });
+                }
+                // A new predecessor (`None`) always means a new parent_id.
+                if prev.is_none() || current.parent_id != prev {
+                    plan.changes.push(PlannedChange { action: "move", identity, uid: Some(current.uid), path: node_path.clone() });
+                }
+                let children = plan_level(&current.children, Some(current.uid), &node.children, identity + 1, &node_path, plan);
+                planned.push(PlannedNode {
+                    existing: Some(current),
+                    title: node.title,
+                    content: node.content,
+                    images: node.images,
+                    changed,
+                    children,
+                });
+                prev = Some(current.uid);
+            }
+            None => {
+                plan.changes.push(PlannedChange { action: "insert", identity, uid: None, path: node_path.clone() });
+                let children = plan_level(&[], None, &node.children, identity + 1, &node_path, plan);
+                planned.push(PlannedNode {
+                    existing: None,
+                    title: node.title,
+                    content: node.content,
+                    images: node.images,
+                    changed: true,
+                    children,
+                });
+                prev = None;
+            }
+        }
+    }
+    for (e, current) in existing.iter().enumerate() {
+        if !matches.contains(&Some(e)) {
+            let node_path = if path.is_empty() { current.title.clone() } else { format!("{path} / {}", current.title) };
+            plan.changes.push(PlannedChange { action: "delete", identity, uid: Some(current.uid), path: node_path });
+            plan.deletes.push(current);
+        }
+    }
+    planned
+}
+
+fn plan<'a>(main_node: &BookParentNode, existing: &'a [TreeNode], incoming: &'a [Incoming<'a>], book: &BookStructure) -> Plan<'a> {
+    let mut plan = Plan {
+        cover_changed: main_node.title != book.title || main_node.content != book.content,
+        nodes: Vec::new(),
+        deletes: Vec::new(),
+        changes: Vec::new(),
+    };
+    if plan.cover_changed {
+        plan.changes.push(PlannedChange { action: "update", identity: 100, uid: Some(main_node.uid), path: book.title.clone() });
+    }
+    plan.nodes = plan_level(existing, Some(main_node.uid), incoming, 101, "", &mut plan);
+    plan
+}
+
+/// The changes with a count per action.
+fn response(doc_id: i32, dry_run: bool, changes: Vec<PlannedChange>) -> serde_json::Value {
+    let count = |action: &str| changes.iter().filter(|c| c.action == action).count();
+    json!({
+        "doc_id": doc_id,
+        "dry_run": dry_run,
+        "summary": {
+            "inserted": count("insert"),
+            "updated": count("update"),
+            "moved": count("move"),
+            "deleted": count("delete"),
+        },
+        "changes": changes
+    })
+}
+
+fn subtree_uids(node: &TreeNode, out: &mut Vec<i32>) {
+    out.push(node.uid);
+    for child in &node.children {
+        subtree_uids(child, out);
+    }
+}
+
+/// Write one planned node after `prev` on `page_uid`; returns its uid and,
+/// when its text was written, the author to index it under.
+async fn place(
+    tx: &Transaction<'_>,
+    doc_id: i32,
+    user_id: i32,
+    node: &PlannedNode<'_>,
+    page_uid: i32,
+    prev: i32,
+    identity: i16,
+) -> Result<(i32, Option<i32>), AppError> {
+    let images = images_json(node.images);
+    let content_type = ContentType::resolve(None, node.content).code();
+    let Some(current) = node.existing else {
+        let row = tx
+            .query_one(
+                "INSERT INTO book(user_id, doc_id, page_id, parent_id, title, content, identity, images, content_type) \
+                 VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING uid",
+                &[&user_id, &doc_id, &page_uid, &prev, &node.title, &node.content, &identity, &images, &content_type],
+            )
+            .await?;
+        return Ok((row.get(0), Some(user_id)));
+    };
+    if node.changed {
+        snapshot_original(tx, TagTable::Book, current.uid).await?;
+        let row = tx
+            .query_one(
+                "UPDATE book SET title=$1, content=$2, images=$3, updated_by=$4, page_id=$5, parent_id=$6, content_type=$9 \
+                 WHERE uid=$7 AND doc_id=$8 RETURNING user_id",
+                &[&node.title, &node.content, &images, &user_id, &page_uid, &prev, &current.uid, &doc_id, &content_type],
+            )
+            .await?;
+        record_revision(tx, TagTable::Book, current.uid, user_id, None).await?;
+        return Ok((current.uid, Some(row.get(0))));
+    }
+    if current.parent_id != Some(prev) || current.page_id != Some(page_uid) {
+        tx.execute(
+            "UPDATE book SET page_id=$1, parent_id=$2 WHERE uid=$3 AND doc_id=$4",
+            &[&page_uid, &prev, &current.uid, &doc_id],
+        )
+        .await?;
+    }
+    Ok((current.uid, None))
+}
+
+fn index_entry<'a>(
+    node: &PlannedNode<'a>,
+    doc_id: i32,
+    uid: i32,
+    author: Option<i32>,
+    identity: i16,
+) -> Option<IndexNode<'a>> {
+    author.map(|user_id| IndexNode {
+        kind: DocKind::Book,
+        uid,
+        doc_id,
+        user_id,
+        identity: Some(identity),
+        title: node.title,
+        content: node.content,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ReuploadQuery {
+    dry_run: Option<bool>,
+}
+
+/// POST /book/:doc_id/upload
+///
+/// Re-import a ZIP (same layout as `upload_book`) into an existing book.
+/// Chapters are matched to existing ones by title, sections within their
+/// chapter, sub-sections within their section; matched nodes keep their uid
+/// and are updated or re-linked in place, unmatched ones are inserted, and
+/// existing nodes missing from the ZIP are soft-deleted with their children.
+/// `?dry_run=true` returns the planned changes without writing anything.
+pub async fn reupload_book(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+    Query(query): Query<ReuploadQuery>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let dry_run = query.dry_run.unwrap_or(false);
+    let mut book = read_book_zip(&mut multipart).await?;
+
+    let mut conn = pool.pg_pool.conn.get().await?;
+    require_book_role(&conn, doc_id, user_id, CollaboratorRole::Editor).await?;
+    let used_images = book.link_images(doc_id);
+    let incoming = incoming(&book);
+    let tx = conn.transaction().await?;
+    // Hold the book while we diff against it so concurrent edits cannot interleave.
+    tx.execute("SELECT 1 FROM books WHERE uid = $1 FOR UPDATE", &[&doc_id]).await?;
+    let (mut nodes, main_node) = fetch_book_pages!(&tx, doc_id)?;
+    nodes.extend(fetch_sub_sections(&tx, doc_id).await?);
+    let (existing, _) = build_tree(main_node.uid, nodes);
+    let plan = plan(&main_node, &existing, &incoming, &book);
+
+    if dry_run {
+        return Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/json")],
+            Json(response(doc_id, true, plan.changes)),
+        ));
+    }
+    insert_tags(&tx, TagTable::Book, doc_id, user_id, &book.tags).await?;
+    if plan.changes.is_empty() {
+        tx.commit().await?;
+        return Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/json")],
+            Json(response(doc_id, false, plan.changes)),
+        ));
+    }
+
+    let mut deleted: Vec<i32> = Vec::new();
+    for node in &plan.deletes {
+        subtree_uids(node, &mut deleted);
+    }
+    if !deleted.is_empty() {
+        tx.execute(
+            "UPDATE book SET deleted_at=$1 WHERE uid=ANY($2) AND doc_id=$3",
+            &[&Local::now(), &deleted, &doc_id],
+        )
+        .await?;
+    }
+
+    let mut indexed: Vec<IndexNode> = Vec::new();
+    if plan.cover_changed {
+        let cover_images = images_json(&book.cover_images);
+        snapshot_original(&tx, TagTable::Book, main_node.uid).await?;
+        tx.execute(
+            "UPDATE book SET title=$1, content=$2, images=$3, updated_by=$4, content_type=$6 WHERE uid=$5",
+            &[
+                &book.title,
+                &book.content,
+                &cover_images,
+                &user_id,
+                &main_node.uid,
+                &ContentType::resolve(None, &book.content).code(),
+            ],
+        )
+        .await?;
+        tx.execute(
+            "UPDATE books SET title=$1, content=$2, images=$3 WHERE uid=$4",
+            &[&book.title, &book.content, &cover_images, &doc_id],
+        )
+        .await?;
+        record_revision(&tx, TagTable::Book, main_node.uid, user_id, None).await?;
+        indexed.push(IndexNode {
+            kind: DocKind::Book,
+            uid: main_node.uid,
+            doc_id,
+            user_id: main_node.user_id,
+            identity: Some(100),
+            title: &book.title,
+            content: &book.content,
+        });
+    }
+
+    let mut prev_chapter = main_node.uid;
+    for chapter in &plan.nodes {
+        let (chapter_uid, author) = place(&tx, doc_id, user_id, chapter, main_node.uid, prev_chapter, 101).await?;
+        indexed.extend(index_entry(chapter, doc_id, chapter_uid, author, 101));
+        let mut prev_section = chapter_uid;
+        for section in &chapter.children {
+            let (section_uid, author) = place(&tx, doc_id, user_id, section, chapter_uid, prev_section, 102).await?;
+            indexed.extend(index_entry(section, doc_id, section_uid, author, 102));
+            let mut prev_sub = section_uid;
+            for sub in &section.children {
+                let (sub_uid, author) = place(&tx, doc_id, user_id, sub, section_uid, prev_sub, 103).await?;
+                indexed.extend(index_entry(sub, doc_id, sub_uid, author, 103));
+                prev_sub = sub_uid;
+            }
+            prev_section = section_uid;
+        }
+        prev_chapter = chapter_uid;
+    }
+    // Images are named after their content, so storing them again is harmless
+    store_images(&pool, "book", &book.images, &used_images, doc_id, user_id).await?;
+    tx.commit().await?;
+
+    if !deleted.is_empty() {
+        pool.search().remove_nodes(DocKind::Book, &deleted);
+    }
+    pool.search().upsert(&indexed);
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(response(doc_id, false, plan.changes)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::upload::{ChapterData, SectionData, SubsectionData};
+    use chrono::Utc;
+
+    fn tree(uid: i32, parent_id: i32, identity: i16, title: &str, children: Vec<TreeNode>) -> TreeNode {
+        TreeNode {
+            uid,
+            parent_id: Some(parent_id),
+            title: title.into(),
+            content: Some(String::new()),
+            identity,
+            page_id: None,
+            images: None,
+            children,
+        }
+    }
+
+    fn chapter(title: &str, sections: Vec<(&str, Vec<&str>)>) -> ChapterData {
+        ChapterData {
+            title: title.into(),
+            sections: sections
+                .into_iter()
+                .enumerate()
+                .map(|(n, (title, subs))| {
+                    let section = SectionData {
+                        title: title.into(),
+                        subsections: subs
+                            .into_iter()
+                            .enumerate()
+                            .map(|(m, title)| ((m as u32, title.to_string()), SubsectionData { title: title.into(), ..Default::default() }))
+                            .collect(),
+                        ..Default::default()
+                    };
+                    ((n as u32, title.to_string()), section)
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_reupload() {
+        let main_node = BookParentNode {
+            uid: 1,
+            user_id: 7,
+            doc_id: 3,
+            title: "Guide".into(),
+            content: String::new(),
+            images: None,
+            created_at: Utc::now(),
+        };
+        // Intro (10) → Basics (11, with section Setup 20 holding Linux 30 → Mac 31) → Old (12)
+        let existing = vec![
+            tree(10, 1, 101, "Intro", vec![]),
+            tree(11, 10, 101, "Basics", vec![tree(20, 11, 102, "Setup", vec![
+                tree(30, 20, 103, "Linux", vec![]),
+                tree(31, 30, 103, "Mac", vec![]),
+            ])]),
+            tree(12, 11, 101, "Old", vec![]),
+        ];
+        // Basics moves first and is retitled by case only, Mac moves before
+        // Linux, Windows is new, Old is gone.
+        let mut book = BookStructure { title: "Guide".into(), ..Default::default() };
+        book.chapters.insert((1, "basics".into()), chapter("BASICS", vec![("Setup", vec!["Mac", "Linux", "Windows"])]));
+        book.chapters.insert((2, "intro".into()), chapter("Intro", vec![]));
+
+        let incoming = incoming(&book);
+        let plan = plan(&main_node, &existing, &incoming, &book);
+        let changes: Vec<(&str, Option<i32>)> = plan.changes.iter().map(|c| (c.action, c.uid)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("update", Some(11)),
+                ("move", Some(11)),
+                ("move", Some(31)),
+                ("move", Some(30)),
+                ("insert", None),
+                ("move", Some(10)),
+                ("delete", Some(12)),
+            ]
+        );
+        assert_eq!(plan.changes[4].path, "BASICS / Setup / Windows");
+        assert!(!plan.cover_changed);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::error::AppError;
+use crate::types::NavNodes;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio_postgres::GenericClient;
+
+/// A chapter, section or sub-section with its children in reading order.
+#[derive(Serialize, Debug)]
+pub struct TreeNode {
+    pub uid: i32,
+    pub parent_id: Option<i32>,
+    pub title: String,
+    pub content: Option<String>,
+    pub identity: i16,
+    pub page_id: Option<i32>,
+    pub images: Option<String>,
+    pub children: Vec<TreeNode>,
+}
+
+/// A broken `parent_id` / `page_id` link found while assembling the tree.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TreeWarning {
+    /// `orphan`, `fork` or `cycle`.
+    pub kind: &'static str,
+    pub uid: i32,
+    pub message: String,
+}
+
+/// Sub-sections (identity 103), which `fetch_book_pages!` leaves out.
+pub async fn fetch_sub_sections(conn: &impl GenericClient, doc_id: i32) -> Result<Vec<NavNodes>, AppError> {
+    let rows = conn
+        .query(
+            "SELECT uid, parent_id, title, content, images, identity, page_id FROM book \
+             WHERE doc_id=$1 AND identity=103 AND deleted_at IS NULL",
+            &[&doc_id],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| NavNodes {
+            uid: row.get(0),
+            parent_id: row.get(1),
+            title: row.get(2),
+            content: row.get(3),
+            images: row.get(4),
+            identity: row.get(5),
+            page_id: row.get(6),
+        })
+        .collect())
+}
+
+/// Assemble chapter → section → sub-section under the front page `main_uid`.
+///
+/// Siblings are ordered by walking `parent_id` from the page. Where the chain
+/// is broken the tree is still returned: at a fork the lowest uid wins,
+/// unreachable siblings (orphans, detached cycles) are appended to their page
+/// in uid order, and nodes whose page is missing are left out. Every such
+/// case is reported in the warnings.
+pub fn build_tree(main_uid: i32, nodes: Vec<NavNodes>) -> (Vec<TreeNode>, Vec<TreeWarning>) {
+    let mut by_page: BTreeMap<i32, Vec<NavNodes>> = BTreeMap::new();
+    let mut warnings = Vec::new();
+    for node in nodes {
+        match node.page_id {
+            Some(page_id) => by_page.entry(page_id).or_default().push(node),
+            None => warnings.push(TreeWarning {
+                kind: "orphan",
+                uid: node.uid,
+                message: format!("Node {} has no page", node.uid),
+            }),
+        }
+    }
+    let chapters = children_of(main_uid, 100, &mut by_page, &mut warnings);
+    for (page_id, nodes) in by_page {
+        for node in nodes {
+            warnings.push(TreeWarning {
+                kind: "orphan",
+                uid: node.uid,
+                message: format!("Page {page_id} of node {} is missing or cannot hold identity {}", node.uid, node.identity),
+            });
+        }
+    }
+    (chapters, warnings)
+}
+
+fn children_of(
+    page_uid: i32,
+    page_identity: i16,
+    by_page: &mut BTreeMap<i32, Vec<NavNodes>>,
+    warnings: &mut Vec<TreeWarning>,
+) -> Vec<TreeNode> {
+    let Some(mut siblings) = by_page.remove(&page_uid) else {
+        return Vec::new();
+    };
+    // Nodes of the wrong level stay behind and are reported as orphans.
+    let (wrong, valid): (Vec<NavNodes>, Vec<NavNodes>) = siblings
+        .drain(..)
+        .partition(|node| node.identity != page_identity + 1);
+    if !wrong.is_empty() {
+        by_page.insert(page_uid, wrong);
+    }
+
+    let mut next_of: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for node in valid.iter() {
+        if let Some(parent_id) = node.parent_id {
+            next_of.entry(parent_id).or_default().push(node.uid);
+        }
+    }
+    for (parent_id, next) in next_of.iter_mut() {
+        next.sort_unstable();
+        if next.len() > 1 {
+            warnings.push(TreeWarning {
+                kind: "fork",
+                uid: *parent_id,
+                message: format!("Nodes {next:?} all follow {parent_id}; {} was kept in place", next[0]),
+            });
+        }
+    }
+
+    let mut order: Vec<i32> = Vec::new();
+    let mut seen: HashSet<i32> = HashSet::new();
+    let mut current = page_uid;
+    while let Some(&next) = next_of.get(&current).and_then(|next| next.first()) {
+        if !seen.insert(next) {
+            break;
+        }
+        order.push(next);
+        current = next;
+    }
+
+    let forked: HashSet<i32> = next_of.values().flat_map(|next| next.iter().skip(1).copied()).collect();
+    let parents: HashMap<i32, Option<i32>> = valid.iter().map(|node| (node.uid, node.parent_id)).collect();
+    let mut stray: Vec<i32> = valid.iter().map(|node| node.uid).filter(|uid| !seen.contains(uid)).collect();
+    stray.sort_unstable();
+    let mut in_cycle: HashSet<i32> = HashSet::new();
+    for &uid in stray.iter() {
+        if in_cycle.contains(&uid) {
+            continue;
+        }
+        // Walk back through unplaced siblings; coming back to `uid` means a loop.
+        let mut path = vec![uid];
+        let mut at = parents.get(&uid).copied().flatten();
+        while let Some(parent) = at {
+            if parent == uid {
+                in_cycle.extend(path.iter().copied());
+                warnings.push(TreeWarning {
+                    kind: "cycle",
+                    uid,
+                    message: format!("Nodes {path:?} point at each other and never reach page {page_uid}"),
+                });
+                break;
+            }
+            if seen.contains(&parent) || path.contains(&parent) || !parents.contains_key(&parent) {
+                break;
+            }
+            path.push(parent);
+            at = parents.get(&parent).copied().flatten();
+        }
+    }
+    for &uid in stray.iter() {
+        if !in_cycle.contains(&uid) && !forked.contains(&uid) {
+            warnings.push(TreeWarning {
+                kind: "orphan",
+                uid,
+                message: format!("Node {uid} cannot be reached from page {page_uid}"),
+            });
+        }
+        order.push(uid);
+    }
+
+    let mut valid: HashMap<i32, NavNodes> = valid.into_iter().map(|node| (node.uid, node)).collect();
+    order
+        .into_iter()
+        .filter_map(|uid| valid.remove(&uid))
+        .map(|node| {
+            let children = children_of(node.uid, node.identity, by_page, warnings);
+            TreeNode {
+                uid: node.uid,
+                parent_id: node.parent_id,
+                title: node.title,
+                content: node.content,
+                identity: node.identity,
+                page_id: node.page_id,
+                images: node.images,
+                children,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nav(uid: i32, parent_id: i32, identity: i16, page_id: i32) -> NavNodes {
+        NavNodes {
+            uid,
+            parent_id: Some(parent_id),
+            title: format!("node {uid}"),
+            content: None,
+            identity,
+            page_id: Some(page_id),
+            images: None,
+        }
+    }
+
+    fn uids(nodes: &[TreeNode]) -> Vec<i32> {
+        nodes.iter().map(|node| node.uid).collect()
+    }
+
+    #[test]
+    fn test_build_tree() {
+        // Chapters 12 → 11, sections of 11: 21 → 20, sub-section 30 under 20.
+        let nodes = vec![
+            nav(11, 12, 101, 1),
+            nav(12, 1, 101, 1),
+            nav(20, 21, 102, 11),
+            nav(21, 11, 102, 11),
+            nav(30, 20, 103, 20),
+        ];
+        let (tree, warnings) = build_tree(1, nodes);
+        assert!(warnings.is_empty());
+        assert_eq!(uids(&tree), vec![12, 11]);
+        assert_eq!(uids(&tree[1].children), vec![21, 20]);
+        assert_eq!(uids(&tree[1].children[1].children), vec![30]);
+
+        let nodes = vec![
+            nav(11, 1, 101, 1),
+            nav(12, 1, 101, 1),  // fork after the front page
+            nav(13, 14, 101, 1), // 13 ↔ 14 loop
+            nav(14, 13, 101, 1),
+            nav(15, 99, 101, 1), // predecessor missing
+            nav(40, 11, 103, 11), // sub-section directly under a chapter
+        ];
+        let (tree, warnings) = build_tree(1, nodes);
+        assert_eq!(uids(&tree), vec![11, 12, 13, 14, 15]);
+        let kinds: Vec<(&str, i32)> = warnings.iter().map(|w| (w.kind, w.uid)).collect();
+        assert_eq!(kinds, vec![("fork", 1), ("cycle", 13), ("orphan", 15), ("orphan", 40)]);
+    }
+}
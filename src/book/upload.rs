@@ -1,4 +1,11 @@
 use crate::error::AppError;
+use crate::file::{image_variants, is_valid_image, mime_for_extension, ALLOWED_EXTENSIONS};
+use crate::lifecycle::DocStatus;
+use crate::search::index::{DocKind, IndexNode};
+use crate::traits::Images;
+use crate::utils::content::ContentType;
+use crate::utils::doc::{insert_tags, normalize_tags, TagTable};
+use crate::utils::front_matter::{self, FrontMatter};
 use crate::AppState;
 use axum::{
     extract::{Multipart, State},
@@ -6,8 +13,11 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use image::ImageFormat;
+use regex::Regex;
+use serde::Serialize;
 use serde_json::json;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::{Cursor, Read};
 use zip::ZipArchive;
 
@@ -15,30 +25,84 @@ use zip::ZipArchive;
 // In-memory representation of the parsed ZIP structure
 // ---------------------------------------------------------------------------
 
+/// Sort key of a chapter, section or sub-section: its `order` (front matter,
+/// else the `NN-` prefix) and then its file or folder name.
+pub(super) type OrderKey = (u32, String);
+
+#[derive(Default)]
+pub(super) struct BookStructure {
+    pub(super) title: String,
+    pub(super) content: String,
+    /// `draft: true` in the cover's front matter.
+    pub(super) draft: bool,
+    /// Normalised tags from every imported file's front matter.
+    pub(super) tags: Vec<String>,
+    /// Image names referenced by the cover, filled by `link_images`.
+    pub(super) cover_images: Vec<String>,
+    pub(super) chapters: BTreeMap<OrderKey, ChapterData>,
+    /// Every image in the ZIP, keyed by its path below the root folder.
+    pub(super) images: BTreeMap<String, ZipImage>,
+}
+
+// `dir` is the ZIP folder of the node's markdown file, which relative image
+// links are resolved against; `images` is filled by `link_images`; `slug` is
+// the front matter `slug`, used to match nodes on re-upload.
+
 #[derive(Default)]
-struct BookStructure {
-    title: String,
-    content: String,
-    chapters: BTreeMap<u32, ChapterData>,
+pub(super) struct ChapterData {
+    pub(super) title: String,
+    pub(super) content: String,
+    pub(super) dir: String,
+    pub(super) images: Vec<String>,
+    pub(super) slug: Option<String>,
+    pub(super) sections: BTreeMap<OrderKey, SectionData>,
 }
 
 #[derive(Default)]
-struct ChapterData {
-    title: String,
-    content: String,
-    sections: BTreeMap<u32, SectionData>,
+pub(super) struct SectionData {
+    pub(super) title: String,
+    pub(super) content: String,
+    pub(super) dir: String,
+    pub(super) images: Vec<String>,
+    pub(super) slug: Option<String>,
+    pub(super) subsections: BTreeMap<OrderKey, SubsectionData>,
 }
 
 #[derive(Default)]
-struct SectionData {
-    title: String,
-    content: String,
-    subsections: BTreeMap<u32, SubsectionData>,
+pub(super) struct SubsectionData {
+    pub(super) title: String,
+    pub(super) content: String,
+    pub(super) dir: String,
+    pub(super) images: Vec<String>,
+    pub(super) slug: Option<String>,
 }
 
-struct SubsectionData {
-    title: String,
-    content: String,
+/// An image file from the ZIP. `name` is derived from the bytes, so the same
+/// file gets the same name on every upload.
+pub(crate) struct ZipImage {
+    pub(super) path: String,
+    pub(super) name: String,
+    pub(super) extension: String,
+    pub(super) bytes: Vec<u8>,
+}
+
+impl ZipImage {
+    pub(crate) fn new(path: String, extension: String, bytes: Vec<u8>) -> Self {
+        let name = format!("{:016x}.{extension}", fnv1a(&bytes));
+        ZipImage { path, name, extension, bytes }
+    }
+}
+
+impl BookStructure {
+    /// Nodes `insert_book` writes: the front page, chapters, sections and
+    /// sub-sections.
+    pub(super) fn node_count(&self) -> usize {
+        1 + self
+            .chapters
+            .values()
+            .map(|chapter| 1 + chapter.sections.values().map(|section| 1 + section.subsections.len()).sum::<usize>())
+            .sum::<usize>()
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -64,7 +128,13 @@ struct SubsectionData {
 ///
 /// Files and folders are ordered by a numeric prefix (`NN-`). Folders
 /// without a prefix are sorted after numbered entries (order = 999).
+/// YAML front matter (`title`, `order`, `tags`, `draft`, `slug`) overrides
+/// the derived title and order; see `utils::front_matter`.
 /// The root folder name (if the ZIP has one) is stripped automatically.
+///
+/// The ZIP is only read here; parsing and inserting run as an import job on
+/// the background workers (see `book::import`). Responds `202` with the job
+/// id to poll at `GET /book/import/:job_id`.
 pub async fn upload_book(
     axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<
         crate::utils::UserId,
@@ -72,57 +142,80 @@ pub async fn upload_book(
     State(pool): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, AppError> {
-    // --- 1. Read ZIP bytes from the first multipart field ---
-    let mut zip_bytes: Option<Vec<u8>> = None;
-    while let Some(field) = multipart.next_field().await? {
-        zip_bytes = Some(field.bytes().await?.to_vec());
-        break;
-    }
-    let zip_bytes =
-        zip_bytes.ok_or_else(|| AppError::BadRequest("No file field in multipart body".into()))?;
+    let zip_bytes = read_zip_field(&mut multipart).await?;
+    let job = pool.imports().enqueue(user_id, zip_bytes)?;
 
-    // --- 2. Parse ZIP into the in-memory BookStructure ---
-    let book = parse_zip(&zip_bytes)?;
-
-    if book.title.is_empty() {
-        return Err(AppError::BadRequest(
-            "ZIP must contain a cover.md / index.md / README.md at the root level".into(),
-        ));
-    }
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!(job)),
+    ))
+}
 
-    // --- 3. Bulk-insert in a single transaction ---
+/// Insert a parsed book in a single transaction, calling `progress` with the
+/// number of nodes written so far after each one.
+pub(super) async fn insert_book(
+    pool: &AppState,
+    user_id: i32,
+    mut book: BookStructure,
+    progress: &(dyn Fn(usize) + Send + Sync),
+) -> Result<serde_json::Value, AppError> {
     let mut conn = pool.pg_pool.conn.get().await?;
-    let empty_images = "[]";
 
     let tx = conn.transaction().await?;
 
-    // Insert the `books` summary row
+    // Insert the `books` summary row; content and images follow once image
+    // links can point at the new doc_id
+    let status = if book.draft { DocStatus::Draft } else { DocStatus::Published };
     let row = tx
         .query_one(
-            "INSERT INTO books(user_id, title, content, images) \
-             VALUES($1, $2, $3, $4) RETURNING uid",
-            &[&user_id, &book.title, &book.content, &empty_images],
+            "INSERT INTO books(user_id, title, content, images, status) \
+             VALUES($1, $2, '', '[]', $3) RETURNING uid",
+            &[&user_id, &book.title, &status.as_str()],
         )
         .await?;
     let doc_id: i32 = row.get(0);
+    insert_tags(&tx, TagTable::Book, doc_id, user_id, &book.tags).await?;
+
+    let used_images = book.link_images(doc_id);
+    let cover_images = images_json(&book.cover_images);
+    tx.execute(
+        "UPDATE books SET content=$1, images=$2 WHERE uid=$3",
+        &[&book.content, &cover_images, &doc_id],
+    )
+    .await?;
 
     // Insert the front-page node (identity = 100)
     let identity_100: i16 = 100;
     let row = tx
         .query_one(
-            "INSERT INTO book(user_id, doc_id, title, content, identity, images) \
-             VALUES($1, $2, $3, $4, $5, $6) RETURNING uid",
+            "INSERT INTO book(user_id, doc_id, title, content, identity, images, content_type) \
+             VALUES($1, $2, $3, $4, $5, $6, $7) RETURNING uid",
             &[
                 &user_id,
                 &doc_id,
                 &book.title,
                 &book.content,
                 &identity_100,
-                &empty_images,
+                &cover_images,
+                &ContentType::resolve(None, &book.content).code(),
             ],
         )
         .await?;
     let main_uid: i32 = row.get(0);
+    let mut inserted = 1;
+    progress(inserted);
+
+    // Every inserted node, fed to the search index once the transaction commits
+    let mut indexed: Vec<IndexNode> = vec![IndexNode {
+        kind: DocKind::Book,
+        uid: main_uid,
+        doc_id,
+        user_id,
+        identity: Some(identity_100),
+        title: &book.title,
+        content: &book.content,
+    }];
 
     let identity_101: i16 = 101;
     let identity_102: i16 = 102;
@@ -131,8 +224,8 @@ pub async fn upload_book(
     // Prepared statement reused for all child nodes
     let insert_node = tx
         .prepare(
-            "INSERT INTO book(user_id, doc_id, page_id, parent_id, title, content, identity, images) \
-             VALUES($1, $2, $3, $4, $5, $6, $7, $8) RETURNING uid",
+            "INSERT INTO book(user_id, doc_id, page_id, parent_id, title, content, identity, images, content_type) \
+             VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING uid",
         )
         .await?;
 
@@ -151,11 +244,23 @@ pub async fn upload_book(
                     &chapter.title,
                     &chapter.content,
                     &identity_101,
-                    &empty_images,
+                    &images_json(&chapter.images),
+                    &ContentType::resolve(None, &chapter.content).code(),
                 ],
             )
             .await?;
         let chapter_uid: i32 = row.get(0);
+        inserted += 1;
+        progress(inserted);
+        indexed.push(IndexNode {
+            kind: DocKind::Book,
+            uid: chapter_uid,
+            doc_id,
+            user_id,
+            identity: Some(identity_101),
+            title: &chapter.title,
+            content: &chapter.content,
+        });
 
         let mut prev_section_uid = chapter_uid;
 
@@ -172,11 +277,23 @@ pub async fn upload_book(
                         &section.title,
                         &section.content,
                         &identity_102,
-                        &empty_images,
+                        &images_json(&section.images),
+                        &ContentType::resolve(None, &section.content).code(),
                     ],
                 )
                 .await?;
             let section_uid: i32 = row.get(0);
+            inserted += 1;
+            progress(inserted);
+            indexed.push(IndexNode {
+                kind: DocKind::Book,
+                uid: section_uid,
+                doc_id,
+                user_id,
+                identity: Some(identity_102),
+                title: &section.title,
+                content: &section.content,
+            });
 
             let mut prev_sub_uid = section_uid;
 
@@ -193,11 +310,23 @@ pub async fn upload_book(
                             &sub.title,
                             &sub.content,
                             &identity_103,
-                            &empty_images,
+                            &images_json(&sub.images),
+                            &ContentType::resolve(None, &sub.content).code(),
                         ],
                     )
                     .await?;
                 prev_sub_uid = row.get(0);
+                inserted += 1;
+                progress(inserted);
+                indexed.push(IndexNode {
+                    kind: DocKind::Book,
+                    uid: prev_sub_uid,
+                    doc_id,
+                    user_id,
+                    identity: Some(identity_103),
+                    title: &sub.title,
+                    content: &sub.content,
+                });
             }
 
             prev_section_uid = section_uid;
@@ -206,136 +335,264 @@ pub async fn upload_book(
         prev_chapter_uid = chapter_uid;
     }
 
+    // Store images before committing so a bad image leaves no book behind
+    store_images(pool, "book", &book.images, &used_images, doc_id, user_id).await?;
+
     tx.commit().await?;
 
-    Ok((
-        StatusCode::OK,
-        [(header::CONTENT_TYPE, "application/json")],
-        Json(json!({
-            "doc_id": doc_id,
-            "title": book.title,
-            "chapters": book.chapters.len(),
-        })),
-    ))
+    pool.search().upsert(&indexed);
+
+    Ok(json!({
+        "doc_id": doc_id,
+        "title": book.title,
+        "chapters": book.chapters.len(),
+        "images": used_images.len(),
+    }))
 }
 
 // ---------------------------------------------------------------------------
 // ZIP parsing
 // ---------------------------------------------------------------------------
 
-fn parse_zip(bytes: &[u8]) -> Result<BookStructure, AppError> {
+/// A problem with the uploaded ZIP; `file` is set when one entry is to blame.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct FileError {
+    pub(super) file: Option<String>,
+    pub(super) message: String,
+}
+
+impl FileError {
+    pub(super) fn archive(message: impl Into<String>) -> Self {
+        FileError { file: None, message: message.into() }
+    }
+
+    fn file(file: &str, message: impl Into<String>) -> Self {
+        FileError { file: Some(file.to_string()), message: message.into() }
+    }
+}
+
+/// One `BadRequest` listing every problem.
+pub(super) fn file_errors(errors: Vec<FileError>) -> AppError {
+    let messages: Vec<String> = errors.into_iter().map(|error| error.message).collect();
+    AppError::BadRequest(messages.join("; "))
+}
+
+/// The bytes of the first multipart field.
+pub(super) async fn read_zip_field(multipart: &mut Multipart) -> Result<Vec<u8>, AppError> {
+    let field = multipart
+        .next_field()
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No file field in multipart body".into()))?;
+    Ok(field.bytes().await?.to_vec())
+}
+
+/// Read the ZIP from the first multipart field and parse it.
+pub(super) async fn read_book_zip(multipart: &mut Multipart) -> Result<BookStructure, AppError> {
+    let zip_bytes = read_zip_field(multipart).await?;
+    parse_book(&zip_bytes).map_err(file_errors)
+}
+
+/// `parse_zip`, and insist on a book title.
+pub(super) fn parse_book(bytes: &[u8]) -> Result<BookStructure, Vec<FileError>> {
+    let book = parse_zip(bytes)?;
+    if book.title.is_empty() {
+        return Err(vec![FileError::archive(
+            "ZIP must contain a cover.md / index.md / README.md at the root level",
+        )]);
+    }
+    Ok(book)
+}
+
+/// Parse the archive, reporting every unreadable, invalid or malformed file
+/// rather than stopping at the first.
+pub(super) fn parse_zip(bytes: &[u8]) -> Result<BookStructure, Vec<FileError>> {
     let cursor = Cursor::new(bytes);
     let mut archive = ZipArchive::new(cursor)
-        .map_err(|e| AppError::BadRequest(format!("Invalid ZIP file: {e}")))?;
+        .map_err(|e| vec![FileError::archive(format!("Invalid ZIP file: {e}"))])?;
+    let mut errors: Vec<FileError> = Vec::new();
 
-    // Collect (path_parts, file_content) for every .md file
+    // Collect (path_parts, file_content) for every .md file, and every image
     let mut files: Vec<(Vec<String>, String)> = Vec::new();
+    let mut images: Vec<(Vec<String>, String, Vec<u8>)> = Vec::new();
 
     for i in 0..archive.len() {
-        let mut entry = archive
-            .by_index(i)
-            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push(FileError::archive(format!("Cannot read ZIP entry {i}: {e}")));
+                continue;
+            }
+        };
 
         if entry.is_dir() {
             continue;
         }
 
         let raw_name = entry.name().to_string();
-        if !raw_name.ends_with(".md") {
-            continue;
-        }
-
-        let mut content = String::new();
-        entry
-            .read_to_string(&mut content)
-            .map_err(|e| AppError::BadRequest(format!("Cannot read {raw_name}: {e}")))?;
-
         let parts: Vec<String> = raw_name
             .split('/')
             .filter(|s| !s.is_empty())
             .map(String::from)
             .collect();
 
+        if let Some(extension) = image_extension(&raw_name) {
+            let mut bytes = Vec::new();
+            if let Err(e) = entry.read_to_end(&mut bytes) {
+                errors.push(FileError::file(&raw_name, format!("Cannot read {raw_name}: {e}")));
+            } else if !is_valid_image(&bytes) {
+                errors.push(FileError::file(&raw_name, format!("{raw_name} is not a recognised image")));
+            } else {
+                images.push((parts, extension, bytes));
+            }
+            continue;
+        }
+
+        if !raw_name.ends_with(".md") {
+            continue;
+        }
+
+        let mut content = String::new();
+        if let Err(e) = entry.read_to_string(&mut content) {
+            errors.push(FileError::file(&raw_name, format!("Cannot read {raw_name}: {e}")));
+            continue;
+        }
+
         files.push((parts, content));
     }
 
-    if files.is_empty() {
-        return Err(AppError::BadRequest(
-            "ZIP contains no .md files".into(),
-        ));
+    if files.is_empty() && errors.is_empty() {
+        errors.push(FileError::archive("ZIP contains no .md files"));
     }
 
     // Strip common root folder (many ZIP tools wrap everything in one folder)
-    let strip = has_single_root(&files);
+    let paths: Vec<&[String]> = files
+        .iter()
+        .map(|(parts, _)| parts.as_slice())
+        .chain(images.iter().map(|(parts, _, _)| parts.as_slice()))
+        .collect();
+    let start = if has_single_root(&paths) { 1 } else { 0 };
     let files: Vec<(Vec<String>, String)> = files
         .into_iter()
-        .map(|(parts, content)| {
-            let start = if strip { 1 } else { 0 };
-            (parts[start..].to_vec(), content)
-        })
+        .map(|(parts, content)| (parts[start..].to_vec(), content))
         .collect();
 
     let mut book = BookStructure::default();
+    for (parts, extension, bytes) in images {
+        let path = parts[start..].join("/");
+        book.images.insert(path.clone(), ZipImage::new(path, extension, bytes));
+    }
 
-    for (parts, content) in &files {
+    // Split off front matter; a folder's `order` and `draft` come from its index file
+    let mut parsed: Vec<(Vec<String>, FrontMatter, String)> = Vec::new();
+    for (parts, content) in files {
+        let file = parts.join("/");
+        match front_matter::split(&file, &content) {
+            Ok((front, body)) => {
+                let body = body.to_string();
+                // Whatever node this file becomes stores `split_title_body`'s body.
+                if let Err(message) = check_content(&file, &split_title_body(&body, "").1) {
+                    errors.push(FileError::file(&file, message));
+                }
+                parsed.push((parts, front, body));
+            }
+            Err(e) => errors.push(FileError::file(&file, error_message(e))),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let folders: HashMap<&[String], &FrontMatter> = parsed
+        .iter()
+        .filter(|(parts, _, _)| parts.len() > 1 && parts.last().is_some_and(|name| is_index(name)))
+        .map(|(parts, front, _)| (&parts[..parts.len() - 1], front))
+        .collect();
+    let folder_key = |folder: &[String]| -> OrderKey {
+        let name = &folder[folder.len() - 1];
+        let order = folders.get(folder).and_then(|front| front.order);
+        (order.unwrap_or_else(|| order_and_title(name).0), name.clone())
+    };
+    let in_draft = |parts: &[String]| {
+        (1..parts.len()).any(|n| folders.get(&parts[..n]).is_some_and(|front| front.draft))
+    };
+
+    for (parts, front, content) in &parsed {
+        if in_draft(parts) || (front.draft && parts.len() > 1) {
+            continue;
+        }
         match parts.as_slice() {
             // ── Root-level .md ──────────────────────────────────────────────
             [filename] => {
                 let lower = filename.to_lowercase();
                 if matches!(lower.as_str(), "cover.md" | "index.md" | "readme.md") {
                     let (title, body) = split_title_body(content, &title_from_name(filename));
-                    book.title = title;
+                    book.title = front.title.clone().unwrap_or(title);
                     book.content = body;
+                    book.draft = front.draft;
+                    book.tags.extend(front.tags.iter().cloned());
                 }
                 // introduction.md and other root files are silently ignored
             }
 
             // ── Chapter-level file (e.g. 01-intro/_index.md) ───────────────
             [chapter_dir, filename] => {
-                let (ch_order, ch_title) = order_and_title(chapter_dir);
-                let chapter = book.chapters.entry(ch_order).or_insert_with(|| ChapterData {
+                let ch_title = order_and_title(chapter_dir).1;
+                let chapter = book.chapters.entry(folder_key(&parts[..1])).or_insert_with(|| ChapterData {
                     title: ch_title.clone(),
+                    dir: chapter_dir.clone(),
                     ..Default::default()
                 });
                 if is_index(filename) {
-                    let (_, body) = split_title_body(content, &ch_title);
+                    let (title, body) = split_title_body(content, &ch_title);
+                    chapter.title = front.title.clone().unwrap_or(title);
                     chapter.content = body;
+                    chapter.slug = front.slug.clone();
+                    book.tags.extend(front.tags.iter().cloned());
                 }
                 // Non-index files at chapter level are ignored
             }
 
             // ── Section file (e.g. 01-intro/01-basics/01-hello.md) ─────────
             [chapter_dir, section_dir, filename] => {
-                let (ch_order, ch_title) = order_and_title(chapter_dir);
-                let (sec_order, sec_title) = order_and_title(section_dir);
+                let ch_title = order_and_title(chapter_dir).1;
+                let sec_title = order_and_title(section_dir).1;
 
-                let chapter = book.chapters.entry(ch_order).or_insert_with(|| ChapterData {
+                let chapter = book.chapters.entry(folder_key(&parts[..1])).or_insert_with(|| ChapterData {
                     title: ch_title,
+                    dir: chapter_dir.clone(),
                     ..Default::default()
                 });
 
-                let section = chapter.sections.entry(sec_order).or_insert_with(|| SectionData {
+                let section = chapter.sections.entry(folder_key(&parts[..2])).or_insert_with(|| SectionData {
                     title: sec_title.clone(),
+                    dir: format!("{chapter_dir}/{section_dir}"),
                     ..Default::default()
                 });
 
                 if is_index(filename) {
-                    let (_, body) = split_title_body(content, &sec_title);
+                    let (title, body) = split_title_body(content, &sec_title);
+                    section.title = front.title.clone().unwrap_or(title);
                     section.content = body;
+                    section.slug = front.slug.clone();
                 } else {
                     let (sub_order, sub_title) = order_and_title(filename);
-                    let (_, body) = split_title_body(content, &sub_title);
-                    section.subsections.entry(sub_order).or_insert(SubsectionData {
-                        title: sub_title,
+                    let (title, body) = split_title_body(content, &sub_title);
+                    let key = (front.order.unwrap_or(sub_order), filename.clone());
+                    section.subsections.entry(key).or_insert(SubsectionData {
+                        title: front.title.clone().unwrap_or(title),
                         content: body,
+                        dir: section.dir.clone(),
+                        slug: front.slug.clone(),
+                        ..Default::default()
                     });
                 }
+                book.tags.extend(front.tags.iter().cloned());
             }
 
             // Deeper nesting is ignored
             _ => {}
         }
     }
+    book.tags = normalize_tags(&book.tags);
 
     // Fallback title if no cover file was present
     if book.title.is_empty() {
@@ -351,30 +608,169 @@ fn parse_zip(bytes: &[u8]) -> Result<BookStructure, AppError> {
     Ok(book)
 }
 
+// ---------------------------------------------------------------------------
+// Images
+// ---------------------------------------------------------------------------
+
+impl BookStructure {
+    /// Point every relative markdown image link that resolves to an image in
+    /// the ZIP at its stored copy under book `doc_id`, and record the names in
+    /// each node's `images`. Other links are left untouched. Returns the
+    /// names of the images that are referenced at least once.
+    pub(super) fn link_images(&mut self, doc_id: i32) -> BTreeSet<String> {
+        let images = &self.images;
+        let url_base = format!("/file/book/{doc_id}");
+        let mut used: BTreeSet<String> = BTreeSet::new();
+        let mut link = |content: &mut String, dir: &str, names: &mut Vec<String>| {
+            link_images_in(content, dir, images, &url_base, names);
+            used.extend(names.iter().cloned());
+        };
+        link(&mut self.content, "", &mut self.cover_images);
+        for chapter in self.chapters.values_mut() {
+            link(&mut chapter.content, &chapter.dir, &mut chapter.images);
+            for section in chapter.sections.values_mut() {
+                link(&mut section.content, &section.dir, &mut section.images);
+                for sub in section.subsections.values_mut() {
+                    link(&mut sub.content, &sub.dir, &mut sub.images);
+                }
+            }
+        }
+        used
+    }
+}
+
+lazy_static::lazy_static! {
+    /// `![alt](target "title")`; the title is kept as-is.
+    pub(crate) static ref IMAGE_LINK: Regex = Regex::new(r#"!\[([^\]]*)\]\(\s*([^)\s]+)(\s+"[^"]*")?\s*\)"#).unwrap();
+}
+
+/// Point every relative markdown image link in `content` that resolves to one
+/// of `images` at `{url_base}/720/{name}`, adding the names to `names` in
+/// link order. Other links are left untouched.
+pub(crate) fn link_images_in(
+    content: &mut String,
+    dir: &str,
+    images: &BTreeMap<String, ZipImage>,
+    url_base: &str,
+    names: &mut Vec<String>,
+) {
+    *content = IMAGE_LINK
+        .replace_all(content, |caps: &regex::Captures| {
+            let found = resolve_link(dir, &caps[2]).and_then(|path| images.get(&path));
+            let Some(image) = found else {
+                return caps[0].to_string();
+            };
+            if !names.contains(&image.name) {
+                names.push(image.name.clone());
+            }
+            let title = caps.get(3).map_or("", |m| m.as_str());
+            format!("![{}]({url_base}/720/{}{title})", &caps[1], image.name)
+        })
+        .into_owned();
+}
+
+/// Resolve `target` relative to the ZIP folder `dir`. Absolute paths, URLs
+/// and links climbing out of the ZIP resolve to nothing.
+fn resolve_link(dir: &str, target: &str) -> Option<String> {
+    if target.starts_with('/') || target.contains(':') {
+        return None;
+    }
+    let mut parts: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/").replace("%20", " "))
+}
+
+/// The `images` column value for a list of image names.
+pub(crate) fn images_json(names: &[String]) -> String {
+    let images: Vec<Images> = names.iter().map(|name| Images { name: name.clone() }).collect();
+    serde_json::to_string(&images).unwrap_or_else(|_| "[]".into())
+}
+
+/// Resize the named images into the `IMAGE_SIZES` variants and store them in
+/// `bucket` as `{doc_id}/{size}-{name}`, like `upload_file` does for a single
+/// image. An image that cannot be decoded is a `400` naming the file.
+pub(crate) async fn store_images(
+    pool: &AppState,
+    bucket: &str,
+    images: &BTreeMap<String, ZipImage>,
+    names: &BTreeSet<String>,
+    doc_id: i32,
+    user_id: i32,
+) -> Result<(), AppError> {
+    let owner = user_id.to_string();
+    let mut stored: BTreeSet<&str> = BTreeSet::new();
+    for image in images.values() {
+        if !names.contains(&image.name) || !stored.insert(&image.name) {
+            continue;
+        }
+        let format = ImageFormat::from_extension(&image.extension)
+            .ok_or_else(|| AppError::BadRequest(format!("{} has an unknown image format", image.path)))?;
+        let decoded = image::load(Cursor::new(&image.bytes), format)
+            .map_err(|e| AppError::BadRequest(format!("Cannot decode {}: {e}", image.path)))?;
+        let mime = mime_for_extension(&image.extension);
+        for (size, bytes) in image_variants(&decoded, format)? {
+            let key = format!("{doc_id}/{size}-{}", image.name);
+            pool.s3().put(bucket, &key, bytes, mime, &owner).await?;
+        }
+    }
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
 
 /// Returns true if every file path shares the same top-level component,
 /// meaning the ZIP was created with a root folder wrapper.
-fn has_single_root(files: &[(Vec<String>, String)]) -> bool {
-    if files.is_empty() {
+pub(crate) fn has_single_root(paths: &[&[String]]) -> bool {
+    if paths.is_empty() {
         return false;
     }
-    let first = match files[0].0.first() {
+    let first = match paths[0].first() {
         Some(f) => f,
         None => return false,
     };
-    files.iter().all(|(parts, _)| parts.first() == Some(first) && parts.len() > 1)
+    paths.iter().all(|parts| parts.first() == Some(first) && parts.len() > 1)
+}
+
+/// Lower-cased extension of an image file we accept, `None` for anything else.
+pub(crate) fn image_extension(name: &str) -> Option<String> {
+    let (_, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_lowercase();
+    ALLOWED_EXTENSIONS.contains(&extension.as_str()).then_some(extension)
+}
+
+/// The client-facing text of an error.
+pub(super) fn error_message(error: AppError) -> String {
+    match error {
+        AppError::NotFound(message) | AppError::BadRequest(message) | AppError::Error((_, message)) => message,
+        AppError::InternalServerError(e) => {
+            tracing::error!(error = %e, "internal server error");
+            "Internal server error".into()
+        }
+    }
+}
+
+/// 64-bit FNV-1a, used to name images after their content.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
 }
 
 /// Parse a numeric prefix (`NN-` or `NN_`) from a file/folder name and
 /// return `(order, human_title)`. Names without a prefix get order 999.
-fn order_and_title(raw: &str) -> (u32, String) {
+pub(crate) fn order_and_title(raw: &str) -> (u32, String) {
     // Strip .md extension if present
     let name = raw.strip_suffix(".md").unwrap_or(raw);
 
-    if let Some(sep) = name.find(|c: char| c == '-' || c == '_') {
+    if let Some(sep) = name.find(['-', '_']) {
         let prefix = &name[..sep];
         if let Ok(order) = prefix.parse::<u32>() {
             let rest = &name[sep + 1..];
@@ -386,7 +782,7 @@ fn order_and_title(raw: &str) -> (u32, String) {
 
 /// Convert a slug like `getting-started` to `Getting Started`.
 fn to_title_case(s: &str) -> String {
-    s.split(|c: char| c == '-' || c == '_')
+    s.split(['-', '_'])
         .filter(|w| !w.is_empty())
         .map(|word| {
             let mut chars = word.chars();
@@ -400,7 +796,7 @@ fn to_title_case(s: &str) -> String {
 }
 
 /// Derive a title from a raw filename (no extension, title-cased).
-fn title_from_name(filename: &str) -> String {
+pub(crate) fn title_from_name(filename: &str) -> String {
     let name = filename.strip_suffix(".md").unwrap_or(filename);
     to_title_case(name)
 }
@@ -413,16 +809,136 @@ fn is_index(filename: &str) -> bool {
     )
 }
 
+/// Resolve and check a node's content the way `create` and `edit` do; the
+/// error names `file`.
+pub(crate) fn check_content(file: &str, content: &str) -> Result<(), String> {
+    ContentType::resolve(None, content)
+        .validate(content)
+        .map_err(|e| format!("{file}: {}", error_message(e)))
+}
+
 /// If the content starts with a Markdown H1 (`# Title`), extract it as the
 /// node title and return the remaining body. Otherwise keep `default_title`.
-/// The body is prefixed with `<basic> ` to signal the basic markdown renderer.
-fn split_title_body(content: &str, default_title: &str) -> (String, String) {
+/// The body is prefixed with `<basic> ` to signal the basic markdown renderer;
+/// an empty body stays empty, like a chapter without `_index.md`.
+pub(crate) fn split_title_body(content: &str, default_title: &str) -> (String, String) {
     let trimmed = content.trim();
-    if let Some(rest) = trimmed.strip_prefix("# ") {
-        let nl = rest.find('\n').unwrap_or(rest.len());
-        let title = rest[..nl].trim().to_string();
-        let body = rest[nl..].trim().to_string();
-        return (title, format!("<basic> {body}"));
+    let (title, body) = match trimmed.strip_prefix("# ") {
+        Some(rest) => {
+            let nl = rest.find('\n').unwrap_or(rest.len());
+            (rest[..nl].trim().to_string(), rest[nl..].trim())
+        }
+        None => (default_title.to_string(), trimmed),
+    };
+    if body.is_empty() {
+        return (title, String::new());
+    }
+    (title, format!("<basic> {body}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n....";
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, bytes) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_link_images() {
+        let bytes = zip(&[
+            ("book/cover.md", b"# Guide\n\n![logo](images/logo.png)"),
+            (
+                "book/01-intro/01-setup/01-linux.md",
+                b"# Linux\n\n![shot](../../images/logo.png \"Logo\") ![same](./shot.PNG) ![web](https://x.dev/a.png) ![gone](missing.png)",
+            ),
+            ("book/images/logo.png", PNG),
+            ("book/01-intro/01-setup/shot.PNG", PNG),
+            ("book/images/unused.gif", b"GIF89a"),
+        ]);
+        let mut book = parse_zip(&bytes).unwrap();
+        assert_eq!(book.images.len(), 3);
+        let used = book.link_images(9);
+
+        // Identical bytes share one name, so both links point at one image.
+        let name = book.images["images/logo.png"].name.clone();
+        assert_eq!(name, book.images["01-intro/01-setup/shot.PNG"].name);
+        assert!(name.ends_with(".png"));
+        assert_eq!(used.into_iter().collect::<Vec<_>>(), vec![name.clone()]);
+        assert_eq!(book.cover_images, vec![name.clone()]);
+        assert_eq!(book.content, format!("<basic> ![logo](/file/book/9/720/{name})"));
+
+        let sub = book.chapters.values().next().unwrap().sections.values().next().unwrap();
+        let sub = sub.subsections.values().next().unwrap();
+        assert_eq!(sub.images, vec![name.clone()]);
+        assert_eq!(
+            sub.content,
+            format!(
+                "<basic> ![shot](/file/book/9/720/{name} \"Logo\") ![same](/file/book/9/720/{name}) \
+                 ![web](https://x.dev/a.png) ![gone](missing.png)"
+            )
+        );
+        assert_eq!(images_json(&sub.images), format!("[{{\"name\":\"{name}\"}}]"));
+
+        let bytes = zip(&[("cover.md", b"# Guide"), ("images/fake.png", b"not an image")]);
+        assert_eq!(
+            parse_zip(&bytes).err(),
+            Some(vec![FileError::file("images/fake.png", "images/fake.png is not a recognised image")])
+        );
+
+        let big = format!("# Big\n\n{}", "a".repeat(crate::utils::content::MAX_CONTENT_BYTES));
+        let bytes = zip(&[("book/cover.md", b"# Guide"), ("book/01-big/_index.md", big.as_bytes())]);
+        let errors = parse_zip(&bytes).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file.as_deref(), Some("01-big/_index.md"));
+        assert!(errors[0].message.starts_with("01-big/_index.md: content is 262152 bytes"));
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let bytes = zip(&[
+            ("cover.md", b"---\ntitle: The Guide\ntags: [Rust, Web]\ndraft: true\n---\n# Ignored heading\n\nWelcome"),
+            ("01-basics/_index.md", b"---\norder: 5\nslug: basics\n---\n"),
+            ("02-advanced/_index.md", b"---\norder: 1\ntags: [async]\n---\n# Advanced"),
+            ("02-advanced/01-io/02-files.md", b"---\norder: 0\ntitle: Files first\n---\nbody"),
+            ("02-advanced/01-io/01-net.md", b"# Net"),
+            ("02-advanced/01-io/03-wip.md", b"---\ndraft: true\n---\n# WIP"),
+            ("03-notes/_index.md", b"---\ndraft: true\n---\n"),
+            ("03-notes/01-a/01-b.md", b"# Hidden"),
+        ]);
+        let book = parse_zip(&bytes).unwrap();
+        assert_eq!(book.title, "The Guide");
+        assert_eq!(book.content, "<basic> Welcome");
+        assert!(book.draft);
+        assert_eq!(book.tags, vec!["rust".to_string(), "web".into(), "async".into()]);
+
+        // `order` beats the NN- prefix; draft folders and files are left out.
+        let chapters: Vec<(&str, Option<&str>)> =
+            book.chapters.values().map(|c| (c.title.as_str(), c.slug.as_deref())).collect();
+        assert_eq!(chapters, vec![("Advanced", None), ("Basics", Some("basics"))]);
+        let io = book.chapters.values().next().unwrap().sections.values().next().unwrap();
+        let subs: Vec<&str> = io.subsections.values().map(|s| s.title.as_str()).collect();
+        assert_eq!(subs, vec!["Files first", "Net"]);
+
+        // Every broken file is reported, not just the first.
+        let bytes = zip(&[
+            ("cover.md", b"# Guide"),
+            ("01-a/01-b/01-c.md", b"---\norder: -1\n---\n"),
+            ("01-a/01-b/02-d.md", b"---\ntitle: open"),
+        ]);
+        let Err(errors) = parse_zip(&bytes) else { panic!("expected errors") };
+        let files: Vec<Option<&str>> = errors.iter().map(|e| e.file.as_deref()).collect();
+        assert_eq!(files, vec![Some("01-a/01-b/01-c.md"), Some("01-a/01-b/02-d.md")]);
+        assert!(errors[0].message.starts_with("01-a/01-b/01-c.md: invalid front matter on line 2"));
     }
-    (default_title.to_string(), format!("<basic> {trimmed}"))
 }
@@ -0,0 +1,465 @@
+//! Reader comments on a blog, or on a chapter, section or sub-section of a
+//! book. Replies form threads under a top-level comment; `root_id` names the
+//! thread so a page of threads loads in two queries. See docs/comments.md.
+
+use crate::auth::permissions::{Permission, Permissions};
+use crate::error::AppError;
+use crate::lifecycle::ensure_visible;
+use crate::utils::doc::TagTable;
+use crate::utils::page::{Page, PageRequest, DEFAULT_PAGE_SIZE};
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tokio_postgres::{Client, Row};
+
+/// Longest comment accepted, in characters.
+const MAX_COMMENT_LEN: usize = 10_000;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Comment {
+    uid: i32,
+    parent_id: Option<i32>,
+    /// `None` once the comment is deleted.
+    user_id: Option<i32>,
+    /// `None` once the comment is deleted.
+    content: Option<String>,
+    deleted: bool,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+    replies: Vec<Comment>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateComment {
+    doc_id: i32,
+    /// Book chapter, section or sub-section; unused for blogs.
+    node_id: Option<i32>,
+    parent_id: Option<i32>,
+    content: String,
+}
+
+#[derive(Deserialize)]
+pub struct EditComment {
+    uid: i32,
+    content: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteComment {
+    uid: i32,
+}
+
+#[derive(Deserialize)]
+pub struct CommentsRequest {
+    doc_id: i32,
+    node_id: Option<i32>,
+}
+
+const COMMENT_COLUMNS: &str = "uid, parent_id, user_id, content, deleted_at IS NOT NULL, created_at, updated_at";
+
+fn comment_from_row(row: &Row) -> Comment {
+    let deleted: bool = row.get(4);
+    Comment {
+        uid: row.get(0),
+        parent_id: row.get(1),
+        user_id: if deleted { None } else { row.get(2) },
+        content: if deleted { None } else { row.get(3) },
+        deleted,
+        created_at: row.get(5),
+        updated_at: row.get(6),
+        replies: Vec::new(),
+    }
+}
+
+fn check_content(content: &str) -> Result<&str, AppError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(AppError::BadRequest("Comment cannot be empty".into()));
+    }
+    if content.chars().count() > MAX_COMMENT_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Comment is longer than {MAX_COMMENT_LEN} characters"
+        )));
+    }
+    Ok(content)
+}
+
+/// The node comments hang off: `None` for blogs, a live chapter, section or
+/// sub-section of the book otherwise.
+async fn comment_node(
+    conn: &Client,
+    table: TagTable,
+    doc_id: i32,
+    node_id: Option<i32>,
+) -> Result<Option<i32>, AppError> {
+    match table {
+        TagTable::Blog => Ok(None),
+        TagTable::Book => {
+            let node_id = node_id
+                .ok_or_else(|| AppError::BadRequest("node_id is required for book comments".into()))?;
+            let identity: Option<i16> = conn
+                .query_opt(
+                    "SELECT identity FROM book WHERE uid = $1 AND doc_id = $2 AND deleted_at IS NULL",
+                    &[&node_id, &doc_id],
+                )
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("Node {node_id} not found")))?
+                .get(0);
+            if !identity.is_some_and(|identity| (101..=103).contains(&identity)) {
+                return Err(AppError::BadRequest(
+                    "Comments attach to a chapter, section or sub-section".into(),
+                ));
+            }
+            Ok(Some(node_id))
+        }
+    }
+}
+
+async fn create_comment(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    body: CreateComment,
+) -> Result<Comment, AppError> {
+    let content = check_content(&body.content)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, table, body.doc_id, Some(user_id)).await?;
+    let node_id = comment_node(&conn, table, body.doc_id, body.node_id).await?;
+
+    // A reply joins its parent's thread; the parent must be live and on the
+    // same blog or book node.
+    let root_id: Option<i32> = match body.parent_id {
+        None => None,
+        Some(parent_id) => {
+            let row = conn
+                .query_opt(
+                    "SELECT COALESCE(root_id, uid) FROM comments \
+                     WHERE uid = $1 AND doc_type = $2 AND doc_id = $3 AND node_id IS NOT DISTINCT FROM $4 \
+                     AND deleted_at IS NULL",
+                    &[&parent_id, &table.doc_type(), &body.doc_id, &node_id],
+                )
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("Comment {parent_id} not found")))?;
+            Some(row.get(0))
+        }
+    };
+
+    let row = conn
+        .query_one(
+            &format!(
+                "INSERT INTO comments(doc_type, doc_id, node_id, parent_id, root_id, user_id, content) \
+                 VALUES($1, $2, $3, $4, $5, $6, $7) RETURNING {COMMENT_COLUMNS}"
+            ),
+            &[
+                &table.doc_type(),
+                &body.doc_id,
+                &node_id,
+                &body.parent_id,
+                &root_id,
+                &user_id,
+                &content,
+            ],
+        )
+        .await?;
+    Ok(comment_from_row(&row))
+}
+
+async fn edit_comment(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    body: EditComment,
+) -> Result<Comment, AppError> {
+    let content = check_content(&body.content)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    // Only the commenter edits, and only while the comment is live.
+    let row = conn
+        .query_opt(
+            &format!(
+                "UPDATE comments SET content = $1, updated_at = now() \
+                 WHERE uid = $2 AND doc_type = $3 AND user_id = $4 AND deleted_at IS NULL \
+                 RETURNING {COMMENT_COLUMNS}"
+            ),
+            &[&content, &body.uid, &table.doc_type(), &user_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Comment {} not found", body.uid)))?;
+    Ok(comment_from_row(&row))
+}
+
+/// Soft-delete a comment. The commenter, the owner of the blog or book, and
+/// moderators may do so; replies stay, under a redacted parent.
+async fn delete_comment(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    permissions: Permissions,
+    uid: i32,
+) -> Result<serde_json::Value, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    let row = conn
+        .query_opt(
+            &format!(
+                "SELECT c.user_id, d.user_id FROM comments c JOIN {} d ON d.uid = c.doc_id \
+                 WHERE c.uid = $1 AND c.doc_type = $2 AND c.deleted_at IS NULL",
+                table.doc_table()
+            ),
+            &[&uid, &table.doc_type()],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Comment {uid} not found")))?;
+    let commenter: i32 = row.get(0);
+    let owner: i32 = row.get(1);
+    if user_id != commenter && user_id != owner && !permissions.has(Permission::Moderate) {
+        return Err(AppError::Error((
+            StatusCode::FORBIDDEN,
+            "Only the commenter or the document owner can delete this comment".into(),
+        )));
+    }
+
+    let row = conn
+        .query_one(
+            "UPDATE comments SET deleted_at = now(), deleted_by = $1 WHERE uid = $2 RETURNING deleted_at",
+            &[&user_id, &uid],
+        )
+        .await?;
+    let deleted_at: DateTime<Utc> = row.get(0);
+    if user_id != commenter {
+        tracing::info!(user_id, comment_id = uid, doc_type = table.doc_type(), "comment removed by moderator");
+    }
+    Ok(json!({ "uid": uid, "deleted_at": deleted_at }))
+}
+
+/// A page of threads, newest first, each with all of its replies oldest
+/// first. Deleted comments only show when they still have live replies.
+async fn list_comments(
+    pool: &AppState,
+    table: TagTable,
+    viewer: Option<i32>,
+    request: CommentsRequest,
+    page: PageRequest,
+) -> Result<Page<Comment>, AppError> {
+    let keyset = page.keyset(DEFAULT_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, table, request.doc_id, viewer).await?;
+    let node_id = comment_node(&conn, table, request.doc_id, request.node_id).await?;
+
+    let rows = conn
+        .query(
+            &format!(
+                "SELECT {COMMENT_COLUMNS} FROM comments \
+                 WHERE doc_type = $1 AND doc_id = $2 AND node_id IS NOT DISTINCT FROM $3 AND parent_id IS NULL \
+                 AND ($4::timestamptz IS NULL OR (created_at, uid) < ($4::timestamptz, $5::int4)) \
+                 ORDER BY created_at DESC, uid DESC LIMIT $6"
+            ),
+            &[
+                &table.doc_type(),
+                &request.doc_id,
+                &node_id,
+                &keyset.created_at,
+                &keyset.uid,
+                &keyset.fetch_limit(),
+            ],
+        )
+        .await?;
+    let mut page = keyset.page(rows.iter().map(comment_from_row).collect(), |c| (c.created_at, c.uid));
+
+    let roots: Vec<i32> = page.items.iter().map(|c| c.uid).collect();
+    let rows = conn
+        .query(
+            &format!(
+                "SELECT {COMMENT_COLUMNS} FROM comments WHERE root_id = ANY($1) ORDER BY created_at, uid"
+            ),
+            &[&roots],
+        )
+        .await?;
+    let replies = rows.iter().map(comment_from_row).collect();
+    page.items = thread(page.items, replies);
+    Ok(page)
+}
+
+/// Hang `replies` (oldest first) under their parents among `roots` and each
+/// other, then drop deleted comments with nothing live beneath them.
+fn thread(roots: Vec<Comment>, replies: Vec<Comment>) -> Vec<Comment> {
+    let mut children: HashMap<i32, Vec<Comment>> = HashMap::new();
+    for reply in replies {
+        if let Some(parent_id) = reply.parent_id {
+            children.entry(parent_id).or_default().push(reply);
+        }
+    }
+    fn attach(mut comment: Comment, children: &mut HashMap<i32, Vec<Comment>>) -> Option<Comment> {
+        let replies = children.remove(&comment.uid).unwrap_or_default();
+        comment.replies = replies.into_iter().filter_map(|reply| attach(reply, children)).collect();
+        (!comment.deleted || !comment.replies.is_empty()).then_some(comment)
+    }
+    roots.into_iter().filter_map(|root| attach(root, &mut children)).collect()
+}
+
+/// POST /blog/comments/create
+pub async fn create_blog_comment(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<CreateComment>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = create_comment(&pool, TagTable::Blog, user_id, body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/comments/create
+pub async fn create_book_comment(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<CreateComment>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = create_comment(&pool, TagTable::Book, user_id, body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /blog/comments/edit
+pub async fn edit_blog_comment(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<EditComment>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = edit_comment(&pool, TagTable::Blog, user_id, body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/comments/edit
+pub async fn edit_book_comment(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<EditComment>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = edit_comment(&pool, TagTable::Book, user_id, body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /blog/comments/delete
+pub async fn delete_blog_comment(
+    Extension(UserId(user_id)): Extension<UserId>,
+    permissions: Permissions,
+    State(pool): State<AppState>,
+    Json(body): Json<DeleteComment>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = delete_comment(&pool, TagTable::Blog, user_id, permissions, body.uid).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/comments/delete
+pub async fn delete_book_comment(
+    Extension(UserId(user_id)): Extension<UserId>,
+    permissions: Permissions,
+    State(pool): State<AppState>,
+    Json(body): Json<DeleteComment>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = delete_comment(&pool, TagTable::Book, user_id, permissions, body.uid).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// GET /blog/comments?doc_id=42
+pub async fn get_blog_comments(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    Query(request): Query<CommentsRequest>,
+    Query(page): Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let result = list_comments(&pool, TagTable::Blog, viewer, request, page).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// GET /book/comments?doc_id=42&node_id=7
+pub async fn get_book_comments(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    Query(request): Query<CommentsRequest>,
+    Query(page): Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let result = list_comments(&pool, TagTable::Book, viewer, request, page).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(uid: i32, parent_id: Option<i32>, deleted: bool) -> Comment {
+        Comment {
+            uid,
+            parent_id,
+            user_id: (!deleted).then_some(1),
+            content: (!deleted).then(|| format!("comment {uid}")),
+            deleted,
+            created_at: DateTime::from_timestamp(uid as i64, 0).unwrap(),
+            updated_at: None,
+            replies: Vec::new(),
+        }
+    }
+
+    fn shape(comments: &[Comment]) -> Vec<(i32, Vec<(i32, usize)>)> {
+        comments
+            .iter()
+            .map(|c| (c.uid, c.replies.iter().map(|r| (r.uid, r.replies.len())).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_thread() {
+        let roots = vec![comment(10, None, false), comment(5, None, true), comment(1, None, true)];
+        let replies = vec![
+            comment(11, Some(10), false),
+            comment(12, Some(11), false),
+            comment(13, Some(10), true),
+            comment(6, Some(5), false),
+        ];
+        let threads = thread(roots, replies);
+        // The deleted reply 13 and the deleted, reply-less thread 1 go; the
+        // deleted thread 5 stays for its live reply.
+        assert_eq!(shape(&threads), vec![(10, vec![(11, 1)]), (5, vec![(6, 0)])]);
+        assert_eq!(threads[1].content, None);
+        assert_eq!(threads[0].replies[0].replies[0].uid, 12);
+    }
+}
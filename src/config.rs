@@ -23,6 +23,10 @@ pub(crate) struct AppConfig {
     pub(crate) allowed_origins: String,
     pub(crate) file_storage_path: FileStoragePath,
     pub(crate) secret_key: String,
+    /// Days soft-deleted content stays in the trash before it is purged.
+    pub(crate) trash_retention_days: i32,
+    /// Background workers running book import jobs.
+    pub(crate) import_workers: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -70,6 +74,16 @@ pub fn init_env_configs() -> Result<Config, AppError> {
     let blog_upload = var("BLOG_UPLOADS").unwrap();
     let book_upload = var("BOOK_UPLOADS").unwrap();
 
+    let trash_retention_days = var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30);
+
+    let import_workers = var("IMPORT_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse().ok())
+        .unwrap_or(2);
+
     let s3_url = var("S3_URL").unwrap();
     let s3_jwt_secret = var("S3_JWT_SECRET").unwrap();
 
@@ -79,6 +93,8 @@ pub fn init_env_configs() -> Result<Config, AppError> {
             app_name,
             auth_app_name,
             secret_key, 
+            trash_retention_days,
+            import_workers,
             hostname, 
             port,
             // http_port, 
@@ -85,6 +85,11 @@ impl From<reqwest::Error> for AppError {
         AppError::InternalServerError(err.to_string())
     }
 }
+impl From<tantivy::TantivyError> for AppError {
+    fn from(err: tantivy::TantivyError) -> Self {
+        AppError::InternalServerError(err.to_string())
+    }
+}
 impl std::fmt::Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
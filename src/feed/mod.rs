@@ -0,0 +1,155 @@
+use crate::error::AppError;
+use crate::types::{Blog, Book};
+use crate::utils::page::{Keyset, PageRequest, DEFAULT_PAGE_SIZE};
+use crate::{fetch_home_blogs, fetch_home_books, AppState};
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use tokio_postgres::Row;
+
+/// A blog (`doc_type` 1) or book (`doc_type` 2) in the merged feed.
+#[derive(Serialize)]
+pub struct FeedItem {
+    uid: i32,
+    user_id: i32,
+    title: String,
+    images: Option<String>,
+    created_at: DateTime<Utc>,
+    doc_type: u8,
+}
+
+impl From<Blog> for FeedItem {
+    fn from(blog: Blog) -> Self {
+        FeedItem {
+            uid: blog.uid,
+            user_id: blog.user_id,
+            title: blog.title,
+            images: blog.images,
+            created_at: blog.created_at,
+            doc_type: 1,
+        }
+    }
+}
+
+impl From<Book> for FeedItem {
+    fn from(book: Book) -> Self {
+        FeedItem {
+            uid: book.uid,
+            user_id: book.user_id,
+            title: book.title,
+            images: book.images,
+            created_at: book.created_at,
+            doc_type: 2,
+        }
+    }
+}
+
+fn feed_item(row: &Row, doc_type: u8) -> FeedItem {
+    FeedItem {
+        uid: row.get(0),
+        user_id: row.get(1),
+        title: row.get(2),
+        images: row.get(3),
+        created_at: row.get(4),
+        doc_type,
+    }
+}
+
+/// Merge two newest-first lists into one newest-first list. Each input holds
+/// at most `fetch_limit` rows past the cursor, so the merged head is exact.
+fn merge_newest(blogs: Vec<FeedItem>, books: Vec<FeedItem>) -> Vec<FeedItem> {
+    let mut items: Vec<FeedItem> = blogs.into_iter().chain(books).collect();
+    items.sort_by(|a, b| {
+        (b.created_at, b.uid, b.doc_type).cmp(&(a.created_at, a.uid, a.doc_type))
+    });
+    items
+}
+
+async fn fetch_following(
+    conn: &tokio_postgres::Client,
+    authors: &[i32],
+    keyset: &Keyset,
+) -> Result<Vec<FeedItem>, AppError> {
+    let blog_rows = conn
+        .query(
+            "SELECT uid, user_id, title, images, created_at FROM blogs WHERE deleted_at IS NULL AND status = 'published' AND user_id = ANY($1) \
+             AND ($2::timestamptz IS NULL OR (created_at, uid) < ($2::timestamptz, $3::int4)) \
+             ORDER BY created_at DESC, uid DESC LIMIT $4",
+            &[&authors, &keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
+        )
+        .await?;
+    let book_rows = conn
+        .query(
+            "SELECT uid, user_id, title, images, created_at FROM books WHERE deleted_at IS NULL AND status = 'published' AND user_id = ANY($1) \
+             AND ($2::timestamptz IS NULL OR (created_at, uid) < ($2::timestamptz, $3::int4)) \
+             ORDER BY created_at DESC, uid DESC LIMIT $4",
+            &[&authors, &keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
+        )
+        .await?;
+
+    Ok(merge_newest(
+        blog_rows.iter().map(|row| feed_item(row, 1)).collect(),
+        book_rows.iter().map(|row| feed_item(row, 2)).collect(),
+    ))
+}
+
+/// GET /feed
+///
+/// Newest blogs and books from the authors the caller follows, merged and
+/// paginated by cursor. Callers who follow nobody get the home blogs/books
+/// instead; `source` tells the client which one it received.
+pub async fn get_feed(
+    axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
+    State(pool): State<AppState>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
+    // Blogs and books are merged by date, so a popularity cursor could name a
+    // uid from either table.
+    if keyset.popular {
+        return Err(AppError::BadRequest("/feed is always newest first; `sort=popular` is not supported".into()));
+    }
+    let conn = pool.pg_pool.conn.get().await?;
+
+    let authors: Vec<i32> = conn
+        .query(
+            "SELECT subscribed_id FROM subscription WHERE user_id=$1",
+            &[&user_id],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let (source, items) = if authors.is_empty() {
+        let blogs = fetch_home_blogs!(&conn, keyset)?;
+        let books = fetch_home_books!(&conn, keyset)?;
+        (
+            "home",
+            merge_newest(
+                blogs.into_iter().map(FeedItem::from).collect(),
+                books.into_iter().map(FeedItem::from).collect(),
+            ),
+        )
+    } else {
+        ("following", fetch_following(&conn, &authors, &keyset).await?)
+    };
+
+    let page = keyset.page(items, |item| (item.created_at, item.uid));
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "source": source,
+            "items": page.items,
+            "next_cursor": page.next_cursor
+        })),
+    ))
+}
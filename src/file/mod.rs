@@ -1,5 +1,6 @@
 pub mod s3_client;
 
+use crate::traits::IMAGE_SIZES;
 use crate::types::ImageMetadata;
 use crate::{error::AppError, AppState};
 use axum::{
@@ -14,9 +15,9 @@ use std::io::Cursor;
 use std::path::Path;
 use uuid::Uuid;
 
-const ALLOWED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif"];
+pub(crate) const ALLOWED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif"];
 
-fn is_valid_image(bytes: &[u8]) -> bool {
+pub(crate) fn is_valid_image(bytes: &[u8]) -> bool {
     bytes.starts_with(b"\xFF\xD8\xFF")
         || bytes.starts_with(b"\x89PNG\r\n\x1a\n")
         || bytes.starts_with(b"GIF87a")
@@ -24,7 +25,7 @@ fn is_valid_image(bytes: &[u8]) -> bool {
         || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP"))
 }
 
-fn mime_for_extension(ext: &str) -> &'static str {
+pub(crate) fn mime_for_extension(ext: &str) -> &'static str {
     match ext {
         "jpg" | "jpeg" => "image/jpeg",
         "png" => "image/png",
@@ -40,6 +41,17 @@ fn encode_image(img: &image::DynamicImage, format: ImageFormat) -> Result<Vec<u8
     Ok(buf.into_inner())
 }
 
+/// Encode `img` at every width in `IMAGE_SIZES`, smallest first.
+pub(crate) fn image_variants(
+    img: &image::DynamicImage,
+    format: ImageFormat,
+) -> Result<Vec<(u32, Vec<u8>)>, AppError> {
+    IMAGE_SIZES
+        .iter()
+        .map(|&size| Ok((size, encode_image(&img.resize(size, img.height(), FilterType::Lanczos3), format)?)))
+        .collect()
+}
+
 pub async fn upload_file(
     axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(state): State<AppState>,
@@ -88,16 +100,12 @@ pub async fn upload_file(
                 img_metadata.cropImgMd.height,
             );
 
-            let sm = encode_image(&cropped.resize(340, cropped.height(), FilterType::Lanczos3), format)?;
-            let md = encode_image(&cropped.resize(720, cropped.height(), FilterType::Lanczos3), format)?;
-            let lg = encode_image(&cropped.resize(1420, cropped.height(), FilterType::Lanczos3), format)?;
-
             let uid_str = user_id.to_string();
             let s3 = state.s3();
 
-            s3.put("tmp", &format!("{}/340-{}", uid_str, filename), sm, mime, &uid_str).await?;
-            s3.put("tmp", &format!("{}/720-{}", uid_str, filename), md, mime, &uid_str).await?;
-            s3.put("tmp", &format!("{}/1420-{}", uid_str, filename), lg, mime, &uid_str).await?;
+            for (size, bytes) in image_variants(&cropped, format)? {
+                s3.put("tmp", &format!("{}/{}-{}", uid_str, size, filename), bytes, mime, &uid_str).await?;
+            }
 
             return Ok((
                 StatusCode::OK,
@@ -132,6 +140,15 @@ pub async fn get_book_file(
     Ok((StatusCode::OK, [(header::CONTENT_TYPE, content_type)], bytes))
 }
 
+pub async fn get_user_file(
+    State(state): State<AppState>,
+    AxumPath((uid, size, filename)): AxumPath<(i32, String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let key = format!("{}/{}-{}", uid, size, filename);
+    let (bytes, content_type) = state.s3().get("user", &key).await?;
+    Ok((StatusCode::OK, [(header::CONTENT_TYPE, content_type)], bytes))
+}
+
 pub async fn get_tmp_file(
     State(state): State<AppState>,
     AxumPath((uid, size, filename)): AxumPath<(i32, String, String)>,
@@ -1,18 +1,28 @@
+use crate::book::import::ImportJobs;
 use crate::config::{init_env_configs, Config};
 use crate::connections::pg::PgConnection;
 use crate::file::s3_client::S3Client;
+use crate::search::index::SearchIndex;
 
 #[derive(Clone)]
 pub(crate) struct AppState {
     pub(crate) config: Config,
     pub(crate) pg_pool: PgConnection,
     s3_client: S3Client,
+    search_index: SearchIndex,
+    import_jobs: ImportJobs,
 }
 
 impl AppState {
     pub fn s3(&self) -> &S3Client {
         &self.s3_client
     }
+    pub fn search(&self) -> &SearchIndex {
+        &self.search_index
+    }
+    pub fn imports(&self) -> &ImportJobs {
+        &self.import_jobs
+    }
     pub fn port(&self) -> &u16 {
         &self.config.app.port
     }
@@ -27,11 +37,17 @@ pub async fn init_app_state() -> AppState {
     let s3_client = S3Client::new(config.s3.url.clone(), config.s3.jwt_secret.clone());
 
     // Best-effort bucket creation — warns on failure but does not abort startup.
-    for bucket in &["tmp", "blog", "book"] {
+    for bucket in &["tmp", "blog", "book", "user"] {
         if let Err(e) = s3_client.ensure_bucket(bucket).await {
             tracing::warn!(bucket, error = %e, "could not ensure S3 bucket (is loony-s3 running?)");
         }
     }
 
-    AppState { config, pg_pool, s3_client }
+    let search_index = SearchIndex::new().expect("Failed to create search index.");
+    match search_index.rebuild(&pg_pool).await {
+        Ok(count) => tracing::info!(count, "search index built"),
+        Err(e) => tracing::warn!(error = %e, "could not build search index"),
+    }
+
+    AppState { config, pg_pool, s3_client, search_index, import_jobs: ImportJobs::new() }
 }
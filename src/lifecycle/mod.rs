@@ -0,0 +1,281 @@
+use crate::book::collaborators::{book_role, require_book_role, CollaboratorRole};
+use crate::error::AppError;
+use crate::utils::doc::TagTable;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio_postgres::Client;
+
+/// How often the publisher looks for scheduled documents that are due.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Publication state of a blog or book. Only `Published` documents appear in
+/// listings, feeds, search and recommendations; `Unlisted` ones are readable
+/// by link; `Draft` and `Scheduled` are visible to the author alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocStatus {
+    Draft,
+    Scheduled,
+    Published,
+    Unlisted,
+}
+
+impl DocStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocStatus::Draft => "draft",
+            DocStatus::Scheduled => "scheduled",
+            DocStatus::Published => "published",
+            DocStatus::Unlisted => "unlisted",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "draft" => Some(DocStatus::Draft),
+            "scheduled" => Some(DocStatus::Scheduled),
+            "published" => Some(DocStatus::Published),
+            "unlisted" => Some(DocStatus::Unlisted),
+            _ => None,
+        }
+    }
+
+    /// Readable by anyone holding the link.
+    pub fn is_public(&self) -> bool {
+        matches!(self, DocStatus::Published | DocStatus::Unlisted)
+    }
+}
+
+/// A validated `status` / `publish_at` pair as stored on `blogs` and `books`.
+#[derive(Debug, PartialEq)]
+pub struct Lifecycle {
+    pub status: DocStatus,
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+impl Lifecycle {
+    /// Defaults to published; a bare `publish_at` means scheduled. Scheduling
+    /// needs a future `publish_at`, and no other status accepts one.
+    pub fn resolve(
+        status: Option<&str>,
+        publish_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, AppError> {
+        let status = match status {
+            Some(raw) => DocStatus::parse(raw)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown status `{raw}`")))?,
+            None if publish_at.is_some() => DocStatus::Scheduled,
+            None => DocStatus::Published,
+        };
+        match (status, publish_at) {
+            (DocStatus::Scheduled, None) => {
+                Err(AppError::BadRequest("`publish_at` is required for scheduled documents".into()))
+            }
+            (DocStatus::Scheduled, Some(at)) if at <= now => {
+                Err(AppError::BadRequest("`publish_at` must be in the future".into()))
+            }
+            (DocStatus::Scheduled, Some(_)) => Ok(Lifecycle { status, publish_at }),
+            (_, Some(_)) => Err(AppError::BadRequest(
+                "`publish_at` is only allowed for scheduled documents".into(),
+            )),
+            (_, None) => Ok(Lifecycle { status, publish_at: None }),
+        }
+    }
+}
+
+/// Fail with `NotFound` unless `viewer` may read the document: public
+/// statuses are open to all, the rest to the author (and, for books,
+/// accepted collaborators). Missing and hidden look the same on purpose.
+pub async fn ensure_visible(
+    conn: &Client,
+    table: TagTable,
+    doc_id: i32,
+    viewer: Option<i32>,
+) -> Result<(), AppError> {
+    let not_found = || AppError::NotFound(format!("Document {doc_id} not found"));
+    let row = conn
+        .query_opt(
+            &format!(
+                "SELECT user_id, status FROM {} WHERE uid = $1 AND deleted_at IS NULL",
+                table.doc_table()
+            ),
+            &[&doc_id],
+        )
+        .await?
+        .ok_or_else(not_found)?;
+    let owner: i32 = row.get(0);
+    let status: String = row.get(1);
+    if DocStatus::parse(&status).is_some_and(|s| s.is_public()) {
+        return Ok(());
+    }
+    let Some(viewer) = viewer else {
+        return Err(not_found());
+    };
+    let allowed = match table {
+        TagTable::Blog => owner == viewer,
+        TagTable::Book => book_role(conn, doc_id, viewer).await?.is_some(),
+    };
+    if allowed { Ok(()) } else { Err(not_found()) }
+}
+
+/// The subset of `doc_ids` that is live and published, for filtering results
+/// that do not come from SQL (e.g. the search index).
+pub async fn published_ids(conn: &Client, table: TagTable, doc_ids: &[i32]) -> Result<HashSet<i32>, AppError> {
+    if doc_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let rows = conn
+        .query(
+            &format!(
+                "SELECT uid FROM {} WHERE uid = ANY($1) AND deleted_at IS NULL AND status = 'published'",
+                table.doc_table()
+            ),
+            &[&doc_ids],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Flip every scheduled document whose `publish_at` has passed.
+pub async fn publish_due(conn: &Client) -> Result<u64, AppError> {
+    let mut published = 0;
+    for table in [TagTable::Blog, TagTable::Book] {
+        published += conn
+            .execute(
+                &format!(
+                    "UPDATE {} SET status = 'published' \
+                     WHERE status = 'scheduled' AND publish_at <= now() AND deleted_at IS NULL",
+                    table.doc_table()
+                ),
+                &[],
+            )
+            .await?;
+    }
+    Ok(published)
+}
+
+/// Background task running `publish_due` every `PUBLISH_INTERVAL`.
+pub fn spawn_publisher(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let result = match state.pg_pool.conn.get().await {
+                Ok(conn) => publish_due(&conn).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(0) => {}
+                Ok(n) => tracing::info!(published = n, "published scheduled documents"),
+                Err(e) => tracing::warn!(error = %e, "scheduled publishing failed"),
+            }
+        }
+    });
+}
+
+#[derive(Deserialize)]
+pub struct SetStatus {
+    doc_id: i32,
+    status: String,
+    publish_at: Option<DateTime<Utc>>,
+}
+
+async fn set_status(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    body: &SetStatus,
+) -> Result<serde_json::Value, AppError> {
+    let lifecycle = Lifecycle::resolve(Some(&body.status), body.publish_at, Utc::now())?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let owner = match table {
+        TagTable::Blog => Some(user_id),
+        TagTable::Book => {
+            require_book_role(&conn, body.doc_id, user_id, CollaboratorRole::Owner).await?;
+            None
+        }
+    };
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE {} SET status = $1, publish_at = $2 \
+                 WHERE uid = $3 AND deleted_at IS NULL AND ($4::int4 IS NULL OR user_id = $4)",
+                table.doc_table()
+            ),
+            &[&lifecycle.status.as_str(), &lifecycle.publish_at, &body.doc_id, &owner],
+        )
+        .await?;
+    if updated == 0 {
+        return Err(AppError::NotFound(format!("Document {} not found", body.doc_id)));
+    }
+
+    Ok(json!({
+        "doc_id": body.doc_id,
+        "status": lifecycle.status,
+        "publish_at": lifecycle.publish_at
+    }))
+}
+
+pub async fn set_blog_status(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<SetStatus>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = set_status(&pool, TagTable::Blog, user_id, &body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+pub async fn set_book_status(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<SetStatus>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = set_status(&pool, TagTable::Book, user_id, &body).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_resolve_lifecycle() {
+        let now = Utc.timestamp_opt(1_000, 0).unwrap();
+        let later = Utc.timestamp_opt(2_000, 0).unwrap();
+
+        assert_eq!(
+            Lifecycle::resolve(None, None, now).unwrap(),
+            Lifecycle { status: DocStatus::Published, publish_at: None }
+        );
+        assert_eq!(
+            Lifecycle::resolve(None, Some(later), now).unwrap(),
+            Lifecycle { status: DocStatus::Scheduled, publish_at: Some(later) }
+        );
+        assert_eq!(Lifecycle::resolve(Some("draft"), None, now).unwrap().status, DocStatus::Draft);
+
+        assert!(Lifecycle::resolve(Some("scheduled"), None, now).is_err());
+        assert!(Lifecycle::resolve(Some("scheduled"), Some(now), now).is_err());
+        assert!(Lifecycle::resolve(Some("unlisted"), Some(later), now).is_err());
+        assert!(Lifecycle::resolve(Some("archived"), None, now).is_err());
+    }
+}
@@ -15,6 +15,16 @@ mod auth;
 mod middleware;
 mod connections;
 mod tracing_setup;
+mod search;
+mod feed;
+mod recommend;
+mod admin;
+mod revision;
+mod lifecycle;
+mod trash;
+mod comment;
+mod reaction;
+mod progress;
 
 pub(crate) use init::AppState;
 
@@ -28,6 +38,10 @@ async fn main() {
     let port = app_state.port().to_owned();
     let app_env = app_state.app_env().to_owned();
 
+    lifecycle::spawn_publisher(app_state.clone());
+    trash::spawn_purger(app_state.clone());
+    book::import::spawn_workers(app_state.clone());
+
     let router = route::create_router(app_state.clone()).await;
     
     let host = if app_env == "production" {
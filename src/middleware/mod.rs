@@ -2,6 +2,7 @@ use crate::error::AppError;
 use crate::utils::UserId;
 use axum::http::HeaderMap;
 use crate::auth::decode_token;
+use crate::auth::permissions::{Permission, Permissions};
 use axum::extract::State;
 use crate::AppState;
 
@@ -11,7 +12,7 @@ use axum::middleware::Next;
 use reqwest::StatusCode;
 
 
-pub fn parse_cookies<'a>(headers: &'a HeaderMap) -> Vec<(&'a str, &'a str)> {
+pub fn parse_cookies(headers: &HeaderMap) -> Vec<(&str, &str)> {
     headers
         .get_all("cookie")
         .iter()
@@ -44,6 +45,7 @@ pub async fn require_auth(
                         .parse()
                         .map_err(|_| AppError::Error((StatusCode::UNAUTHORIZED, "Invalid token subject".into())))?;
                     req.extensions_mut().insert(UserId(user_id));
+                    req.extensions_mut().insert(Permissions::from_claims(&claims));
                     return Ok(next.run(req).await);
                 }
                 Err(_) => {
@@ -55,3 +57,38 @@ pub async fn require_auth(
     Ok(AppError::Error((StatusCode::UNAUTHORIZED, "access_token not found".into())).into_response())
 }
 
+
+/// Like `require_auth`, but never rejects: a valid `access_token` cookie
+/// injects `UserId`, anything else passes through anonymously. Public read
+/// routes use it to personalise responses via `Option<Extension<UserId>>`.
+pub async fn optional_auth(
+    State(state): State<AppState>,
+    header: HeaderMap,
+    mut req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let token = parse_cookies(&header)
+        .into_iter()
+        .find(|(name, _)| *name == "access_token")
+        .map(|(_, value)| value);
+    if let Some(token) = token
+        && let Ok(claims) = decode_token(token, &state.config.app.auth_app_name, &state.config.app.secret_key)
+        && let Ok(user_id) = claims.sub.parse::<i32>()
+    {
+        req.extensions_mut().insert(UserId(user_id));
+        req.extensions_mut().insert(Permissions::from_claims(&claims));
+    }
+    next.run(req).await
+}
+
+/// Per-route guard, layered after `require_auth`:
+/// `route_layer(from_fn_with_state(Permission::Write, require_permission))`.
+pub async fn require_permission(
+    State(permission): State<Permission>,
+    permissions: Permissions,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    permissions.require(permission)?;
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,254 @@
+//! Where a reader is in a book: the last node read and a percentage per
+//! `(user_id, doc_id)`, plus the chapters, sections and sub-sections they
+//! marked completed. See docs/reading-progress.md.
+
+use crate::error::AppError;
+use crate::lifecycle::ensure_visible;
+use crate::utils::doc::TagTable;
+use crate::utils::page::{PageRequest, DEFAULT_PAGE_SIZE};
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use tokio_postgres::Client;
+
+#[derive(Deserialize)]
+pub struct SaveProgress {
+    node_id: i32,
+    /// How far through the whole book, 0–100.
+    percent: i16,
+    /// `true` marks `node_id` completed, `false` clears it, absent leaves it.
+    completed: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct ReadingProgress {
+    doc_id: i32,
+    node_id: i32,
+    percent: i16,
+    updated_at: DateTime<Utc>,
+    /// Completed chapter, section and sub-section uids, ascending.
+    completed: Vec<i32>,
+}
+
+#[derive(Serialize)]
+pub struct ContinueReading {
+    doc_id: i32,
+    user_id: i32,
+    title: String,
+    images: Option<String>,
+    node_id: i32,
+    /// `None` when the node has since been deleted.
+    node_title: Option<String>,
+    percent: i16,
+    updated_at: DateTime<Utc>,
+}
+
+/// Uids of the nodes of `doc_id` that `user_id` has completed.
+pub async fn completed_nodes(conn: &Client, user_id: i32, doc_id: i32) -> Result<HashSet<i32>, AppError> {
+    let rows = conn
+        .query(
+            "SELECT node_id FROM reading_completed WHERE user_id = $1 AND doc_id = $2",
+            &[&user_id, &doc_id],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Set `completed` on every chapter, section and sub-section in `nodes`, a
+/// `child_nodes` or `chapters` array from `/book/get/nav`, descending into
+/// `children`.
+pub fn mark_completed(nodes: &mut Value, done: &HashSet<i32>) {
+    let Some(nodes) = nodes.as_array_mut() else {
+        return;
+    };
+    for node in nodes {
+        let uid = node["uid"].as_i64();
+        let identity = node["identity"].as_i64();
+        if let (Some(uid), Some(101..=103)) = (uid, identity) {
+            node["completed"] = Value::Bool(done.contains(&(uid as i32)));
+        }
+        if let Some(children) = node.get_mut("children") {
+            mark_completed(children, done);
+        }
+    }
+}
+
+async fn load_progress(conn: &Client, user_id: i32, doc_id: i32) -> Result<ReadingProgress, AppError> {
+    let row = conn
+        .query_opt(
+            "SELECT node_id, percent, updated_at FROM reading_progress WHERE user_id = $1 AND doc_id = $2",
+            &[&user_id, &doc_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No reading progress for book {doc_id}")))?;
+    let mut completed: Vec<i32> = completed_nodes(conn, user_id, doc_id).await?.into_iter().collect();
+    completed.sort_unstable();
+    Ok(ReadingProgress {
+        doc_id,
+        node_id: row.get(0),
+        percent: row.get(1),
+        updated_at: row.get(2),
+        completed,
+    })
+}
+
+/// POST /book/:doc_id/progress — record the caller's position in a book.
+pub async fn save_book_progress(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+    Json(body): Json<SaveProgress>,
+) -> Result<impl IntoResponse, AppError> {
+    if !(0..=100).contains(&body.percent) {
+        return Err(AppError::BadRequest("percent must be between 0 and 100".into()));
+    }
+    let mut conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, TagTable::Book, doc_id, Some(user_id)).await?;
+    let identity: Option<i16> = conn
+        .query_opt(
+            "SELECT identity FROM book WHERE uid = $1 AND doc_id = $2 AND deleted_at IS NULL",
+            &[&body.node_id, &doc_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Node {} not found", body.node_id)))?
+        .get(0);
+    if body.completed.is_some() && !identity.is_some_and(|identity| (101..=103).contains(&identity)) {
+        return Err(AppError::BadRequest(
+            "Only a chapter, section or sub-section can be completed".into(),
+        ));
+    }
+
+    let transaction = conn.transaction().await?;
+    transaction
+        .execute(
+            "INSERT INTO reading_progress(user_id, doc_id, node_id, percent) VALUES($1, $2, $3, $4) \
+             ON CONFLICT (user_id, doc_id) DO UPDATE SET node_id = EXCLUDED.node_id, percent = EXCLUDED.percent, updated_at = now()",
+            &[&user_id, &doc_id, &body.node_id, &body.percent],
+        )
+        .await?;
+    match body.completed {
+        Some(true) => {
+            transaction
+                .execute(
+                    "INSERT INTO reading_completed(user_id, doc_id, node_id) VALUES($1, $2, $3) ON CONFLICT DO NOTHING",
+                    &[&user_id, &doc_id, &body.node_id],
+                )
+                .await?;
+        }
+        Some(false) => {
+            transaction
+                .execute(
+                    "DELETE FROM reading_completed WHERE user_id = $1 AND node_id = $2",
+                    &[&user_id, &body.node_id],
+                )
+                .await?;
+        }
+        None => {}
+    }
+    transaction.commit().await?;
+
+    let progress = load_progress(&conn, user_id, doc_id).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(progress),
+    ))
+}
+
+/// GET /book/:doc_id/progress — the caller's position in a book.
+pub async fn get_book_progress(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    ensure_visible(&conn, TagTable::Book, doc_id, Some(user_id)).await?;
+    let progress = load_progress(&conn, user_id, doc_id).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(progress),
+    ))
+}
+
+/// GET /user/reading — books the caller has started and not finished, most
+/// recently read first.
+pub async fn get_continue_reading(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let rows = conn
+        .query(
+            "SELECT p.doc_id, b.user_id, b.title, b.images, p.node_id, n.title, p.percent, p.updated_at \
+             FROM reading_progress p \
+             JOIN books b ON b.uid = p.doc_id AND b.deleted_at IS NULL \
+             LEFT JOIN book n ON n.uid = p.node_id AND n.deleted_at IS NULL \
+             WHERE p.user_id = $1 AND p.percent < 100 \
+             AND (b.status IN ('published', 'unlisted') OR b.user_id = $1 OR EXISTS ( \
+                 SELECT 1 FROM book_collaborators c WHERE c.doc_id = b.uid AND c.user_id = $1 AND c.accepted_at IS NOT NULL)) \
+             AND ($2::timestamptz IS NULL OR (p.updated_at, p.doc_id) < ($2::timestamptz, $3::int4)) \
+             ORDER BY p.updated_at DESC, p.doc_id DESC LIMIT $4",
+            &[&user_id, &keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
+        )
+        .await?;
+
+    let books = rows
+        .iter()
+        .map(|row| ContinueReading {
+            doc_id: row.get(0),
+            user_id: row.get(1),
+            title: row.get(2),
+            images: row.get(3),
+            node_id: row.get(4),
+            node_title: row.get(5),
+            percent: row.get(6),
+            updated_at: row.get(7),
+        })
+        .collect::<Vec<ContinueReading>>();
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(keyset.page(books, |b| (b.updated_at, b.doc_id))),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mark_completed() {
+        let mut chapters = json!([
+            { "uid": 11, "identity": 101, "children": [
+                { "uid": 12, "identity": 102, "children": [
+                    { "uid": 13, "identity": 103, "children": [] }
+                ] }
+            ] },
+            { "uid": 14, "identity": 101, "children": [] }
+        ]);
+        mark_completed(&mut chapters, &HashSet::from([11, 13]));
+        assert_eq!(chapters[0]["completed"], json!(true));
+        assert_eq!(chapters[0]["children"][0]["completed"], json!(false));
+        assert_eq!(chapters[0]["children"][0]["children"][0]["completed"], json!(true));
+        assert_eq!(chapters[1]["completed"], json!(false));
+
+        // The flat format has no `children`; nodes of other identities are untouched.
+        let mut flat = json!([{ "uid": 11, "identity": 101 }, { "uid": 10, "identity": 100 }]);
+        mark_completed(&mut flat, &HashSet::from([11]));
+        assert_eq!(flat, json!([{ "uid": 11, "identity": 101, "completed": true }, { "uid": 10, "identity": 100 }]));
+    }
+}
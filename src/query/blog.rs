@@ -1,11 +1,17 @@
-
 #[macro_export]
 macro_rules! fetch_home_blogs {
-    ($conn:expr) => {{
+    ($conn:expr, $keyset:expr) => {{
         use tokio_postgres::Row;
         
-        let blogs_query = "SELECT uid, user_id, title, images, created_at FROM blogs where deleted_at is NULL LIMIT 5";
-        let rows: Vec<Row> = $conn.query(blogs_query, &[]).await?;
+        let blogs_query = format!("SELECT uid, user_id, title, images, created_at, status, like_count, bookmark_count FROM blogs where deleted_at is NULL AND status = 'published' \
+                           AND {after} \
+                           {order} LIMIT $3",
+            after = $keyset.after("blogs", "", 1),
+            order = $keyset.order_by(""),
+        );
+        let rows: Vec<Row> = $conn
+            .query(&blogs_query, &[&$keyset.created_at, &$keyset.uid, &$keyset.fetch_limit()])
+            .await?;
 
         let blogs = rows
             .iter()
@@ -15,6 +21,9 @@ macro_rules! fetch_home_blogs {
                 title: row.get(2),
                 images: row.get(3),
                 created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
             })
             .collect::<Vec<Blog>>();
 
@@ -24,11 +33,19 @@ macro_rules! fetch_home_blogs {
 
 #[macro_export]
 macro_rules! fetch_blogs_by_user_id {
-    ($conn:expr, $user_id:expr) => {{
+    ($conn:expr, $user_id:expr, $viewer:expr, $keyset:expr) => {{
         use tokio_postgres::Row;
 
-        let query = "SELECT uid, user_id, title, images, created_at FROM blogs WHERE deleted_at IS NULL AND user_id = $1";
-        let rows: Vec<Row> = $conn.query(query, &[&$user_id]).await?;
+        let query = format!("SELECT uid, user_id, title, images, created_at, status, like_count, bookmark_count FROM blogs WHERE deleted_at IS NULL AND user_id = $1 \
+                     AND (status = 'published' OR user_id = $5::int4) \
+                     AND {after} \
+                     {order} LIMIT $4",
+            after = $keyset.after("blogs", "", 2),
+            order = $keyset.order_by(""),
+        );
+        let rows: Vec<Row> = $conn
+            .query(&query, &[&$user_id, &$keyset.created_at, &$keyset.uid, &$keyset.fetch_limit(), &$viewer])
+            .await?;
 
         let blogs = rows
             .iter()
@@ -38,9 +55,46 @@ macro_rules! fetch_blogs_by_user_id {
                 title: row.get(2),
                 images: row.get(3),
                 created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
             })
             .collect::<Vec<Blog>>();
 
         Ok::<_, AppError>(blogs)
     }};
-}
\ No newline at end of file
+}
+
+#[macro_export]
+macro_rules! fetch_blogs_by_tag {
+    ($conn:expr, $tag:expr, $keyset:expr) => {{
+        use tokio_postgres::Row;
+
+        let query = format!("SELECT b.uid, b.user_id, b.title, b.images, b.created_at, b.status, b.like_count, b.bookmark_count FROM blogs b \
+                     WHERE b.deleted_at IS NULL AND b.status = 'published' AND EXISTS (SELECT 1 FROM blog_tags t WHERE t.doc_id = b.uid AND t.tag = $1) \
+                     AND {after} \
+                     {order} LIMIT $4",
+            after = $keyset.after("blogs", "b.", 2),
+            order = $keyset.order_by("b."),
+        );
+        let rows: Vec<Row> = $conn
+            .query(&query, &[&$tag, &$keyset.created_at, &$keyset.uid, &$keyset.fetch_limit()])
+            .await?;
+
+        let blogs = rows
+            .iter()
+            .map(|row| Blog {
+                uid: row.get(0),
+                user_id: row.get(1),
+                title: row.get(2),
+                images: row.get(3),
+                created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
+            })
+            .collect::<Vec<Blog>>();
+
+        Ok::<_, AppError>(blogs)
+    }};
+}
@@ -1,10 +1,18 @@
 #[macro_export]
 macro_rules! fetch_books_by_user_id {
-    ($conn:expr, $user_id:expr) => {{
+    ($conn:expr, $user_id:expr, $viewer:expr, $keyset:expr) => {{
         use tokio_postgres::Row;
 
-        let query = "SELECT uid, user_id, title, images, created_at FROM books WHERE deleted_at IS NULL AND user_id = $1";
-        let rows: Vec<Row> = $conn.query(query, &[&$user_id]).await?;
+        let query = format!("SELECT uid, user_id, title, images, created_at, status, like_count, bookmark_count FROM books WHERE deleted_at IS NULL AND user_id = $1 \
+                     AND (status = 'published' OR user_id = $5::int4) \
+                     AND {after} \
+                     {order} LIMIT $4",
+            after = $keyset.after("books", "", 2),
+            order = $keyset.order_by(""),
+        );
+        let rows: Vec<Row> = $conn
+            .query(&query, &[&$user_id, &$keyset.created_at, &$keyset.uid, &$keyset.fetch_limit(), &$viewer])
+            .await?;
 
         let books = rows
             .iter()
@@ -14,6 +22,9 @@ macro_rules! fetch_books_by_user_id {
                 title: row.get(2),
                 images: row.get(3),
                 created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
             })
             .collect::<Vec<Book>>();
 
@@ -26,7 +37,7 @@ macro_rules! fetch_books_by_doc_ids {
     ($conn:expr, $doc_ids:expr) => {{
         use tokio_postgres::Row;
         
-        let books_query = "SELECT uid, user_id, title, images, created_at FROM books where uid=ANY($1) AND deleted_at is NULL";
+        let books_query = "SELECT uid, user_id, title, images, created_at, status, like_count, bookmark_count FROM books where uid=ANY($1) AND deleted_at is NULL AND status = 'published'";
         let rows: Vec<Row> = $conn.query(books_query, &[&$doc_ids]).await?;
 
         let books = rows
@@ -37,6 +48,9 @@ macro_rules! fetch_books_by_doc_ids {
                 title: row.get(2),
                 images: row.get(3),
                 created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
             })
             .collect::<Vec<Book>>();
 
@@ -90,11 +104,51 @@ macro_rules! fetch_book_pages {
 
 #[macro_export]
 macro_rules! fetch_home_books {
-    ($conn:expr) => {{
+    ($conn:expr, $keyset:expr) => {{
         use tokio_postgres::Row;
         
-        let books_query = "SELECT uid, user_id, title, images, created_at FROM books where deleted_at is NULL LIMIT 5";
-        let rows: Vec<Row> = $conn.query(books_query, &[]).await?;
+        let books_query = format!("SELECT uid, user_id, title, images, created_at, status, like_count, bookmark_count FROM books where deleted_at is NULL AND status = 'published' \
+                           AND {after} \
+                           {order} LIMIT $3",
+            after = $keyset.after("books", "", 1),
+            order = $keyset.order_by(""),
+        );
+        let rows: Vec<Row> = $conn
+            .query(&books_query, &[&$keyset.created_at, &$keyset.uid, &$keyset.fetch_limit()])
+            .await?;
+
+        let books = rows
+            .iter()
+            .map(|row| Book {
+                uid: row.get(0),
+                user_id: row.get(1),
+                title: row.get(2),
+                images: row.get(3),
+                created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
+            })
+            .collect::<Vec<Book>>();
+
+        Ok::<_, AppError>(books)
+    }};
+}
+#[macro_export]
+macro_rules! fetch_books_by_tag {
+    ($conn:expr, $tag:expr, $keyset:expr) => {{
+        use tokio_postgres::Row;
+
+        let query = format!("SELECT b.uid, b.user_id, b.title, b.images, b.created_at, b.status, b.like_count, b.bookmark_count FROM books b \
+                     WHERE b.deleted_at IS NULL AND b.status = 'published' AND EXISTS (SELECT 1 FROM book_tags t WHERE t.doc_id = b.uid AND t.tag = $1) \
+                     AND {after} \
+                     {order} LIMIT $4",
+            after = $keyset.after("books", "b.", 2),
+            order = $keyset.order_by("b."),
+        );
+        let rows: Vec<Row> = $conn
+            .query(&query, &[&$tag, &$keyset.created_at, &$keyset.uid, &$keyset.fetch_limit()])
+            .await?;
 
         let books = rows
             .iter()
@@ -104,9 +158,12 @@ macro_rules! fetch_home_books {
                 title: row.get(2),
                 images: row.get(3),
                 created_at: row.get(4),
+                status: row.get(5),
+                like_count: row.get(6),
+                bookmark_count: row.get(7),
             })
             .collect::<Vec<Book>>();
 
         Ok::<_, AppError>(books)
     }};
-}
\ No newline at end of file
+}
@@ -0,0 +1,283 @@
+//! Likes and bookmarks on blogs and books. Each is one row per user and
+//! document; `like_count` / `bookmark_count` on `blogs` and `books` are kept
+//! in step in the same transaction so listings can sort on them.
+
+use crate::error::AppError;
+use crate::lifecycle::ensure_visible;
+use crate::utils::doc::TagTable;
+use crate::utils::page::{PageRequest, DEFAULT_PAGE_SIZE};
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Clone, Copy)]
+enum Reaction {
+    Like,
+    Bookmark,
+}
+
+impl Reaction {
+    fn table(&self) -> &'static str {
+        match self {
+            Reaction::Like => "likes",
+            Reaction::Bookmark => "bookmarks",
+        }
+    }
+
+    fn counter(&self) -> &'static str {
+        match self {
+            Reaction::Like => "like_count",
+            Reaction::Bookmark => "bookmark_count",
+        }
+    }
+
+    /// Response key saying whether the caller now has the reaction.
+    fn state_key(&self) -> &'static str {
+        match self {
+            Reaction::Like => "liked",
+            Reaction::Bookmark => "bookmarked",
+        }
+    }
+}
+
+/// Add (`on`) or remove the caller's reaction. Both directions are
+/// idempotent; the counter only moves when a row was inserted or deleted.
+async fn react(
+    pool: &AppState,
+    table: TagTable,
+    reaction: Reaction,
+    user_id: i32,
+    doc_id: i32,
+    on: bool,
+) -> Result<serde_json::Value, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    if on {
+        ensure_visible(&conn, table, doc_id, Some(user_id)).await?;
+    }
+
+    let transaction = conn.transaction().await?;
+    let changed = if on {
+        transaction
+            .execute(
+                &format!(
+                    "INSERT INTO {}(doc_type, doc_id, user_id) VALUES($1, $2, $3) ON CONFLICT DO NOTHING",
+                    reaction.table()
+                ),
+                &[&table.doc_type(), &doc_id, &user_id],
+            )
+            .await?
+    } else {
+        transaction
+            .execute(
+                &format!(
+                    "DELETE FROM {} WHERE doc_type = $1 AND doc_id = $2 AND user_id = $3",
+                    reaction.table()
+                ),
+                &[&table.doc_type(), &doc_id, &user_id],
+            )
+            .await?
+    };
+    let delta: i32 = match (changed, on) {
+        (0, _) => 0,
+        (_, true) => 1,
+        (_, false) => -1,
+    };
+    let row = transaction
+        .query_opt(
+            &format!(
+                "UPDATE {table} SET {counter} = GREATEST({counter} + $1, 0) WHERE uid = $2 RETURNING {counter}",
+                table = table.doc_table(),
+                counter = reaction.counter()
+            ),
+            &[&delta, &doc_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Document {doc_id} not found")))?;
+    let count: i32 = row.get(0);
+    transaction.commit().await?;
+
+    let mut result = json!({ "doc_id": doc_id });
+    result[reaction.state_key()] = json!(on);
+    result[reaction.counter()] = json!(count);
+    Ok(result)
+}
+
+#[derive(Serialize)]
+pub struct Bookmark {
+    uid: i32,
+    doc_type: i16,
+    doc_id: i32,
+    user_id: i32,
+    title: String,
+    images: Option<String>,
+    like_count: i32,
+    bookmark_count: i32,
+    created_at: DateTime<Utc>,
+}
+
+/// GET /user/bookmarks — the caller's bookmarks, newest first. Documents that
+/// were deleted or are no longer visible to the caller are left out.
+pub async fn get_bookmarks(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let rows = conn
+        .query(
+            "SELECT m.uid, m.doc_type, m.doc_id, d.user_id, d.title, d.images, d.like_count, d.bookmark_count, m.created_at \
+             FROM bookmarks m \
+             JOIN LATERAL ( \
+                 SELECT user_id, title, images, like_count, bookmark_count, status, deleted_at FROM blogs WHERE m.doc_type = 1 AND uid = m.doc_id \
+                 UNION ALL \
+                 SELECT user_id, title, images, like_count, bookmark_count, status, deleted_at FROM books WHERE m.doc_type = 2 AND uid = m.doc_id \
+             ) d ON true \
+             WHERE m.user_id = $1 AND d.deleted_at IS NULL AND (d.status IN ('published', 'unlisted') OR d.user_id = $1) \
+             AND ($2::timestamptz IS NULL OR (m.created_at, m.uid) < ($2::timestamptz, $3::int4)) \
+             ORDER BY m.created_at DESC, m.uid DESC LIMIT $4",
+            &[&user_id, &keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
+        )
+        .await?;
+
+    let bookmarks = rows
+        .iter()
+        .map(|row| Bookmark {
+            uid: row.get(0),
+            doc_type: row.get(1),
+            doc_id: row.get(2),
+            user_id: row.get(3),
+            title: row.get(4),
+            images: row.get(5),
+            like_count: row.get(6),
+            bookmark_count: row.get(7),
+            created_at: row.get(8),
+        })
+        .collect::<Vec<Bookmark>>();
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(keyset.page(bookmarks, |b| (b.created_at, b.uid))),
+    ))
+}
+
+/// POST /blog/:doc_id/like
+pub async fn like_blog(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Blog, Reaction::Like, user_id, doc_id, true).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /blog/:doc_id/unlike
+pub async fn unlike_blog(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Blog, Reaction::Like, user_id, doc_id, false).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /blog/:doc_id/bookmark
+pub async fn bookmark_blog(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Blog, Reaction::Bookmark, user_id, doc_id, true).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /blog/:doc_id/unbookmark
+pub async fn unbookmark_blog(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Blog, Reaction::Bookmark, user_id, doc_id, false).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/:doc_id/like
+pub async fn like_book(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Book, Reaction::Like, user_id, doc_id, true).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/:doc_id/unlike
+pub async fn unlike_book(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Book, Reaction::Like, user_id, doc_id, false).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/:doc_id/bookmark
+pub async fn bookmark_book(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Book, Reaction::Bookmark, user_id, doc_id, true).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
+
+/// POST /book/:doc_id/unbookmark
+pub async fn unbookmark_book(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(doc_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = react(&pool, TagTable::Book, Reaction::Bookmark, user_id, doc_id, false).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(result),
+    ))
+}
@@ -0,0 +1,267 @@
+pub mod score;
+
+use crate::error::AppError;
+use crate::utils::doc::{normalize_tags, TagTable};
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tokio_postgres::Client;
+
+use score::{rank, Candidate, Profile};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+/// How much recent history feeds a reader's profile.
+const HISTORY_VIEWS: i64 = 200;
+const HISTORY_SEARCHES: i64 = 50;
+/// Upper bound on candidate documents scored per request.
+const MAX_CANDIDATES: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct RecommendRequest {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct RecommendedDoc {
+    uid: i32,
+    user_id: i32,
+    title: String,
+    images: Option<String>,
+    created_at: DateTime<Utc>,
+    score: f64,
+}
+
+/// Record that `user_id` opened a blog or book. Best-effort: failures are
+/// logged, never surfaced to the reader.
+pub async fn record_view(conn: &Client, table: TagTable, user_id: i32, doc_id: i32) {
+    let result = conn
+        .execute(
+            "INSERT INTO interactions(user_id, doc_id, doc_type, interaction_type) VALUES($1, $2, $3, 'view')",
+            &[&user_id, &doc_id, &table.doc_type()],
+        )
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(user_id, doc_id, error = %e, "could not record view");
+    }
+}
+
+/// Record a search query for `user_id`. Best-effort, like `record_view`.
+pub async fn record_search(conn: &Client, user_id: i32, query: &str) {
+    let result = conn
+        .execute(
+            "INSERT INTO search_history(user_id, search_query) VALUES($1, $2)",
+            &[&user_id, &query],
+        )
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(user_id, error = %e, "could not record search");
+    }
+}
+
+async fn load_profile(conn: &Client, table: TagTable, user_id: i32) -> Result<Profile, AppError> {
+    let mut profile = Profile::default();
+
+    let views = conn
+        .query(
+            &format!(
+                "SELECT i.doc_id, d.user_id FROM interactions i JOIN {} d ON d.uid = i.doc_id \
+                 WHERE i.user_id = $1 AND i.doc_type = $2 AND i.interaction_type = 'view' \
+                 ORDER BY i.created_at DESC LIMIT $3",
+                table.doc_table()
+            ),
+            &[&user_id, &table.doc_type(), &HISTORY_VIEWS],
+        )
+        .await?;
+    let viewed: Vec<i32> = views.iter().map(|row| row.get(0)).collect();
+    let viewed_tags = fetch_tags_by_doc(conn, table, &viewed).await?;
+    for row in views.iter() {
+        let doc_id: i32 = row.get(0);
+        let tags = viewed_tags.get(&doc_id).map(Vec::as_slice).unwrap_or_default();
+        profile.record_view(doc_id, row.get(1), tags);
+    }
+
+    let searches = conn
+        .query(
+            "SELECT search_query FROM search_history WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2",
+            &[&user_id, &HISTORY_SEARCHES],
+        )
+        .await?;
+    for row in searches.iter() {
+        let query: String = row.get(0);
+        let terms: Vec<String> = query.split_whitespace().map(String::from).collect();
+        profile.record_search(&normalize_tags(&terms));
+    }
+
+    let follows = conn
+        .query("SELECT subscribed_id FROM subscription WHERE user_id = $1", &[&user_id])
+        .await?;
+    for row in follows.iter() {
+        profile.record_follow(row.get(0));
+    }
+
+    Ok(profile)
+}
+
+async fn fetch_tags_by_doc(
+    conn: &Client,
+    table: TagTable,
+    doc_ids: &[i32],
+) -> Result<HashMap<i32, Vec<String>>, AppError> {
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    if doc_ids.is_empty() {
+        return Ok(tags);
+    }
+    let rows = conn
+        .query(
+            &format!("SELECT doc_id, tag FROM {} WHERE doc_id = ANY($1)", table.table()),
+            &[&doc_ids],
+        )
+        .await?;
+    for row in rows.iter() {
+        tags.entry(row.get(0)).or_default().push(row.get(1));
+    }
+    Ok(tags)
+}
+
+/// Live documents by someone else that share a tag or an author with the profile.
+async fn load_candidates(
+    conn: &Client,
+    table: TagTable,
+    user_id: i32,
+    profile: &Profile,
+) -> Result<Vec<Candidate<RecommendedDoc>>, AppError> {
+    let rows = conn
+        .query(
+            &format!(
+                "SELECT d.uid, d.user_id, d.title, d.images, d.created_at FROM {docs} d \
+                 WHERE d.deleted_at IS NULL AND d.status = 'published' AND d.user_id <> $1 \
+                 AND (d.user_id = ANY($2) OR EXISTS (SELECT 1 FROM {tags} t WHERE t.doc_id = d.uid AND t.tag = ANY($3))) \
+                 ORDER BY d.created_at DESC, d.uid DESC LIMIT $4",
+                docs = table.doc_table(),
+                tags = table.table()
+            ),
+            &[&user_id, &profile.authors(), &profile.tags(), &MAX_CANDIDATES],
+        )
+        .await?;
+    let doc_ids: Vec<i32> = rows.iter().map(|row| row.get(0)).collect();
+    let mut tags = fetch_tags_by_doc(conn, table, &doc_ids).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let doc = RecommendedDoc {
+                uid: row.get(0),
+                user_id: row.get(1),
+                title: row.get(2),
+                images: row.get(3),
+                created_at: row.get(4),
+                score: 0.0,
+            };
+            Candidate {
+                doc_id: doc.uid,
+                author: doc.user_id,
+                created_at: doc.created_at,
+                tags: tags.remove(&doc.uid).unwrap_or_default(),
+                item: doc,
+            }
+        })
+        .collect())
+}
+
+/// Newest documents, used for anonymous readers and cold-start profiles.
+async fn load_latest(conn: &Client, table: TagTable, limit: usize) -> Result<Vec<RecommendedDoc>, AppError> {
+    let rows = conn
+        .query(
+            &format!(
+                "SELECT uid, user_id, title, images, created_at FROM {} WHERE deleted_at IS NULL AND status = 'published' \
+                 ORDER BY created_at DESC, uid DESC LIMIT $1",
+                table.doc_table()
+            ),
+            &[&(limit as i64)],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| RecommendedDoc {
+            uid: row.get(0),
+            user_id: row.get(1),
+            title: row.get(2),
+            images: row.get(3),
+            created_at: row.get(4),
+            score: 0.0,
+        })
+        .collect())
+}
+
+async fn recommend(
+    pool: &AppState,
+    table: TagTable,
+    user: Option<UserId>,
+    limit: Option<usize>,
+) -> Result<serde_json::Value, AppError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let conn = pool.pg_pool.conn.get().await?;
+
+    let mut items: Vec<RecommendedDoc> = Vec::new();
+    if let Some(UserId(user_id)) = user {
+        let profile = load_profile(&conn, table, user_id).await?;
+        if !profile.is_empty() {
+            let candidates = load_candidates(&conn, table, user_id, &profile).await?;
+            items = rank(&profile, candidates, limit)
+                .into_iter()
+                .map(|(mut doc, score)| {
+                    doc.score = score;
+                    doc
+                })
+                .collect();
+        }
+    }
+
+    let source = if items.is_empty() {
+        items = load_latest(&conn, table, limit).await?;
+        "latest"
+    } else {
+        "personalised"
+    };
+
+    Ok(json!({
+        "source": source,
+        "items": items
+    }))
+}
+
+pub async fn get_recommended_blogs(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    query: Query<RecommendRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let recommended = recommend(&pool, TagTable::Blog, user.map(|Extension(u)| u), query.limit).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(recommended),
+    ))
+}
+
+pub async fn get_recommended_books(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    query: Query<RecommendRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let recommended = recommend(&pool, TagTable::Book, user.map(|Extension(u)| u), query.limit).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(recommended),
+    ))
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Weight of one unit of tag affinity in a candidate's score.
+pub const TAG_WEIGHT: f64 = 1.0;
+/// Weight of one unit of author affinity in a candidate's score.
+pub const AUTHOR_WEIGHT: f64 = 2.0;
+
+/// Affinity added per viewed document (to each of its tags and its author).
+const VIEW_SIGNAL: f64 = 1.0;
+/// Affinity added to a tag per search containing it.
+const SEARCH_SIGNAL: f64 = 0.5;
+/// Affinity added to an author the reader follows.
+const FOLLOW_SIGNAL: f64 = 3.0;
+
+/// What we know about a reader's interests. Ordered maps keep every
+/// derived list (and therefore every SQL parameter) deterministic.
+#[derive(Default)]
+pub struct Profile {
+    tags: BTreeMap<String, f64>,
+    authors: BTreeMap<i32, f64>,
+    seen: BTreeSet<i32>,
+}
+
+impl Profile {
+    pub fn record_view(&mut self, doc_id: i32, author: i32, tags: &[String]) {
+        self.seen.insert(doc_id);
+        *self.authors.entry(author).or_default() += VIEW_SIGNAL;
+        for tag in tags {
+            *self.tags.entry(tag.clone()).or_default() += VIEW_SIGNAL;
+        }
+    }
+
+    /// `terms` are already normalised (see `utils::doc::normalize_tags`).
+    pub fn record_search(&mut self, terms: &[String]) {
+        for term in terms {
+            *self.tags.entry(term.clone()).or_default() += SEARCH_SIGNAL;
+        }
+    }
+
+    pub fn record_follow(&mut self, author: i32) {
+        *self.authors.entry(author).or_default() += FOLLOW_SIGNAL;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.authors.is_empty()
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        self.tags.keys().cloned().collect()
+    }
+
+    pub fn authors(&self) -> Vec<i32> {
+        self.authors.keys().copied().collect()
+    }
+
+    pub fn has_seen(&self, doc_id: i32) -> bool {
+        self.seen.contains(&doc_id)
+    }
+
+    /// Tag overlap plus author affinity. Tags are de-duplicated so a document
+    /// cannot inflate its score by repeating one tag.
+    pub fn score(&self, author: i32, tags: &[String]) -> f64 {
+        let tag_score: f64 = tags
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .sum();
+        let author_score = self.authors.get(&author).copied().unwrap_or_default();
+        TAG_WEIGHT * tag_score + AUTHOR_WEIGHT * author_score
+    }
+}
+
+pub struct Candidate<T> {
+    pub doc_id: i32,
+    pub author: i32,
+    pub created_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub item: T,
+}
+
+/// Score candidates, drop already-seen and zero-score ones, and order by
+/// score, then recency, then doc_id — so equal inputs always rank equally.
+pub fn rank<T>(profile: &Profile, candidates: Vec<Candidate<T>>, limit: usize) -> Vec<(T, f64)> {
+    let mut scored: Vec<(f64, DateTime<Utc>, i32, T)> = candidates
+        .into_iter()
+        .filter(|c| !profile.has_seen(c.doc_id))
+        .map(|c| (profile.score(c.author, &c.tags), c.created_at, c.doc_id, c.item))
+        .filter(|(score, ..)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then_with(|| b.1.cmp(&a.1))
+            .then_with(|| b.2.cmp(&a.2))
+    });
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(score, _, _, item)| (item, score))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn tags(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn candidate(doc_id: i32, author: i32, secs: i64, raw: &[&str]) -> Candidate<i32> {
+        Candidate {
+            doc_id,
+            author,
+            created_at: Utc.timestamp_opt(secs, 0).unwrap(),
+            tags: tags(raw),
+            item: doc_id,
+        }
+    }
+
+    #[test]
+    fn test_score_combines_tag_overlap_and_author_affinity() {
+        let mut profile = Profile::default();
+        profile.record_view(1, 10, &tags(&["rust", "async"]));
+        profile.record_search(&tags(&["rust"]));
+        profile.record_follow(20);
+
+        // rust: 1.0 view + 0.5 search; author 10: 1.0 view
+        assert_eq!(profile.score(10, &tags(&["rust", "rust"])), 1.5 + 2.0);
+        // author 20 followed: 3.0 * AUTHOR_WEIGHT
+        assert_eq!(profile.score(20, &tags(&["cooking"])), 6.0);
+        assert_eq!(profile.score(30, &tags(&["cooking"])), 0.0);
+    }
+
+    #[test]
+    fn test_rank_is_deterministic_and_skips_seen() {
+        let mut profile = Profile::default();
+        profile.record_view(1, 10, &tags(&["rust"]));
+
+        let ranked = rank(
+            &profile,
+            vec![
+                candidate(1, 10, 500, &["rust"]),  // already seen
+                candidate(2, 99, 100, &["rust"]),  // 1.0
+                candidate(3, 99, 200, &["rust"]),  // 1.0, newer
+                candidate(4, 10, 100, &["rust"]),  // 1.0 + 2.0
+                candidate(5, 99, 300, &["golf"]),  // 0.0
+            ],
+            10,
+        );
+        let order: Vec<i32> = ranked.iter().map(|(doc_id, _)| *doc_id).collect();
+        assert_eq!(order, vec![4, 3, 2]);
+    }
+}
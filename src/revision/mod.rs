@@ -0,0 +1,383 @@
+use crate::book::collaborators::{require_book_role, CollaboratorRole};
+use crate::error::AppError;
+use crate::search::index::{DocKind, IndexNode};
+use crate::utils::content::ContentType;
+use crate::utils::doc::TagTable;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use similar::TextDiff;
+use tokio_postgres::{Client, Transaction};
+
+/// Lines of unchanged context around each hunk in a unified diff.
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Serialize)]
+pub struct Revision {
+    uid: i32,
+    node_id: i32,
+    doc_id: i32,
+    user_id: i32,
+    title: String,
+    restored_from: Option<i32>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct DiffRequest {
+    from: i32,
+    to: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    revision_id: i32,
+}
+
+fn doc_kind(table: TagTable) -> DocKind {
+    match table {
+        TagTable::Blog => DocKind::Blog,
+        TagTable::Book => DocKind::Book,
+    }
+}
+
+/// Keep a node's pre-edit state as its first revision. Call inside the edit
+/// transaction before the `UPDATE`; a no-op once the node has history.
+pub async fn snapshot_original(tx: &Transaction<'_>, table: TagTable, node_id: i32) -> Result<(), AppError> {
+    let author = match table {
+        TagTable::Blog => "user_id",
+        TagTable::Book => "COALESCE(updated_by, user_id)",
+    };
+    tx.execute(
+        &format!(
+            "INSERT INTO node_revisions(doc_type, node_id, doc_id, user_id, title, content, images, content_type) \
+             SELECT $1, uid, doc_id, {author}, title, content, images, content_type FROM {nodes} \
+             WHERE uid = $2 AND NOT EXISTS (SELECT 1 FROM node_revisions WHERE doc_type = $1 AND node_id = $2)",
+            nodes = table.node_table()
+        ),
+        &[&table.doc_type(), &node_id],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Append the node's current (just written) state as an immutable revision.
+pub async fn record_revision(
+    tx: &Transaction<'_>,
+    table: TagTable,
+    node_id: i32,
+    user_id: i32,
+    restored_from: Option<i32>,
+) -> Result<i32, AppError> {
+    let row = tx
+        .query_one(
+            &format!(
+                "INSERT INTO node_revisions(doc_type, node_id, doc_id, user_id, title, content, images, content_type, restored_from) \
+                 SELECT $1, uid, doc_id, $3, title, content, images, content_type, $4 FROM {} WHERE uid = $2 RETURNING uid",
+                table.node_table()
+            ),
+            &[&table.doc_type(), &node_id, &user_id, &restored_from],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Blogs are single-author; books go through collaborator roles. Viewing
+/// history needs read access to the document, restoring needs edit access.
+pub(crate) async fn require_access(
+    conn: &Client,
+    table: TagTable,
+    doc_id: i32,
+    user_id: i32,
+    write: bool,
+) -> Result<(), AppError> {
+    match table {
+        TagTable::Blog => {
+            let owner = conn
+                .query_opt(
+                    "SELECT user_id FROM blogs WHERE uid = $1 AND deleted_at IS NULL",
+                    &[&doc_id],
+                )
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("Blog {doc_id} not found")))?;
+            let owner: i32 = owner.get(0);
+            if owner != user_id {
+                return Err(AppError::Error((
+                    StatusCode::FORBIDDEN,
+                    format!("Not the author of blog {doc_id}"),
+                )));
+            }
+        }
+        TagTable::Book => {
+            let needed = if write { CollaboratorRole::Editor } else { CollaboratorRole::Viewer };
+            require_book_role(conn, doc_id, user_id, needed).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn node_doc_id(conn: &Client, table: TagTable, node_id: i32) -> Result<i32, AppError> {
+    let row = conn
+        .query_opt(
+            &format!("SELECT doc_id FROM {} WHERE uid = $1", table.node_table()),
+            &[&node_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Node {node_id} not found")))?;
+    Ok(row.get(0))
+}
+
+/// Unified diff of `old` → `new`, labelled with revision ids.
+pub fn unified_diff(from: i32, to: i32, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT)
+        .header(&format!("revision {from}"), &format!("revision {to}"))
+        .to_string()
+}
+
+async fn list_revisions(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    node_id: i32,
+) -> Result<serde_json::Value, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    let doc_id = node_doc_id(&conn, table, node_id).await?;
+    require_access(&conn, table, doc_id, user_id, false).await?;
+
+    let rows = conn
+        .query(
+            "SELECT uid, node_id, doc_id, user_id, title, restored_from, created_at FROM node_revisions \
+             WHERE doc_type = $1 AND node_id = $2 ORDER BY uid DESC",
+            &[&table.doc_type(), &node_id],
+        )
+        .await?;
+    let revisions: Vec<Revision> = rows
+        .iter()
+        .map(|row| Revision {
+            uid: row.get(0),
+            node_id: row.get(1),
+            doc_id: row.get(2),
+            user_id: row.get(3),
+            title: row.get(4),
+            restored_from: row.get(5),
+            created_at: row.get(6),
+        })
+        .collect();
+
+    Ok(json!({
+        "node_id": node_id,
+        "revisions": revisions
+    }))
+}
+
+async fn diff_revisions(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    request: &DiffRequest,
+) -> Result<serde_json::Value, AppError> {
+    let conn = pool.pg_pool.conn.get().await?;
+    let rows = conn
+        .query(
+            "SELECT uid, node_id, doc_id, title, content FROM node_revisions WHERE doc_type = $1 AND uid = ANY($2)",
+            &[&table.doc_type(), &vec![request.from, request.to]],
+        )
+        .await?;
+    let find = |uid: i32| {
+        rows.iter()
+            .find(|row| row.get::<_, i32>(0) == uid)
+            .ok_or_else(|| AppError::NotFound(format!("Revision {uid} not found")))
+    };
+    let (from, to) = (find(request.from)?, find(request.to)?);
+    let node_id: i32 = from.get(1);
+    if to.get::<_, i32>(1) != node_id {
+        return Err(AppError::BadRequest("Revisions belong to different nodes".into()));
+    }
+    require_access(&conn, table, from.get(2), user_id, false).await?;
+
+    let (old_title, new_title): (String, String) = (from.get(3), to.get(3));
+    let (old_content, new_content): (String, String) = (from.get(4), to.get(4));
+
+    Ok(json!({
+        "node_id": node_id,
+        "from": request.from,
+        "to": request.to,
+        "title": (old_title != new_title).then(|| json!({ "from": old_title, "to": new_title })),
+        "diff": unified_diff(request.from, request.to, &old_content, &new_content)
+    }))
+}
+
+/// Write a revision's content and content type back to its node (and to the
+/// document row when the node is the main one), recording the restore as a
+/// new revision. Revisions from before `content_type` was recorded take the
+/// type implied by their content.
+async fn restore_revision(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    revision_id: i32,
+) -> Result<serde_json::Value, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let revision = conn
+        .query_opt(
+            "SELECT node_id, doc_id, title, content, images, content_type FROM node_revisions WHERE doc_type = $1 AND uid = $2",
+            &[&table.doc_type(), &revision_id],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Revision {revision_id} not found")))?;
+    let node_id: i32 = revision.get(0);
+    let doc_id: i32 = revision.get(1);
+    let title: String = revision.get(2);
+    let content: String = revision.get(3);
+    let images: Option<String> = revision.get(4);
+    let content_type = match revision.get::<_, Option<i16>>(5) {
+        Some(code) => ContentType::from_code(code),
+        None => ContentType::resolve(None, &content),
+    };
+    require_access(&conn, table, doc_id, user_id, true).await?;
+    content_type.validate(&content)?;
+
+    let transaction = conn.transaction().await?;
+    snapshot_original(&transaction, table, node_id).await?;
+    // Returns the node's author, whether it is the main node, and its identity.
+    let updated = match table {
+        TagTable::Blog => {
+            transaction
+                .query_opt(
+                    "UPDATE blog SET title=$1, content=$2, images=$3, content_type=$6 WHERE uid=$4 AND doc_id=$5 AND deleted_at IS NULL \
+                     RETURNING user_id, parent_id IS NULL, NULL::smallint",
+                    &[&title, &content, &images, &node_id, &doc_id, &content_type.code()],
+                )
+                .await?
+        }
+        TagTable::Book => {
+            transaction
+                .query_opt(
+                    "UPDATE book SET title=$1, content=$2, images=$3, updated_by=$6, content_type=$7 WHERE uid=$4 AND doc_id=$5 AND deleted_at IS NULL \
+                     RETURNING user_id, identity = 100, identity",
+                    &[&title, &content, &images, &node_id, &doc_id, &user_id, &content_type.code()],
+                )
+                .await?
+        }
+    }
+    .ok_or_else(|| AppError::NotFound(format!("Node {node_id} is deleted")))?;
+    let author: i32 = updated.get(0);
+    let is_main: bool = updated.get(1);
+    let identity: Option<i16> = updated.get(2);
+
+    if is_main {
+        transaction
+            .execute(
+                &format!(
+                    "UPDATE {} SET title=$1, content=$2, images=$3 WHERE uid=$4",
+                    table.doc_table()
+                ),
+                &[&title, &content, &images, &doc_id],
+            )
+            .await?;
+    }
+    let new_revision = record_revision(&transaction, table, node_id, user_id, Some(revision_id)).await?;
+    transaction.commit().await?;
+
+    pool.search().upsert(&[IndexNode {
+        kind: doc_kind(table),
+        uid: node_id,
+        doc_id,
+        user_id: author,
+        identity,
+        title: &title,
+        content: &content,
+    }]);
+
+    Ok(json!({
+        "node_id": node_id,
+        "doc_id": doc_id,
+        "restored_from": revision_id,
+        "revision_id": new_revision,
+        "title": title,
+        "content": content,
+        "content_type": content_type,
+        "images": images
+    }))
+}
+
+fn ok(body: serde_json::Value) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(body),
+    )
+}
+
+pub async fn get_blog_revisions(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(node_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(list_revisions(&pool, TagTable::Blog, user_id, node_id).await?))
+}
+
+pub async fn get_book_revisions(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    AxumPath(node_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(list_revisions(&pool, TagTable::Book, user_id, node_id).await?))
+}
+
+pub async fn get_blog_revision_diff(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    query: Query<DiffRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(diff_revisions(&pool, TagTable::Blog, user_id, &query).await?))
+}
+
+pub async fn get_book_revision_diff(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    query: Query<DiffRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(diff_revisions(&pool, TagTable::Book, user_id, &query).await?))
+}
+
+pub async fn restore_blog_revision(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<RestoreRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(restore_revision(&pool, TagTable::Blog, user_id, body.revision_id).await?))
+}
+
+pub async fn restore_book_revision(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<RestoreRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(restore_revision(&pool, TagTable::Book, user_id, body.revision_id).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff(1, 2, "one\ntwo\nthree\n", "one\n2\nthree\n");
+        assert_eq!(
+            diff,
+            "--- revision 1\n+++ revision 2\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+        assert_eq!(unified_diff(1, 2, "same\n", "same\n"), "");
+    }
+}
@@ -1,18 +1,41 @@
-use crate::blog::get::get_home_blogs;
-use crate::book::get::{get_book_chapters_and_sections, get_chapter_details, get_home_books, get_section_details};
-use crate::user::{get_subscribed_users, subscribe_user, un_subscribe_user};
+use crate::blog::get::{get_blog_node_html, get_blog_tag_cloud, get_blogs_by_tag, get_home_blogs};
+use crate::book::get::{
+    get_book_chapters_and_sections, get_book_tag_cloud, get_books_by_tag, get_chapter_details,
+    get_book_node_html, get_home_books, get_section_details,
+};
+use crate::user::{get_permissions, get_subscribed_users, subscribe_user, un_subscribe_user};
+use crate::user::follow::{get_follow_summary, get_followers, get_following, get_mutuals};
+use crate::user::profile::{edit_profile, get_profile};
 use crate::{
     blog::{
         delete::{
             delete_blog, delete_blog_node
         },
-        append_blog_node, create_blog, edit_blog, edit_blog_node,
+        append_blog_node, create_blog, edit_blog, edit_blog_node, move_blog_node,
         get::{
-            get_all_blog_nodes, get_all_blogs_by_page_no, get_all_blogs_by_user_id, get_users_blog,
+            get_all_blog_nodes, get_all_blogs, get_all_blogs_by_user_id, get_users_blog,
         },
+        upload::upload_blog,
     },
 };
-use crate::middleware::require_auth;
+use crate::admin::{
+    moderate_delete_blog, moderate_delete_book, moderate_restore_blog, moderate_restore_book,
+};
+use crate::auth::permissions::Permission;
+use crate::comment::{
+    create_blog_comment, create_book_comment, delete_blog_comment, delete_book_comment,
+    edit_blog_comment, edit_book_comment, get_blog_comments, get_book_comments,
+};
+use crate::lifecycle::{set_blog_status, set_book_status};
+use crate::trash::{
+    get_blog_trash, get_book_trash, restore_blog, restore_blog_node, restore_book, restore_book_node,
+};
+use crate::middleware::{optional_auth, require_auth, require_permission};
+use crate::progress::{get_book_progress, get_continue_reading, save_book_progress};
+use crate::reaction::{
+    bookmark_blog, bookmark_book, get_bookmarks, like_blog, like_book, unbookmark_blog,
+    unbookmark_book, unlike_blog, unlike_book,
+};
 use axum::middleware;
 use axum::{
     extract::DefaultBodyLimit,
@@ -26,13 +49,24 @@ use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::set_header::SetResponseHeaderLayer;
 
 use crate::book::{
+    collaborators::{accept_invitation, get_collaborators, invite_collaborator, revoke_collaborator},
     create::{append_book_node, create_book},
     delete::{delete_book, delete_book_node},
-    edit::{edit_book, edit_book_node},
-    get::{get_all_books_by_page_no, get_all_books_by_user_id, get_users_book},
+    edit::{edit_book, edit_book_node, move_book_node},
+    export::export_book,
+    get::{get_all_books, get_all_books_by_user_id, get_users_book},
+    import::get_import_job,
+    reupload::reupload_book,
     upload::upload_book,
 };
-use crate::file::{get_blog_file, get_book_file, get_tmp_file, upload_file};
+use crate::file::{get_blog_file, get_book_file, get_tmp_file, get_user_file, upload_file};
+use crate::search::search;
+use crate::feed::get_feed;
+use crate::recommend::{get_recommended_blogs, get_recommended_books};
+use crate::revision::{
+    get_blog_revision_diff, get_blog_revisions, get_book_revision_diff, get_book_revisions,
+    restore_blog_revision, restore_book_revision,
+};
 
 use crate::AppState;
 use serde_json::json;
@@ -47,13 +81,29 @@ pub async fn home() -> Result<impl IntoResponse, (StatusCode, Json<serde_json::V
 }
 
 // Public read routes — no authentication required
+fn user_read_routes() -> Router<AppState> {
+    Router::new()
+        .route("/:user_id/followers", get(get_followers))
+        .route("/:user_id/following", get(get_following))
+        .route("/:user_id/mutuals", get(get_mutuals))
+        .route("/:user_id/follow_counts", get(get_follow_summary))
+        .route("/:user_id/profile", get(get_profile))
+}
+
 fn blog_read_routes() -> Router<AppState> {
     Router::new()
         .route("/get/nodes", get(get_all_blog_nodes))
-        .route("/get/:page_no/by_page", get(get_all_blogs_by_page_no))
+        .route("/get/all", get(get_all_blogs))
+        // Pre-cursor path, kept for old clients; `page_no` is ignored.
+        .route("/get/:page_no/by_page", get(get_all_blogs))
         .route("/get/:user_id/get_users_blog", get(get_users_blog))
         .route("/get/:uid/user_blogs", get(get_all_blogs_by_user_id))
         .route("/get/home_blogs", get(get_home_blogs))
+        .route("/get/by_tag/:tag", get(get_blogs_by_tag))
+        .route("/get/tag_cloud", get(get_blog_tag_cloud))
+        .route("/get/recommended", get(get_recommended_blogs))
+        .route("/node/:uid/html", get(get_blog_node_html))
+        .route("/comments", get(get_blog_comments))
 }
 
 fn book_read_routes() -> Router<AppState> {
@@ -61,43 +111,131 @@ fn book_read_routes() -> Router<AppState> {
         .route("/get/chapter", get(get_chapter_details))
         .route("/get/section", get(get_section_details))
         .route("/get/nav", get(get_book_chapters_and_sections))
+        .route("/export/:file", get(export_book))
         .route("/get/:user_id/get_users_book", get(get_users_book))
-        .route("/get/:page_no/by_page", get(get_all_books_by_page_no))
+        .route("/get/all", get(get_all_books))
+        // Pre-cursor path, kept for old clients; `page_no` is ignored.
+        .route("/get/:page_no/by_page", get(get_all_books))
         .route("/get/:uid/user_books", get(get_all_books_by_user_id))
         .route("/get/home_books", get(get_home_books))
+        .route("/get/by_tag/:tag", get(get_books_by_tag))
+        .route("/get/tag_cloud", get(get_book_tag_cloud))
+        .route("/get/recommended", get(get_recommended_books))
+        .route("/node/:uid/html", get(get_book_node_html))
+        .route("/comments", get(get_book_comments))
 }
 
 fn file_read_routes() -> Router<AppState> {
     Router::new()
         .route("/blog/:uid/:size/:filename", get(get_blog_file))
         .route("/book/:uid/:size/:filename", get(get_book_file))
+        .route("/user/:uid/:size/:filename", get(get_user_file))
         .route("/tmp/:uid/:size/:filename", get(get_tmp_file))
 }
 
+/// Guard every route in `router` with `permission`. Must sit inside the
+/// `require_auth` layer, which injects the caller's `Permissions`.
+fn with_permission(router: Router<AppState>, permission: Permission) -> Router<AppState> {
+    router.route_layer(middleware::from_fn_with_state(permission, require_permission))
+}
+
 // Authenticated write routes — require a valid session
 fn blog_write_routes() -> Router<AppState> {
-    Router::new()
+    let write = Router::new()
         .route("/create", post(create_blog))
+        .route("/append/node", post(append_blog_node))
+        // Markdown / ZIP import — same 50 MB limit as `/book/upload`
+        .route(
+            "/upload",
+            post(upload_blog).layer(DefaultBodyLimit::max(50 * 1024 * 1024)),
+        )
+        .route("/comments/create", post(create_blog_comment));
+    let update = Router::new()
         .route("/edit/main", post(edit_blog))
         .route("/edit/node", post(edit_blog_node))
+        .route("/move/node", post(move_blog_node))
+        .route("/revisions/restore", post(restore_blog_revision))
+        .route("/status", post(set_blog_status))
+        .route("/comments/edit", post(edit_blog_comment));
+    let delete = Router::new()
         .route("/delete", post(delete_blog))
         .route("/delete/node", post(delete_blog_node))
-        .route("/append/node", post(append_blog_node))
+        .route("/trash/restore", post(restore_blog))
+        .route("/trash/restore/node", post(restore_blog_node))
+        .route("/comments/delete", post(delete_blog_comment));
+
+    Router::new()
+        .merge(with_permission(write, Permission::Write))
+        .merge(with_permission(update, Permission::Update))
+        .merge(with_permission(delete, Permission::Delete))
+        .route("/revisions/diff", get(get_blog_revision_diff))
+        .route("/revisions/:node_id", get(get_blog_revisions))
+        .route("/trash", get(get_blog_trash))
+        .route("/:doc_id/like", post(like_blog))
+        .route("/:doc_id/unlike", post(unlike_blog))
+        .route("/:doc_id/bookmark", post(bookmark_blog))
+        .route("/:doc_id/unbookmark", post(unbookmark_blog))
 }
 
 fn book_write_routes() -> Router<AppState> {
-    Router::new()
+    let write = Router::new()
         .route("/create", post(create_book))
-        .route("/edit/main", post(edit_book))
-        .route("/edit/node", post(edit_book_node))
-        .route("/delete", post(delete_book))
-        .route("/delete/node", post(delete_book_node))
         .route("/append/node", post(append_book_node))
         // ZIP upload — override body limit to 50 MB for this route only
         .route(
             "/upload",
             post(upload_book).layer(DefaultBodyLimit::max(50 * 1024 * 1024)),
         )
+        .route("/comments/create", post(create_book_comment));
+    let update = Router::new()
+        .route("/edit/main", post(edit_book))
+        .route("/edit/node", post(edit_book_node))
+        .route("/move/node", post(move_book_node))
+        // Re-import a ZIP into an existing book — same 50 MB limit as `/upload`
+        .route(
+            "/:doc_id/upload",
+            post(reupload_book).layer(DefaultBodyLimit::max(50 * 1024 * 1024)),
+        )
+        .route("/revisions/restore", post(restore_book_revision))
+        .route("/status", post(set_book_status))
+        .route("/comments/edit", post(edit_book_comment));
+    let delete = Router::new()
+        .route("/delete", post(delete_book))
+        .route("/delete/node", post(delete_book_node))
+        .route("/trash/restore", post(restore_book))
+        .route("/trash/restore/node", post(restore_book_node))
+        .route("/comments/delete", post(delete_book_comment));
+    // Co-authoring — ownership and membership are checked per book in the handlers
+    let collaborate = Router::new()
+        .route("/:doc_id/collaborators/invite", post(invite_collaborator))
+        .route("/:doc_id/collaborators/accept", post(accept_invitation))
+        .route("/:doc_id/collaborators/revoke", post(revoke_collaborator));
+
+    Router::new()
+        .merge(with_permission(write, Permission::Write))
+        .merge(with_permission(update, Permission::Update))
+        .merge(with_permission(delete, Permission::Delete))
+        .merge(with_permission(collaborate, Permission::Update))
+        .route("/:doc_id/collaborators", get(get_collaborators))
+        .route("/revisions/diff", get(get_book_revision_diff))
+        .route("/revisions/:node_id", get(get_book_revisions))
+        .route("/trash", get(get_book_trash))
+        .route("/import/:job_id", get(get_import_job))
+        .route("/:doc_id/like", post(like_book))
+        .route("/:doc_id/unlike", post(unlike_book))
+        .route("/:doc_id/bookmark", post(bookmark_book))
+        .route("/:doc_id/unbookmark", post(unbookmark_book))
+        .route("/:doc_id/progress", get(get_book_progress).post(save_book_progress))
+}
+
+// Moderation — admin role only
+fn admin_routes() -> Router<AppState> {
+    let moderate = Router::new()
+        .route("/blog/delete", post(moderate_delete_blog))
+        .route("/blog/restore", post(moderate_restore_blog))
+        .route("/book/delete", post(moderate_delete_book))
+        .route("/book/restore", post(moderate_restore_book));
+    with_permission(moderate, Permission::Moderate)
 }
 
 fn user_routes() -> Router<AppState> {
@@ -105,11 +243,16 @@ fn user_routes() -> Router<AppState> {
         .route("/:user_id/subscribe", post(subscribe_user))
         .route("/:user_id/un_subscribe", post(un_subscribe_user))
         .route("/get_subscribed_users", get(get_subscribed_users))
+        .route("/bookmarks", get(get_bookmarks))
+        .route("/reading", get(get_continue_reading))
+        .route("/permissions", get(get_permissions))
+        .route("/profile", post(edit_profile))
 }
 
 fn file_write_routes() -> Router<AppState> {
-    Router::new()
-        .route("/upload", post(upload_file))
+    let write = Router::new()
+        .route("/upload", post(upload_file));
+    with_permission(write, Permission::Write)
 }
 
 pub async fn create_router(app_state: AppState) -> Router {
@@ -139,6 +282,8 @@ pub async fn create_router(app_state: AppState) -> Router {
         .nest("/book", book_write_routes())
         .nest("/user", user_routes())
         .nest("/file", file_write_routes())
+        .nest("/admin", admin_routes())
+        .route("/feed", get(get_feed))
         .with_state(app_state.clone())
         .layer(middleware::from_fn_with_state(app_state.clone(), require_auth));
 
@@ -147,8 +292,11 @@ pub async fn create_router(app_state: AppState) -> Router {
         .nest("/blog", blog_read_routes())
         .nest("/book", book_read_routes())
         .nest("/file", file_read_routes())
+        .nest("/user", user_read_routes())
+        .route("/search", get(search))
         .route("/", get(home))
-        .with_state(app_state.clone());
+        .with_state(app_state.clone())
+        .layer(middleware::from_fn_with_state(app_state.clone(), optional_auth));
 
     Router::new()
         .merge(public)
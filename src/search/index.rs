@@ -0,0 +1,401 @@
+use serde::Serialize;
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, INDEXED, STORED,
+    STRING,
+};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+use tokio::sync::{mpsc, oneshot};
+use tokio_postgres::{Client, Row};
+
+use crate::connections::pg::PgConnection;
+use crate::error::AppError;
+
+/// Heap budget handed to the tantivy writer.
+const WRITER_HEAP_BYTES: usize = 50_000_000;
+/// Upper bound on snippet length (characters) returned per field.
+const SNIPPET_MAX_CHARS: usize = 180;
+/// Title matches rank above body matches.
+const TITLE_BOOST: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DocKind {
+    Blog,
+    Book,
+}
+
+impl DocKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocKind::Blog => "blog",
+            DocKind::Book => "book",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "blog" => Some(DocKind::Blog),
+            "book" => Some(DocKind::Book),
+            _ => None,
+        }
+    }
+}
+
+/// A single `blog` / `book` node row as it goes into the index.
+pub struct IndexNode<'a> {
+    pub kind: DocKind,
+    pub uid: i32,
+    pub doc_id: i32,
+    pub user_id: i32,
+    pub identity: Option<i16>,
+    pub title: &'a str,
+    pub content: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub kind: &'static str,
+    pub doc_id: i32,
+    pub uid: i32,
+    pub user_id: i32,
+    pub identity: Option<i16>,
+    pub title: String,
+    pub score: f32,
+    pub title_snippet: String,
+    pub content_snippet: String,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    node_key: Field,
+    doc_key: Field,
+    kind: Field,
+    doc_id: Field,
+    uid: Field,
+    user_id: Field,
+    identity: Field,
+    title: Field,
+    content: Field,
+}
+
+/// In-memory tantivy index over every live `blog` and `book` node.
+///
+/// The index is rebuilt from Postgres at startup (see `rebuild`) and kept in
+/// sync by the write handlers. Index failures never fail a request — they are
+/// logged and the next rebuild repairs the drift.
+///
+/// Handlers only queue their changes; a dedicated writer thread applies them,
+/// commits and reloads the reader, so a commit never blocks the runtime.
+/// Changes become searchable shortly after the request that made them.
+#[derive(Clone)]
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    writes: mpsc::UnboundedSender<WriteOp>,
+    fields: Fields,
+}
+
+/// A change queued for the writer thread. Each call queues its changes as
+/// one op, so they land in the same commit.
+enum WriteOp {
+    Delete(Vec<Term>),
+    /// Delete each term, then add its document.
+    Replace(Vec<(Term, TantivyDocument)>),
+    /// Answered once every change queued before it is committed.
+    Commit(oneshot::Sender<Result<(), String>>),
+}
+
+/// Apply queued changes until every `SearchIndex` handle is dropped. Each
+/// wake-up drains whatever is queued and commits it once, so a burst of
+/// handler writes costs a single commit.
+fn run_writer(mut writer: IndexWriter, reader: IndexReader, mut writes: mpsc::UnboundedReceiver<WriteOp>) {
+    while let Some(op) = writes.blocking_recv() {
+        let mut waiting: Vec<oneshot::Sender<Result<(), String>>> = Vec::new();
+        let mut next = Some(op);
+        while let Some(op) = next {
+            match op {
+                WriteOp::Delete(terms) => {
+                    for term in terms {
+                        writer.delete_term(term);
+                    }
+                }
+                WriteOp::Replace(documents) => {
+                    for (term, document) in documents {
+                        writer.delete_term(term);
+                        if let Err(e) = writer.add_document(document) {
+                            tracing::warn!(error = %e, "search index add failed");
+                        }
+                    }
+                }
+                WriteOp::Commit(done) => waiting.push(done),
+            }
+            next = writes.try_recv().ok();
+        }
+        let result = writer
+            .commit()
+            .and_then(|_| reader.reload())
+            .map_err(|e| e.to_string());
+        if let Err(e) = &result {
+            tracing::warn!(error = %e, "search index commit failed");
+        }
+        for done in waiting {
+            let _ = done.send(result.clone());
+        }
+    }
+}
+
+fn node_query(kind: DocKind) -> &'static str {
+    match kind {
+        DocKind::Blog => "SELECT uid, doc_id, user_id, title, content, NULL::smallint FROM blog WHERE deleted_at IS NULL",
+        DocKind::Book => "SELECT uid, doc_id, user_id, title, content, identity FROM book WHERE deleted_at IS NULL",
+    }
+}
+
+fn rows_to_nodes(kind: DocKind, rows: &[Row]) -> Vec<IndexNode<'_>> {
+    rows.iter()
+        .map(|row| IndexNode {
+            kind,
+            uid: row.get(0),
+            doc_id: row.get(1),
+            user_id: row.get(2),
+            identity: row.get(5),
+            title: row.get(3),
+            content: row.get(4),
+        })
+        .collect()
+}
+
+fn node_key(kind: DocKind, uid: i32) -> String {
+    format!("{}:{}", kind.as_str(), uid)
+}
+
+impl SearchIndex {
+    pub fn new() -> Result<Self, AppError> {
+        let text = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("en_stem")
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            )
+            .set_stored();
+
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            node_key: builder.add_text_field("node_key", STRING),
+            doc_key: builder.add_text_field("doc_key", STRING),
+            kind: builder.add_text_field("kind", STRING | STORED),
+            doc_id: builder.add_i64_field("doc_id", INDEXED | STORED),
+            uid: builder.add_i64_field("uid", STORED),
+            user_id: builder.add_i64_field("user_id", STORED),
+            identity: builder.add_i64_field("identity", STORED),
+            title: builder.add_text_field("title", text.clone()),
+            content: builder.add_text_field("content", text),
+        };
+
+        let index = Index::create_in_ram(builder.build());
+        let writer = index.writer(WRITER_HEAP_BYTES)?;
+        let reader: IndexReader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+
+        let (writes, receiver) = mpsc::unbounded_channel();
+        let writer_reader = reader.clone();
+        std::thread::Builder::new()
+            .name("search-writer".into())
+            .spawn(move || run_writer(writer, writer_reader, receiver))?;
+
+        Ok(SearchIndex {
+            index,
+            reader,
+            writes,
+            fields,
+        })
+    }
+
+    /// Load every non-deleted `blog` and `book` node into the index.
+    pub async fn rebuild(&self, pg: &PgConnection) -> Result<usize, AppError> {
+        let conn = pg.conn.get().await?;
+        let blog_rows = conn.query(node_query(DocKind::Blog), &[]).await?;
+        let book_rows = conn.query(node_query(DocKind::Book), &[]).await?;
+
+        let mut nodes = rows_to_nodes(DocKind::Blog, &blog_rows);
+        nodes.extend(rows_to_nodes(DocKind::Book, &book_rows));
+
+        let count = nodes.len();
+        self.upsert(&nodes);
+        self.committed()
+            .await
+            .map_err(|_| AppError::InternalServerError("search writer stopped".into()))?
+            .map_err(AppError::InternalServerError)?;
+        Ok(count)
+    }
+
+    /// Replace a document's entries with its live nodes, e.g. after a restore.
+    /// Best-effort like the other write hooks.
+    pub async fn reindex_doc(&self, conn: &Client, kind: DocKind, doc_id: i32) {
+        let query = format!("{} AND doc_id = $1", node_query(kind));
+        match conn.query(&query, &[&doc_id]).await {
+            Ok(rows) => {
+                self.remove_doc(kind, doc_id);
+                self.upsert(&rows_to_nodes(kind, &rows));
+            }
+            Err(e) => tracing::warn!(doc_id, error = %e, "search index reindex_doc failed"),
+        }
+    }
+
+    /// Insert or replace nodes, keyed by `(kind, uid)`.
+    pub fn upsert(&self, nodes: &[IndexNode]) {
+        let fields = self.fields;
+        let documents = nodes
+            .iter()
+            .map(|node| {
+                let key = node_key(node.kind, node.uid);
+                let document = doc!(
+                    fields.node_key => key.clone(),
+                    fields.doc_key => node_key(node.kind, node.doc_id),
+                    fields.kind => node.kind.as_str(),
+                    fields.doc_id => node.doc_id as i64,
+                    fields.uid => node.uid as i64,
+                    fields.user_id => node.user_id as i64,
+                    fields.identity => node.identity.map(i64::from).unwrap_or(-1),
+                    fields.title => node.title,
+                    fields.content => node.content,
+                );
+                (Term::from_field_text(fields.node_key, &key), document)
+            })
+            .collect();
+        self.send(WriteOp::Replace(documents));
+    }
+
+    /// Drop individual nodes from the index.
+    pub fn remove_nodes(&self, kind: DocKind, uids: &[i32]) {
+        let terms = uids
+            .iter()
+            .map(|uid| Term::from_field_text(self.fields.node_key, &node_key(kind, *uid)))
+            .collect();
+        self.send(WriteOp::Delete(terms));
+    }
+
+    /// Drop every node belonging to a document.
+    pub fn remove_doc(&self, kind: DocKind, doc_id: i32) {
+        self.send(WriteOp::Delete(vec![Term::from_field_text(self.fields.doc_key, &node_key(kind, doc_id))]));
+    }
+
+    fn send(&self, op: WriteOp) {
+        if self.writes.send(op).is_err() {
+            tracing::warn!("search writer stopped; index change dropped");
+        }
+    }
+
+    /// Resolves once every change queued so far is committed and searchable.
+    fn committed(&self) -> oneshot::Receiver<Result<(), String>> {
+        let (done, committed) = oneshot::channel();
+        self.send(WriteOp::Commit(done));
+        committed
+    }
+
+    /// Ranked search over title (boosted) and content, optionally restricted
+    /// to one document kind.
+    pub fn search(
+        &self,
+        query: &str,
+        kind: Option<DocKind>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, AppError> {
+        let fields = self.fields;
+        let mut parser = QueryParser::for_index(&self.index, vec![fields.title, fields.content]);
+        parser.set_field_boost(fields.title, TITLE_BOOST);
+        let (text_query, _) = parser.parse_query_lenient(query);
+
+        let query: Box<dyn Query> = match kind {
+            Some(kind) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, text_query),
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(fields.kind, kind.as_str()),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ])),
+            None => text_query,
+        };
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
+
+        let mut title_snippets = SnippetGenerator::create(&searcher, &*query, fields.title)?;
+        title_snippets.set_max_num_chars(SNIPPET_MAX_CHARS);
+        let mut content_snippets = SnippetGenerator::create(&searcher, &*query, fields.content)?;
+        content_snippets.set_max_num_chars(SNIPPET_MAX_CHARS);
+
+        let mut hits = Vec::with_capacity(top_docs.len());
+        for (score, address) in top_docs {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let int = |field: Field| document.get_first(field).and_then(|v| v.as_i64()).unwrap_or(-1);
+            let kind = match document.get_first(fields.kind).and_then(|v| v.as_str()) {
+                Some("book") => DocKind::Book,
+                _ => DocKind::Blog,
+            };
+            let identity = int(fields.identity);
+            hits.push(SearchHit {
+                kind: kind.as_str(),
+                doc_id: int(fields.doc_id) as i32,
+                uid: int(fields.uid) as i32,
+                user_id: int(fields.user_id) as i32,
+                identity: if identity < 0 { None } else { Some(identity as i16) },
+                title: document
+                    .get_first(fields.title)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                score,
+                title_snippet: title_snippets.snippet_from_doc(&document).to_html(),
+                content_snippet: content_snippets.snippet_from_doc(&document).to_html(),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node<'a>(kind: DocKind, uid: i32, doc_id: i32, title: &'a str, content: &'a str) -> IndexNode<'a> {
+        IndexNode { kind, uid, doc_id, user_id: 1, identity: None, title, content }
+    }
+
+    #[test]
+    fn test_title_match_ranks_first() {
+        let index = SearchIndex::new().unwrap();
+        index.upsert(&[
+            node(DocKind::Blog, 1, 1, "Cooking pasta", "A short note about rust compilers"),
+            node(DocKind::Book, 2, 2, "Rust compilers", "Everything about building them"),
+        ]);
+        index.committed().blocking_recv().unwrap().unwrap();
+        let hits = index.search("rust", None, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].uid, 2);
+        assert!(hits[0].title_snippet.contains("<b>Rust</b>"));
+    }
+
+    #[test]
+    fn test_upsert_replaces_and_remove_doc_drops() {
+        let index = SearchIndex::new().unwrap();
+        index.upsert(&[node(DocKind::Blog, 1, 7, "Old title", "old body")]);
+        index.upsert(&[node(DocKind::Blog, 1, 7, "New title", "new body")]);
+        index.committed().blocking_recv().unwrap().unwrap();
+        assert!(index.search("old", None, 10).unwrap().is_empty());
+        assert_eq!(index.search("new", Some(DocKind::Blog), 10).unwrap().len(), 1);
+        assert!(index.search("new", Some(DocKind::Book), 10).unwrap().is_empty());
+
+        index.remove_doc(DocKind::Blog, 7);
+        index.committed().blocking_recv().unwrap().unwrap();
+        assert!(index.search("new", None, 10).unwrap().is_empty());
+    }
+}
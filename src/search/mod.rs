@@ -0,0 +1,81 @@
+pub mod index;
+
+use crate::error::AppError;
+use crate::lifecycle::published_ids;
+use crate::recommend::record_search;
+use crate::utils::doc::TagTable;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use index::DocKind;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 50;
+
+#[derive(Deserialize)]
+pub struct SearchRequest {
+    q: String,
+    kind: Option<String>,
+    limit: Option<usize>,
+}
+
+/// GET /search?q=<terms>[&kind=blog|book][&limit=N]
+pub async fn search(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    query: Query<SearchRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let request: SearchRequest = query.0;
+    let q = request.q.trim();
+    if q.is_empty() {
+        return Err(AppError::BadRequest("Query parameter `q` must not be empty".into()));
+    }
+    let kind = match request.kind.as_deref() {
+        None => None,
+        Some(raw) => Some(
+            DocKind::parse(raw)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown kind `{raw}`")))?,
+        ),
+    };
+    let limit = request.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    // The index holds every live node; drafts, scheduled and unlisted
+    // documents are dropped here, so over-fetch to keep pages full.
+    let mut hits = pool.search().search(q, kind, limit * 2)?;
+    let conn = pool.pg_pool.conn.get().await?;
+    let doc_ids = |kind: DocKind| -> Vec<i32> {
+        hits.iter()
+            .filter(|hit| hit.kind == kind.as_str())
+            .map(|hit| hit.doc_id)
+            .collect()
+    };
+    let blogs = published_ids(&conn, TagTable::Blog, &doc_ids(DocKind::Blog)).await?;
+    let books = published_ids(&conn, TagTable::Book, &doc_ids(DocKind::Book)).await?;
+    hits.retain(|hit| match DocKind::parse(hit.kind) {
+        Some(DocKind::Blog) => blogs.contains(&hit.doc_id),
+        Some(DocKind::Book) => books.contains(&hit.doc_id),
+        None => false,
+    });
+    hits.truncate(limit);
+
+    if let Some(Extension(UserId(user_id))) = user {
+        record_search(&conn, user_id, q).await;
+    }
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({
+            "query": q,
+            "hits": hits
+        })),
+    ))
+}
@@ -7,6 +7,18 @@ pub struct Images {
     pub name: String,
 }
 
+/// Widths every uploaded image is stored at, as `{size}-{name}`.
+pub const IMAGE_SIZES: [u32; 3] = [340, 720, 1420];
+
+/// Parse an `images` column (a JSON array of `Images`) into names.
+/// Malformed or missing values yield no names.
+pub fn image_names(images: Option<&str>) -> Vec<String> {
+    images
+        .and_then(|raw| serde_json::from_str::<Vec<Images>>(raw).ok())
+        .map(|images| images.into_iter().map(|image| image.name).collect())
+        .unwrap_or_default()
+}
+
 /// Move every image in `images` from `{src_bucket}/{user_id}/{size}-{name}`
 /// to `{dst_bucket}/{project_id}/{size}-{name}` via the S3 service.
 /// Errors are logged but not propagated — mirrors the previous best-effort
@@ -20,7 +32,7 @@ pub async fn move_images_to_s3(
     project_id: i32,
 ) -> Result<(), AppError> {
     for image in images {
-        for size in &[340u32, 720, 1420] {
+        for size in &IMAGE_SIZES {
             let src_key = format!("{}/{}-{}", user_id, size, image.name);
             let dst_key = format!("{}/{}-{}", project_id, size, image.name);
             if let Err(e) = s3.mv(src_bucket, &src_key, dst_bucket, &dst_key, &user_id.to_string()).await {
@@ -30,3 +42,16 @@ pub async fn move_images_to_s3(
     }
     Ok(())
 }
+
+/// Delete every size of each named image under `{bucket}/{project_id}/`.
+/// Best-effort like `move_images_to_s3`: failures are logged and skipped.
+pub async fn delete_images_from_s3(names: &[String], s3: &S3Client, bucket: &str, project_id: i32) {
+    for name in names {
+        for size in &IMAGE_SIZES {
+            let key = format!("{}/{}-{}", project_id, size, name);
+            if let Err(e) = s3.delete(bucket, &key, "system").await {
+                tracing::warn!(bucket, %key, error = %e, "delete_images_from_s3 failed for one image");
+            }
+        }
+    }
+}
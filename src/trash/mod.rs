@@ -0,0 +1,540 @@
+use crate::error::AppError;
+use crate::search::index::DocKind;
+use crate::traits::{delete_images_from_s3, image_names};
+use crate::utils::doc::TagTable;
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio_postgres::Row;
+
+/// How often the purger looks for trash past its retention period.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn doc_kind(table: TagTable) -> DocKind {
+    match table {
+        TagTable::Blog => DocKind::Blog,
+        TagTable::Book => DocKind::Book,
+    }
+}
+
+#[derive(Serialize)]
+pub struct TrashItem {
+    uid: i32,
+    doc_id: i32,
+    title: String,
+    deleted_at: DateTime<Utc>,
+    purge_at: DateTime<Utc>,
+}
+
+impl TrashItem {
+    fn from_row(row: &Row, retention_days: i32) -> Self {
+        let deleted_at: DateTime<Utc> = row.get(3);
+        TrashItem {
+            uid: row.get(0),
+            doc_id: row.get(1),
+            title: row.get(2),
+            deleted_at,
+            purge_at: deleted_at + ChronoDuration::days(retention_days.into()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RestoreDoc {
+    doc_id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreNode {
+    uid: i32,
+}
+
+/// Deleted documents owned by `user_id`, and deleted nodes inside their
+/// live documents, newest deletion first.
+async fn list_trash(pool: &AppState, table: TagTable, user_id: i32) -> Result<serde_json::Value, AppError> {
+    let retention_days = pool.config.app.trash_retention_days;
+    let conn = pool.pg_pool.conn.get().await?;
+    let docs = conn
+        .query(
+            &format!(
+                "SELECT uid, uid, title, deleted_at FROM {} \
+                 WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, uid DESC",
+                table.doc_table()
+            ),
+            &[&user_id],
+        )
+        .await?;
+    let nodes = conn
+        .query(
+            &format!(
+                "SELECT n.uid, n.doc_id, n.title, n.deleted_at FROM {nodes} n JOIN {docs} d ON d.uid = n.doc_id \
+                 WHERE d.user_id = $1 AND d.deleted_at IS NULL AND n.deleted_at IS NOT NULL \
+                 ORDER BY n.deleted_at DESC, n.uid DESC",
+                nodes = table.node_table(),
+                docs = table.doc_table()
+            ),
+            &[&user_id],
+        )
+        .await?;
+
+    let docs: Vec<TrashItem> = docs.iter().map(|row| TrashItem::from_row(row, retention_days)).collect();
+    let nodes: Vec<TrashItem> = nodes.iter().map(|row| TrashItem::from_row(row, retention_days)).collect();
+    Ok(json!({
+        "retention_days": retention_days,
+        "docs": docs,
+        "nodes": nodes
+    }))
+}
+
+/// Undo a document soft-delete. Only nodes deleted together with the
+/// document come back; nodes removed individually beforehand stay in the
+/// trash. `owner: None` skips the ownership check (moderation).
+pub async fn restore_doc(
+    pool: &AppState,
+    table: TagTable,
+    doc_id: i32,
+    owner: Option<i32>,
+) -> Result<u64, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let state1 = conn
+        .prepare(&format!(
+            "UPDATE {nodes} SET deleted_at=NULL WHERE doc_id=$1 \
+             AND deleted_at = (SELECT deleted_at FROM {docs} WHERE uid=$1 AND ($2::int4 IS NULL OR user_id=$2))",
+            nodes = table.node_table(),
+            docs = table.doc_table()
+        ))
+        .await?;
+    let state2 = conn
+        .prepare(&format!(
+            "UPDATE {} SET deleted_at=NULL WHERE uid=$1 AND deleted_at IS NOT NULL AND ($2::int4 IS NULL OR user_id=$2)",
+            table.doc_table()
+        ))
+        .await?;
+    let transaction = conn.transaction().await?;
+    let nodes = transaction.execute(&state1, &[&doc_id, &owner]).await?;
+    let restored = transaction.execute(&state2, &[&doc_id, &owner]).await?;
+    if restored == 0 {
+        return Err(AppError::NotFound(format!("No deleted document with uid {doc_id}")));
+    }
+    transaction.commit().await?;
+
+    pool.search().reindex_doc(&conn, doc_kind(table), doc_id).await;
+    Ok(nodes)
+}
+
+/// Undo a node soft-delete inside a live document. Everything deleted in the
+/// same operation (a chapter's sections, for instance) comes back, and the
+/// node that was re-pointed past it when it was deleted is linked to it again.
+async fn restore_node(
+    pool: &AppState,
+    table: TagTable,
+    node_id: i32,
+    owner: i32,
+) -> Result<serde_json::Value, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let identity = match table {
+        TagTable::Blog => "NULL::smallint",
+        TagTable::Book => "n.identity",
+    };
+    let node = conn
+        .query_opt(
+            &format!(
+                "SELECT n.doc_id, n.parent_id, n.deleted_at, {identity} FROM {nodes} n JOIN {docs} d ON d.uid = n.doc_id \
+                 WHERE n.uid = $1 AND n.deleted_at IS NOT NULL AND d.deleted_at IS NULL AND d.user_id = $2",
+                nodes = table.node_table(),
+                docs = table.doc_table()
+            ),
+            &[&node_id, &owner],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No deleted node with uid {node_id}")))?;
+    let doc_id: i32 = node.get(0);
+    let parent_id: Option<i32> = node.get(1);
+    let deleted_at: DateTime<Utc> = node.get(2);
+    let identity: Option<i16> = node.get(3);
+
+    let Some(parent_id) = parent_id else {
+        return Err(AppError::BadRequest("The main node is restored with its document".into()));
+    };
+    let parent_live = conn
+        .query_opt(
+            &format!("SELECT 1 FROM {} WHERE uid = $1 AND deleted_at IS NULL", table.node_table()),
+            &[&parent_id],
+        )
+        .await?
+        .is_some();
+    if !parent_live {
+        return Err(AppError::BadRequest(format!("Restore node {parent_id} first")));
+    }
+
+    let transaction = conn.transaction().await?;
+    // The live node that took our place in the chain when we were deleted.
+    // Blog nodes have no `identity` column; book chains are per identity.
+    let successor_sql = |identity_filter: &str| {
+        format!(
+            "SELECT uid FROM {} WHERE parent_id = $1 AND doc_id = $2 AND deleted_at IS NULL{identity_filter} LIMIT 1",
+            table.node_table()
+        )
+    };
+    let successor = match table {
+        TagTable::Blog => transaction.query_opt(&successor_sql(""), &[&parent_id, &doc_id]).await?,
+        TagTable::Book => {
+            transaction
+                .query_opt(
+                    &successor_sql(" AND ($3::int2 IS NULL OR identity = $3)"),
+                    &[&parent_id, &doc_id, &identity],
+                )
+                .await?
+        }
+    }
+    .map(|row| row.get::<_, i32>(0));
+    let restored: Vec<i32> = transaction
+        .query(
+            &format!(
+                "UPDATE {} SET deleted_at = NULL WHERE doc_id = $1 AND deleted_at = $2 RETURNING uid",
+                table.node_table()
+            ),
+            &[&doc_id, &deleted_at],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    if let Some(successor) = successor {
+        transaction
+            .execute(
+                &format!("UPDATE {} SET parent_id = $1 WHERE uid = $2", table.node_table()),
+                &[&node_id, &successor],
+            )
+            .await?;
+    }
+    transaction.commit().await?;
+
+    pool.search().reindex_doc(&conn, doc_kind(table), doc_id).await;
+
+    Ok(json!({
+        "uid": node_id,
+        "doc_id": doc_id,
+        "restored": restored,
+        "update_node": successor.map(|uid| json!({ "uid": uid, "parent_id": node_id }))
+    }))
+}
+
+/// Hard-delete trash older than `retention_days`: whole documents with
+/// everything hanging off them, then stray nodes of live documents. Images
+/// are removed from S3 after the rows are gone. Returns the number of rows
+/// purged from the node tables.
+pub async fn purge_expired(pool: &AppState, retention_days: i32) -> Result<u64, AppError> {
+    let mut purged = 0;
+    for table in [TagTable::Blog, TagTable::Book] {
+        let conn = pool.pg_pool.conn.get().await?;
+        let doc_ids: Vec<i32> = conn
+            .query(
+                &format!(
+                    "SELECT uid FROM {} WHERE deleted_at < now() - make_interval(days => $1)",
+                    table.doc_table()
+                ),
+                &[&retention_days],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        drop(conn);
+        for doc_id in doc_ids {
+            purged += purge_doc(pool, table, doc_id).await?;
+        }
+        purged += purge_nodes(pool, table, retention_days).await?;
+    }
+    Ok(purged)
+}
+
+async fn purge_doc(pool: &AppState, table: TagTable, doc_id: i32) -> Result<u64, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let transaction = conn.transaction().await?;
+    let mut names: HashSet<String> = HashSet::new();
+    let rows = transaction
+        .query(
+            &format!(
+                "SELECT images FROM {docs} WHERE uid = $1 UNION ALL SELECT images FROM {nodes} WHERE doc_id = $1",
+                docs = table.doc_table(),
+                nodes = table.node_table()
+            ),
+            &[&doc_id],
+        )
+        .await?;
+    for row in rows.iter() {
+        names.extend(image_names(row.get(0)));
+    }
+
+    transaction
+        .execute(
+            "DELETE FROM node_revisions WHERE doc_type = $1 AND doc_id = $2",
+            &[&table.doc_type(), &doc_id],
+        )
+        .await?;
+    transaction
+        .execute(
+            "DELETE FROM interactions WHERE doc_type = $1 AND doc_id = $2",
+            &[&table.doc_type(), &doc_id],
+        )
+        .await?;
+    transaction
+        .execute(
+            "DELETE FROM comments WHERE doc_type = $1 AND doc_id = $2",
+            &[&table.doc_type(), &doc_id],
+        )
+        .await?;
+    for reactions in ["likes", "bookmarks"] {
+        transaction
+            .execute(
+                &format!("DELETE FROM {reactions} WHERE doc_type = $1 AND doc_id = $2"),
+                &[&table.doc_type(), &doc_id],
+            )
+            .await?;
+    }
+    transaction
+        .execute(&format!("DELETE FROM {} WHERE doc_id = $1", table.table()), &[&doc_id])
+        .await?;
+    if let TagTable::Book = table {
+        transaction
+            .execute("DELETE FROM book_collaborators WHERE doc_id = $1", &[&doc_id])
+            .await?;
+        transaction
+            .execute("DELETE FROM reading_progress WHERE doc_id = $1", &[&doc_id])
+            .await?;
+        transaction
+            .execute("DELETE FROM reading_completed WHERE doc_id = $1", &[&doc_id])
+            .await?;
+    }
+    let purged = transaction
+        .execute(&format!("DELETE FROM {} WHERE doc_id = $1", table.node_table()), &[&doc_id])
+        .await?;
+    transaction
+        .execute(&format!("DELETE FROM {} WHERE uid = $1", table.doc_table()), &[&doc_id])
+        .await?;
+    transaction.commit().await?;
+
+    pool.search().remove_doc(doc_kind(table), doc_id);
+    let names: Vec<String> = names.into_iter().collect();
+    delete_images_from_s3(&names, pool.s3(), table.node_table(), doc_id).await;
+    Ok(purged)
+}
+
+/// Expired nodes of live documents. An image is only deleted when no live
+/// node of the same document still references it.
+async fn purge_nodes(pool: &AppState, table: TagTable, retention_days: i32) -> Result<u64, AppError> {
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let transaction = conn.transaction().await?;
+    let rows = transaction
+        .query(
+            &format!(
+                "DELETE FROM {nodes} n USING {docs} d \
+                 WHERE d.uid = n.doc_id AND d.deleted_at IS NULL \
+                 AND n.deleted_at < now() - make_interval(days => $1) \
+                 RETURNING n.uid, n.doc_id, n.images",
+                nodes = table.node_table(),
+                docs = table.doc_table()
+            ),
+            &[&retention_days],
+        )
+        .await?;
+    if rows.is_empty() {
+        return Ok(0);
+    }
+    let node_ids: Vec<i32> = rows.iter().map(|row| row.get(0)).collect();
+    transaction
+        .execute(
+            "DELETE FROM node_revisions WHERE doc_type = $1 AND node_id = ANY($2)",
+            &[&table.doc_type(), &node_ids],
+        )
+        .await?;
+    transaction
+        .execute(
+            "DELETE FROM comments WHERE doc_type = $1 AND node_id = ANY($2)",
+            &[&table.doc_type(), &node_ids],
+        )
+        .await?;
+    if let TagTable::Book = table {
+        transaction
+            .execute("DELETE FROM reading_completed WHERE node_id = ANY($1)", &[&node_ids])
+            .await?;
+        transaction
+            .execute("DELETE FROM reading_progress WHERE node_id = ANY($1)", &[&node_ids])
+            .await?;
+    }
+
+    let mut candidates: Vec<(i32, Vec<String>)> = Vec::new();
+    for row in rows.iter() {
+        let doc_id: i32 = row.get(1);
+        let names = image_names(row.get(2));
+        if names.is_empty() {
+            continue;
+        }
+        let live = transaction
+            .query(
+                &format!(
+                    "SELECT images FROM {docs} WHERE uid = $1 UNION ALL \
+                     SELECT images FROM {nodes} WHERE doc_id = $1",
+                    docs = table.doc_table(),
+                    nodes = table.node_table()
+                ),
+                &[&doc_id],
+            )
+            .await?;
+        let in_use: HashSet<String> = live.iter().flat_map(|row| image_names(row.get(0))).collect();
+        candidates.push((doc_id, names.into_iter().filter(|name| !in_use.contains(name)).collect()));
+    }
+    transaction.commit().await?;
+
+    pool.search().remove_nodes(doc_kind(table), &node_ids);
+    for (doc_id, names) in candidates {
+        delete_images_from_s3(&names, pool.s3(), table.node_table(), doc_id).await;
+    }
+    Ok(node_ids.len() as u64)
+}
+
+/// Background task running `purge_expired` every `PURGE_INTERVAL`.
+pub fn spawn_purger(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let retention_days = state.config.app.trash_retention_days;
+            match purge_expired(&state, retention_days).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!(purged = n, retention_days, "purged expired trash"),
+                Err(e) => tracing::warn!(error = %e, "trash purge failed"),
+            }
+        }
+    });
+}
+
+fn ok(body: serde_json::Value) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(body),
+    )
+}
+
+pub async fn get_blog_trash(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(list_trash(&pool, TagTable::Blog, user_id).await?))
+}
+
+pub async fn get_book_trash(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(list_trash(&pool, TagTable::Book, user_id).await?))
+}
+
+pub async fn restore_blog(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<RestoreDoc>,
+) -> Result<impl IntoResponse, AppError> {
+    let nodes = restore_doc(&pool, TagTable::Blog, body.doc_id, Some(user_id)).await?;
+    Ok(ok(json!({ "doc_id": body.doc_id, "restored_nodes": nodes })))
+}
+
+pub async fn restore_book(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<RestoreDoc>,
+) -> Result<impl IntoResponse, AppError> {
+    let nodes = restore_doc(&pool, TagTable::Book, body.doc_id, Some(user_id)).await?;
+    Ok(ok(json!({ "doc_id": body.doc_id, "restored_nodes": nodes })))
+}
+
+pub async fn restore_blog_node(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<RestoreNode>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(restore_node(&pool, TagTable::Blog, body.uid, user_id).await?))
+}
+
+pub async fn restore_book_node(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<RestoreNode>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(ok(restore_node(&pool, TagTable::Book, body.uid, user_id).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    /// `(uid, parent_id, deleted_at)` rows of one document's node table.
+    type Nodes = Vec<(i32, i32, Option<u32>)>;
+
+    /// `delete_blog_node`: stamp the node, re-point its successor past it.
+    fn delete_node(nodes: &mut Nodes, uid: i32, at: u32) {
+        let parent_id = nodes.iter().find(|n| n.0 == uid).unwrap().1;
+        for node in nodes.iter_mut() {
+            if node.0 == uid {
+                node.2 = Some(at);
+            } else if node.1 == uid && node.2.is_none() {
+                node.1 = parent_id;
+            }
+        }
+    }
+
+    /// `delete_blog` / `delete_book`: stamp the nodes that are still live.
+    fn delete_doc(nodes: &mut Nodes, at: u32) {
+        for node in nodes.iter_mut().filter(|n| n.2.is_none()) {
+            node.2 = Some(at);
+        }
+    }
+
+    /// `restore_doc`: bring back the nodes stamped with the document's time.
+    fn restore_doc(nodes: &mut Nodes, at: u32) {
+        for node in nodes.iter_mut().filter(|n| n.2 == Some(at)) {
+            node.2 = None;
+        }
+    }
+
+    /// The live chain from the main node `root`, or `None` on a fork.
+    fn chain(nodes: &Nodes, root: i32) -> Option<Vec<i32>> {
+        let mut chain = vec![root];
+        loop {
+            let next: Vec<i32> = nodes
+                .iter()
+                .filter(|n| n.2.is_none() && n.1 == *chain.last().unwrap())
+                .map(|n| n.0)
+                .collect();
+            match next[..] {
+                [] => return Some(chain),
+                [uid] => chain.push(uid),
+                _ => return None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_doc_keeps_earlier_node_deletes() {
+        // Main node 1 (parent 0), then 2 → 3 → 4.
+        let mut nodes: Nodes = vec![(1, 0, None), (2, 1, None), (3, 2, None), (4, 3, None)];
+        delete_node(&mut nodes, 3, 10);
+        let before = chain(&nodes, 1);
+        assert_eq!(before, Some(vec![1, 2, 4]));
+
+        delete_doc(&mut nodes, 20);
+        restore_doc(&mut nodes, 20);
+        assert_eq!(chain(&nodes, 1), before);
+        assert_eq!(nodes.iter().find(|n| n.0 == 3).unwrap().2, Some(10));
+    }
+}
@@ -41,6 +41,9 @@ pub(crate) struct Book {
     pub title: String,
     pub images: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub status: String,
+    pub like_count: i32,
+    pub bookmark_count: i32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,6 +53,9 @@ pub(crate) struct Blog {
     pub title: String,
     pub images: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub status: String,
+    pub like_count: i32,
+    pub bookmark_count: i32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -0,0 +1,208 @@
+//! Who follows whom, read from `subscription` (`user_id` follows
+//! `subscribed_id`). See docs/followers.md.
+
+use crate::error::AppError;
+use crate::utils::page::{Page, PageRequest, DEFAULT_PAGE_SIZE};
+use crate::utils::UserId;
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path as AxumPath, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio_postgres::Client;
+
+#[derive(Serialize)]
+pub struct FollowCounts {
+    pub followers: i64,
+    pub following: i64,
+}
+
+/// One user in a follower / following listing, as seen by the caller.
+/// `follows_you` and `followed_by_you` are `false` for signed-out callers.
+#[derive(Serialize)]
+pub struct FollowUser {
+    user_id: i32,
+    display_name: Option<String>,
+    avatar: Option<String>,
+    follower_count: i64,
+    following_count: i64,
+    follows_you: bool,
+    followed_by_you: bool,
+    /// When the follow that put this user in the listing was made.
+    followed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct FollowSummary {
+    user_id: i32,
+    follower_count: i64,
+    following_count: i64,
+    follows_you: bool,
+    followed_by_you: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Listing {
+    /// Users following the subject.
+    Followers,
+    /// Users the subject follows.
+    Following,
+    /// Users the subject follows who follow back.
+    Mutuals,
+}
+
+impl Listing {
+    /// `(listed user, subject)` columns of `subscription s`.
+    fn columns(&self) -> (&'static str, &'static str) {
+        match self {
+            Listing::Followers => ("s.user_id", "s.subscribed_id"),
+            Listing::Following | Listing::Mutuals => ("s.subscribed_id", "s.user_id"),
+        }
+    }
+}
+
+pub async fn follow_counts(conn: &Client, user_id: i32) -> Result<FollowCounts, AppError> {
+    let row = conn
+        .query_one(
+            "SELECT (SELECT count(*) FROM subscription WHERE subscribed_id = $1), \
+             (SELECT count(*) FROM subscription WHERE user_id = $1)",
+            &[&user_id],
+        )
+        .await?;
+    Ok(FollowCounts {
+        followers: row.get(0),
+        following: row.get(1),
+    })
+}
+
+async fn list_follows(
+    pool: &AppState,
+    listing: Listing,
+    user_id: i32,
+    viewer: Option<i32>,
+    query: &PageRequest,
+) -> Result<Page<FollowUser>, AppError> {
+    let keyset = query.keyset(DEFAULT_PAGE_SIZE)?;
+    let (member, subject) = listing.columns();
+    let mutual = match listing {
+        Listing::Mutuals => "AND EXISTS (SELECT 1 FROM subscription m WHERE m.user_id = s.subscribed_id AND m.subscribed_id = $1)",
+        _ => "",
+    };
+    let conn = pool.pg_pool.conn.get().await?;
+    let rows = conn
+        .query(
+            &format!(
+                "SELECT {member}, \
+                 (SELECT count(*) FROM subscription f WHERE f.subscribed_id = {member}), \
+                 (SELECT count(*) FROM subscription f WHERE f.user_id = {member}), \
+                 EXISTS (SELECT 1 FROM subscription f WHERE f.user_id = {member} AND f.subscribed_id = $2), \
+                 EXISTS (SELECT 1 FROM subscription f WHERE f.user_id = $2 AND f.subscribed_id = {member}), \
+                 s.created_at, p.display_name, p.avatar \
+                 FROM subscription s \
+                 LEFT JOIN user_profiles p ON p.user_id = {member} \
+                 WHERE {subject} = $1 {mutual} \
+                 AND ($3::timestamptz IS NULL OR (s.created_at, {member}) < ($3::timestamptz, $4::int4)) \
+                 ORDER BY s.created_at DESC, {member} DESC LIMIT $5"
+            ),
+            &[&user_id, &viewer, &keyset.created_at, &keyset.uid, &keyset.fetch_limit()],
+        )
+        .await?;
+
+    let users = rows
+        .iter()
+        .map(|row| FollowUser {
+            user_id: row.get(0),
+            display_name: row.get(6),
+            avatar: row.get(7),
+            follower_count: row.get(1),
+            following_count: row.get(2),
+            follows_you: row.get(3),
+            followed_by_you: row.get(4),
+            followed_at: row.get(5),
+        })
+        .collect::<Vec<FollowUser>>();
+    Ok(keyset.page(users, |u| (u.followed_at, u.user_id)))
+}
+
+/// GET /user/:user_id/followers
+pub async fn get_followers(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let page = list_follows(&pool, Listing::Followers, user_id, viewer, &query).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(page),
+    ))
+}
+
+/// GET /user/:user_id/following
+pub async fn get_following(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let page = list_follows(&pool, Listing::Following, user_id, viewer, &query).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(page),
+    ))
+}
+
+/// GET /user/:user_id/mutuals
+pub async fn get_mutuals(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(user_id): AxumPath<i32>,
+    query: Query<PageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let page = list_follows(&pool, Listing::Mutuals, user_id, viewer, &query).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(page),
+    ))
+}
+
+/// GET /user/:user_id/follow_counts — follower and following counts, and how
+/// the caller relates to the user.
+pub async fn get_follow_summary(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(user_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let conn = pool.pg_pool.conn.get().await?;
+    let counts = follow_counts(&conn, user_id).await?;
+    let row = conn
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM subscription WHERE user_id = $1 AND subscribed_id = $2), \
+             EXISTS (SELECT 1 FROM subscription WHERE user_id = $2 AND subscribed_id = $1)",
+            &[&user_id, &viewer],
+        )
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(FollowSummary {
+            user_id,
+            follower_count: counts.followers,
+            following_count: counts.following,
+            follows_you: row.get(0),
+            followed_by_you: row.get(1),
+        }),
+    ))
+}
@@ -1,3 +1,7 @@
+pub mod follow;
+pub mod profile;
+
+use crate::auth::permissions::Permissions;
 use crate::error::AppError;
 use crate::AppState;
 use axum::{
@@ -9,6 +13,8 @@ use axum::{
 };
 use serde_json::json;
 
+use follow::follow_counts;
+
 pub async fn get_subscribed_users(
     axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(pool): State<AppState>,
@@ -31,66 +37,69 @@ pub async fn get_subscribed_users(
     ))
 }
 
+/// POST /user/:user_id/subscribe — follow `user_id`. Following someone twice
+/// is a no-op.
 pub async fn subscribe_user(
     axum::extract::Extension(crate::utils::UserId(auth_user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(pool): State<AppState>,
     AxumPath(user_id): AxumPath<i32>,
 ) -> Result<impl IntoResponse, AppError> {
+    if user_id == auth_user_id {
+        return Err(AppError::BadRequest("You cannot follow yourself".into()));
+    }
     let conn = pool.pg_pool.conn.get().await?;
-    let row = conn
-        .query_one(
-            "SELECT * FROM subscription where user_id=$1 and subscribed_id=$2",
-            &[&auth_user_id, &user_id],
-        )
-        .await?;
-    let message;
-    if row.is_empty() {
-        message = String::from("Created");
-        conn.query_one(
-            "INSERT INTO subscription(user_id, subscribed_id) VALUES($1, $2)",
+    let inserted = conn
+        .execute(
+            "INSERT INTO subscription(user_id, subscribed_id) VALUES($1, $2) ON CONFLICT (user_id, subscribed_id) DO NOTHING",
             &[&auth_user_id, &user_id],
         )
         .await?;
-    } else {
-        message = String::from("Aldready subscribed.");
-    }
+    let message = if inserted == 1 { "Created" } else { "Already subscribed." };
+    let counts = follow_counts(&conn, user_id).await?;
 
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(json!({ "message" : message })),
+        Json(json!({ "message": message, "following": true, "follower_count": counts.followers })),
     ))
 }
 
+/// POST /user/:user_id/un_subscribe — stop following `user_id`. Unfollowing
+/// someone not followed is a no-op.
 pub async fn un_subscribe_user(
     axum::extract::Extension(crate::utils::UserId(auth_user_id)): axum::extract::Extension<crate::utils::UserId>,
     State(pool): State<AppState>,
     AxumPath(user_id): AxumPath<i32>,
 ) -> Result<impl IntoResponse, AppError> {
     let conn = pool.pg_pool.conn.get().await?;
-    let row = conn
-        .query_one(
-            "SELECT * FROM subscription where user_id=$1 and subscribed_id=$2",
+    let deleted = conn
+        .execute(
+            "DELETE FROM subscription WHERE user_id = $1 AND subscribed_id = $2",
             &[&auth_user_id, &user_id],
         )
         .await?;
+    let message = if deleted == 0 { "Not subscribed." } else { "User unfollowed." };
+    let counts = follow_counts(&conn, user_id).await?;
 
-    let message;
-
-    if row.is_empty() {
-        message = String::from("Column does not exist.");
-    } else {
-        conn.query_one(
-            "DELETE FROM subscription where user_id = $1 AND subscribed_id = $2",
-            &[&auth_user_id, &user_id],
-        )
-        .await?;
-        message = String::from("User unfollowed.");
-    }
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(json!({ "message": message, "following": false, "follower_count": counts.followers })),
+    ))
+}
 
+/// GET /user/permissions — the caller's role and granted permissions.
+pub async fn get_permissions(
+    axum::extract::Extension(crate::utils::UserId(user_id)): axum::extract::Extension<crate::utils::UserId>,
+    permissions: Permissions,
+) -> Result<impl IntoResponse, AppError> {
     Ok((
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/json")],
-        Json(json!({ "message" : message })),
+        Json(json!({
+            "user_id": user_id,
+            "role": permissions.role,
+            "permissions": permissions.set.granted()
+        })),
     ))
 }
@@ -0,0 +1,276 @@
+//! Public author profiles: display name, bio, avatar and links, kept in
+//! `user_profiles`. Avatars go through `POST /file/upload` like any other
+//! image and are moved into the `user` bucket as `{user_id}/{size}-{name}`.
+//! See docs/profiles.md.
+
+use super::follow::follow_counts;
+use crate::error::AppError;
+use crate::file::s3_client::S3Client;
+use crate::file::ALLOWED_EXTENSIONS;
+use crate::traits::{delete_images_from_s3, IMAGE_SIZES};
+use crate::types::{Blog, Book};
+use crate::utils::page::Keyset;
+use crate::utils::UserId;
+use crate::AppState;
+use crate::{fetch_blogs_by_user_id, fetch_books_by_user_id};
+use axum::{
+    extract::{Extension, Path as AxumPath, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio_postgres::Client;
+
+const MAX_DISPLAY_NAME_LEN: usize = 50;
+const MAX_BIO_LEN: usize = 2_000;
+const MAX_LINKS: usize = 5;
+const MAX_LINK_LABEL_LEN: usize = 40;
+const MAX_LINK_URL_LEN: usize = 500;
+/// Newest blogs and books shown on a profile.
+const RECENT_DOCS: i64 = 5;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ProfileLink {
+    label: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+pub struct Profile {
+    user_id: i32,
+    display_name: Option<String>,
+    bio: Option<String>,
+    /// Image name under `/file/user/:user_id/:size/`.
+    avatar: Option<String>,
+    links: Vec<ProfileLink>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+/// Fields left out are kept; an empty `display_name`, `bio` or `avatar`
+/// clears it.
+#[derive(Deserialize)]
+pub struct EditProfile {
+    display_name: Option<String>,
+    bio: Option<String>,
+    /// `name` returned by `POST /file/upload`.
+    avatar: Option<String>,
+    links: Option<Vec<ProfileLink>>,
+}
+
+/// The stored profile, or an empty one for users who never saved theirs.
+async fn load_profile(conn: &Client, user_id: i32) -> Result<Profile, AppError> {
+    let row = conn
+        .query_opt(
+            "SELECT display_name, bio, avatar, links, updated_at FROM user_profiles WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    Ok(match row {
+        Some(row) => {
+            let links: Option<String> = row.get(3);
+            Profile {
+                user_id,
+                display_name: row.get(0),
+                bio: row.get(1),
+                avatar: row.get(2),
+                links: links.and_then(|links| serde_json::from_str(&links).ok()).unwrap_or_default(),
+                updated_at: row.get(4),
+            }
+        }
+        None => Profile {
+            user_id,
+            display_name: None,
+            bio: None,
+            avatar: None,
+            links: Vec::new(),
+            updated_at: None,
+        },
+    })
+}
+
+/// Trim `value` and check its length; `Some("")` means clear.
+fn check_text(field: &str, value: Option<String>, max: usize) -> Result<Option<String>, AppError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let value = value.trim().to_string();
+    if value.chars().count() > max {
+        return Err(AppError::BadRequest(format!("{field} is longer than {max} characters")));
+    }
+    Ok(Some(value))
+}
+
+fn check_avatar(name: &str) -> Result<(), AppError> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    if name.contains(['/', '\\']) || name.starts_with('.') || !ALLOWED_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(AppError::BadRequest(format!("`{name}` is not an uploaded image name")));
+    }
+    Ok(())
+}
+
+/// Move every width of a new avatar from `tmp` to `user`. Unlike
+/// `move_images_to_s3` a failure is returned, so the profile never names an
+/// image that was not uploaded.
+async fn move_avatar(s3: &S3Client, user_id: i32, name: &str) -> Result<(), AppError> {
+    for size in &IMAGE_SIZES {
+        let key = format!("{user_id}/{size}-{name}");
+        s3.mv("tmp", &key, "user", &key, &user_id.to_string())
+            .await
+            .map_err(|e| match e {
+                AppError::NotFound(_) => AppError::BadRequest(format!("`{name}` is not an uploaded image")),
+                e => e,
+            })?;
+    }
+    Ok(())
+}
+
+fn check_links(links: &[ProfileLink]) -> Result<(), AppError> {
+    if links.len() > MAX_LINKS {
+        return Err(AppError::BadRequest(format!("At most {MAX_LINKS} links are allowed")));
+    }
+    for link in links {
+        if link.label.trim().is_empty() || link.label.chars().count() > MAX_LINK_LABEL_LEN {
+            return Err(AppError::BadRequest(format!(
+                "Link labels must be 1 to {MAX_LINK_LABEL_LEN} characters"
+            )));
+        }
+        let scheme_ok = link.url.starts_with("https://") || link.url.starts_with("http://");
+        if !scheme_ok || link.url.len() > MAX_LINK_URL_LEN || link.url.contains(char::is_whitespace) {
+            return Err(AppError::BadRequest(format!("`{}` is not an http(s) URL", link.url)));
+        }
+    }
+    Ok(())
+}
+
+/// GET /user/:user_id/profile — the profile with follower, following, blog
+/// and book counts and the newest blogs and books the caller may see.
+pub async fn get_profile(
+    user: Option<Extension<UserId>>,
+    State(pool): State<AppState>,
+    AxumPath(user_id): AxumPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = user.map(|Extension(UserId(id))| id);
+    let conn = pool.pg_pool.conn.get().await?;
+    let profile = load_profile(&conn, user_id).await?;
+    let follows = follow_counts(&conn, user_id).await?;
+    let row = conn
+        .query_one(
+            "SELECT \
+             (SELECT count(*) FROM blogs WHERE user_id = $1 AND deleted_at IS NULL AND (status = 'published' OR user_id = $2::int4)), \
+             (SELECT count(*) FROM books WHERE user_id = $1 AND deleted_at IS NULL AND (status = 'published' OR user_id = $2::int4)), \
+             EXISTS (SELECT 1 FROM subscription WHERE user_id = $1 AND subscribed_id = $2::int4), \
+             EXISTS (SELECT 1 FROM subscription WHERE user_id = $2::int4 AND subscribed_id = $1)",
+            &[&user_id, &viewer],
+        )
+        .await?;
+    let blog_count: i64 = row.get(0);
+    let book_count: i64 = row.get(1);
+    let follows_you: bool = row.get(2);
+    let followed_by_you: bool = row.get(3);
+
+    let keyset = Keyset::first(RECENT_DOCS);
+    let blogs = fetch_blogs_by_user_id!(&conn, user_id, viewer, keyset)?;
+    let books = fetch_books_by_user_id!(&conn, user_id, viewer, keyset)?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(serde_json::json!({
+            "profile": profile,
+            "follower_count": follows.followers,
+            "following_count": follows.following,
+            "blog_count": blog_count,
+            "book_count": book_count,
+            "follows_you": follows_you,
+            "followed_by_you": followed_by_you,
+            "recent_blogs": keyset.page(blogs, |b| (b.created_at, b.uid)).items,
+            "recent_books": keyset.page(books, |b| (b.created_at, b.uid)).items,
+        })),
+    ))
+}
+
+/// POST /user/profile — update the caller's profile.
+pub async fn edit_profile(
+    Extension(UserId(user_id)): Extension<UserId>,
+    State(pool): State<AppState>,
+    Json(body): Json<EditProfile>,
+) -> Result<impl IntoResponse, AppError> {
+    let display_name = check_text("display_name", body.display_name, MAX_DISPLAY_NAME_LEN)?;
+    let bio = check_text("bio", body.bio, MAX_BIO_LEN)?;
+    let avatar = body.avatar.map(|avatar| avatar.trim().to_string());
+    if let Some(avatar) = avatar.as_deref().filter(|avatar| !avatar.is_empty()) {
+        check_avatar(avatar)?;
+    }
+    let links = match &body.links {
+        Some(links) => {
+            check_links(links)?;
+            Some(serde_json::to_string(links).map_err(|e| AppError::InternalServerError(e.to_string()))?)
+        }
+        None => None,
+    };
+
+    let conn = pool.pg_pool.conn.get().await?;
+    let previous = load_profile(&conn, user_id).await?.avatar;
+    // `Some("")` clears the avatar; an unchanged name is already in `user`.
+    let replaced = avatar.as_deref().filter(|avatar| previous.as_deref() != Some(*avatar));
+    if let Some(avatar) = replaced.filter(|avatar| !avatar.is_empty()) {
+        move_avatar(pool.s3(), user_id, avatar).await?;
+    }
+    // NULL keeps the stored value, '' clears it.
+    conn.execute(
+        "INSERT INTO user_profiles(user_id, display_name, bio, avatar, links) \
+         VALUES($1, NULLIF($2, ''), NULLIF($3, ''), NULLIF($4, ''), $5) \
+         ON CONFLICT (user_id) DO UPDATE SET \
+         display_name = CASE WHEN $2::text IS NULL THEN user_profiles.display_name ELSE NULLIF($2, '') END, \
+         bio = CASE WHEN $3::text IS NULL THEN user_profiles.bio ELSE NULLIF($3, '') END, \
+         avatar = CASE WHEN $4::text IS NULL THEN user_profiles.avatar ELSE NULLIF($4, '') END, \
+         links = COALESCE($5, user_profiles.links), \
+         updated_at = now()",
+        &[&user_id, &display_name, &bio, &avatar, &links],
+    )
+    .await?;
+
+    if let (Some(_), Some(previous)) = (replaced, previous) {
+        delete_images_from_s3(&[previous], pool.s3(), "user", user_id).await;
+    }
+
+    let profile = load_profile(&conn, user_id).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(profile),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(label: &str, url: &str) -> ProfileLink {
+        ProfileLink { label: label.into(), url: url.into() }
+    }
+
+    #[test]
+    fn test_profile_checks() {
+        assert_eq!(check_text("bio", Some("  hi  ".into()), 5).unwrap(), Some("hi".into()));
+        assert_eq!(check_text("bio", Some(" ".into()), 5).unwrap(), Some(String::new()));
+        assert!(check_text("bio", Some("toolong".into()), 5).is_err());
+
+        assert!(check_avatar("0f8e.PNG").is_ok());
+        assert!(check_avatar("../7/340-a.png").is_err());
+        assert!(check_avatar("notes.txt").is_err());
+
+        assert!(check_links(&[link("Site", "https://x.dev"), link("Blog", "http://x.dev/b")]).is_ok());
+        assert!(check_links(&[link("Site", "javascript:alert(1)")]).is_err());
+        assert!(check_links(&[link(" ", "https://x.dev")]).is_err());
+        let many: Vec<ProfileLink> = (0..=MAX_LINKS).map(|_| link("a", "https://x.dev")).collect();
+        assert!(check_links(&many).is_err());
+    }
+}
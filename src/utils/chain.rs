@@ -0,0 +1,376 @@
+use crate::error::AppError;
+use crate::revision::require_access;
+use crate::utils::doc::TagTable;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::{Row, Transaction};
+
+/// Position of a node in its document's `parent_id` chain. Blog nodes have
+/// no `identity` or `page_id`.
+#[derive(Debug, Clone)]
+pub struct ChainNode {
+    pub uid: i32,
+    pub doc_id: i32,
+    pub parent_id: Option<i32>,
+    pub identity: Option<i16>,
+    pub page_id: Option<i32>,
+}
+
+impl ChainNode {
+    fn from_row(row: &Row) -> Self {
+        ChainNode {
+            uid: row.get(0),
+            doc_id: row.get(1),
+            parent_id: row.get(2),
+            identity: row.get(3),
+            page_id: row.get(4),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct UpdateNode {
+    uid: i32,
+    parent_id: i32,
+}
+
+fn chain_columns(table: TagTable) -> &'static str {
+    match table {
+        TagTable::Blog => "uid, doc_id, parent_id, NULL::smallint, NULL::int4",
+        TagTable::Book => "uid, doc_id, parent_id, identity, page_id",
+    }
+}
+
+/// A live node by uid.
+pub async fn fetch_chain_node(
+    tx: &Transaction<'_>,
+    table: TagTable,
+    uid: i32,
+) -> Result<Option<ChainNode>, AppError> {
+    let row = tx
+        .query_opt(
+            &format!(
+                "SELECT {} FROM {} WHERE uid = $1 AND deleted_at IS NULL",
+                chain_columns(table),
+                table.node_table()
+            ),
+            &[&uid],
+        )
+        .await?;
+    Ok(row.as_ref().map(ChainNode::from_row))
+}
+
+/// Check that `node` may follow `predecessor`, inside `page` for books.
+///
+/// Nothing can follow itself and the main node never moves. A book node's
+/// page must sit exactly one level above it (101 under 100, 102 under 101,
+/// 103 under 102), and its predecessor is either that page (first child) or
+/// a sibling of the same identity on that page.
+pub fn check_placement(
+    node: &ChainNode,
+    predecessor: &ChainNode,
+    page: Option<&ChainNode>,
+) -> Result<(), AppError> {
+    if node.parent_id.is_none() {
+        return Err(AppError::BadRequest("The main node cannot be moved".into()));
+    }
+    if predecessor.uid == node.uid {
+        return Err(AppError::BadRequest(format!("Node {} cannot follow itself", node.uid)));
+    }
+    if predecessor.doc_id != node.doc_id {
+        return Err(AppError::BadRequest(format!(
+            "Node {} is not in document {}",
+            predecessor.uid, node.doc_id
+        )));
+    }
+    let Some(identity) = node.identity else {
+        return Ok(());
+    };
+    let page = page.ok_or_else(|| AppError::BadRequest("`page_id` is required".into()))?;
+    if page.uid == node.uid || page.doc_id != node.doc_id {
+        return Err(AppError::BadRequest(format!("Node {} cannot be placed under {}", node.uid, page.uid)));
+    }
+    if page.identity != Some(identity - 1) {
+        return Err(AppError::BadRequest(format!(
+            "Nodes with identity {identity} belong under identity {}, not {}",
+            identity - 1,
+            page.identity.map_or("none".to_string(), |i| i.to_string())
+        )));
+    }
+    let is_first = predecessor.uid == page.uid;
+    let is_sibling = predecessor.identity == Some(identity) && predecessor.page_id == Some(page.uid);
+    if !is_first && !is_sibling {
+        return Err(AppError::BadRequest(format!(
+            "Node {} is neither page {} nor one of its identity {identity} nodes",
+            predecessor.uid, page.uid
+        )));
+    }
+    Ok(())
+}
+
+/// Every live node of a document.
+async fn fetch_chain(tx: &Transaction<'_>, table: TagTable, doc_id: i32) -> Result<Vec<ChainNode>, AppError> {
+    let rows = tx
+        .query(
+            &format!(
+                "SELECT {} FROM {} WHERE doc_id = $1 AND deleted_at IS NULL ORDER BY uid",
+                chain_columns(table),
+                table.node_table()
+            ),
+            &[&doc_id],
+        )
+        .await?;
+    Ok(rows.iter().map(ChainNode::from_row).collect())
+}
+
+/// The node directly after `uid` in the chain of `identity` nodes (any node
+/// for blogs), other than `exclude`.
+fn successor(chain: &[ChainNode], uid: i32, identity: Option<i16>, exclude: i32) -> Option<usize> {
+    chain
+        .iter()
+        .position(|n| n.parent_id == Some(uid) && n.uid != exclude && (identity.is_none() || n.identity == identity))
+}
+
+/// Re-stitch `chain`, the live nodes of `node`'s document, so that `node`
+/// follows `predecessor` (inside `page` for books): its old successor takes
+/// its old parent, and the node now following `predecessor` follows it.
+/// Returns the changed nodes, `node` first, or an error if following
+/// `parent_id` from `node` would come back around.
+pub fn plan_move(
+    chain: &[ChainNode],
+    node: &ChainNode,
+    predecessor: &ChainNode,
+    page: Option<&ChainNode>,
+) -> Result<Vec<ChainNode>, AppError> {
+    let page_id = page.map(|page| page.uid);
+    if node.parent_id == Some(predecessor.uid) && node.page_id == page_id {
+        return Ok(Vec::new());
+    }
+    let Some(old_parent) = node.parent_id else {
+        return Err(AppError::BadRequest("The main node cannot be moved".into()));
+    };
+    let mut chain = chain.to_vec();
+    let mut changed: Vec<usize> = Vec::new();
+    let at = chain
+        .iter()
+        .position(|n| n.uid == node.uid)
+        .ok_or_else(|| AppError::NotFound(format!("Node {} not found", node.uid)))?;
+    changed.push(at);
+
+    if let Some(next) = successor(&chain, node.uid, node.identity, node.uid) {
+        chain[next].parent_id = Some(old_parent);
+        changed.push(next);
+    }
+    let next = successor(&chain, predecessor.uid, node.identity, node.uid);
+    chain[at].parent_id = Some(predecessor.uid);
+    chain[at].page_id = page_id;
+    if let Some(next) = next {
+        chain[next].parent_id = Some(node.uid);
+        if !changed.contains(&next) {
+            changed.push(next);
+        }
+    }
+
+    let mut seen = vec![node.uid];
+    let mut parent = chain[at].parent_id;
+    while let Some(uid) = parent {
+        if seen.contains(&uid) {
+            return Err(AppError::BadRequest(format!("Moving node {} would create a cycle", node.uid)));
+        }
+        seen.push(uid);
+        parent = chain.iter().find(|n| n.uid == uid).and_then(|n| n.parent_id);
+    }
+    Ok(changed.into_iter().map(|i| chain[i].clone()).collect())
+}
+
+/// Move `node` to follow `predecessor` (inside `page` for books) with
+/// `plan_move` and write the changed rows. The caller locks the document,
+/// validates with `check_placement` and commits.
+pub async fn move_node(
+    tx: &Transaction<'_>,
+    table: TagTable,
+    node: &ChainNode,
+    predecessor: &ChainNode,
+    page: Option<&ChainNode>,
+) -> Result<serde_json::Value, AppError> {
+    let chain = fetch_chain(tx, table, node.doc_id).await?;
+    let changes = plan_move(&chain, node, predecessor, page)?;
+    let mut update_nodes: Vec<UpdateNode> = Vec::new();
+    for change in &changes {
+        match table {
+            TagTable::Blog => {
+                tx.execute("UPDATE blog SET parent_id = $1 WHERE uid = $2", &[&change.parent_id, &change.uid])
+                    .await?
+            }
+            TagTable::Book => {
+                tx.execute(
+                    "UPDATE book SET parent_id = $1, page_id = $2 WHERE uid = $3",
+                    &[&change.parent_id, &change.page_id, &change.uid],
+                )
+                .await?
+            }
+        };
+        if change.uid != node.uid
+            && let Some(parent_id) = change.parent_id
+        {
+            update_nodes.push(UpdateNode { uid: change.uid, parent_id });
+        }
+    }
+
+    Ok(json!({
+        "uid": node.uid,
+        "parent_id": predecessor.uid,
+        "page_id": page.map(|page| page.uid),
+        "update_nodes": update_nodes
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct MoveNode {
+    uid: i32,
+    /// New predecessor in the chain.
+    parent_id: i32,
+    /// New containing page (books only); defaults to the current one.
+    page_id: Option<i32>,
+}
+
+/// Lock the document, validate and apply one move. Blogs need the author,
+/// books an editor.
+pub async fn move_doc_node(
+    pool: &AppState,
+    table: TagTable,
+    user_id: i32,
+    body: &MoveNode,
+) -> Result<serde_json::Value, AppError> {
+    let not_found = |uid: i32| AppError::NotFound(format!("Node {uid} not found"));
+    let mut conn = pool.pg_pool.conn.get().await?;
+    let doc_id: i32 = conn
+        .query_opt(
+            &format!("SELECT doc_id FROM {} WHERE uid = $1 AND deleted_at IS NULL", table.node_table()),
+            &[&body.uid],
+        )
+        .await?
+        .ok_or_else(|| not_found(body.uid))?
+        .get(0);
+    require_access(&conn, table, doc_id, user_id, true).await?;
+
+    let transaction = conn.transaction().await?;
+    // Serialise moves within a document so concurrent re-stitching cannot interleave.
+    transaction
+        .execute(
+            &format!("SELECT 1 FROM {} WHERE uid = $1 FOR UPDATE", table.doc_table()),
+            &[&doc_id],
+        )
+        .await?;
+    let node = fetch_chain_node(&transaction, table, body.uid)
+        .await?
+        .ok_or_else(|| not_found(body.uid))?;
+    let predecessor = fetch_chain_node(&transaction, table, body.parent_id)
+        .await?
+        .ok_or_else(|| not_found(body.parent_id))?;
+    let page = match body.page_id.or(node.page_id) {
+        Some(page_id) => Some(
+            fetch_chain_node(&transaction, table, page_id)
+                .await?
+                .ok_or_else(|| not_found(page_id))?,
+        ),
+        None => None,
+    };
+    check_placement(&node, &predecessor, page.as_ref())?;
+    let result = move_node(&transaction, table, &node, &predecessor, page.as_ref()).await?;
+    transaction.commit().await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(uid: i32, parent_id: Option<i32>, identity: i16, page_id: Option<i32>) -> ChainNode {
+        ChainNode { uid, doc_id: 1, parent_id, identity: Some(identity), page_id }
+    }
+
+    #[test]
+    fn test_check_placement() {
+        let main = node(10, None, 100, None);
+        let chapter_a = node(11, Some(10), 101, Some(10));
+        let chapter_b = node(12, Some(11), 101, Some(10));
+        let section = node(20, Some(11), 102, Some(11));
+
+        // Reorder chapters, or move a section into another chapter.
+        assert!(check_placement(&chapter_b, &main, Some(&main)).is_ok());
+        assert!(check_placement(&chapter_a, &chapter_b, Some(&main)).is_ok());
+        assert!(check_placement(&section, &chapter_b, Some(&chapter_b)).is_ok());
+
+        assert!(check_placement(&main, &chapter_a, Some(&main)).is_err());
+        assert!(check_placement(&chapter_a, &chapter_a, Some(&main)).is_err());
+        // A chapter cannot become a section, nor a section sit under the front page.
+        assert!(check_placement(&chapter_b, &chapter_a, Some(&chapter_a)).is_err());
+        assert!(check_placement(&section, &main, Some(&main)).is_err());
+        // The predecessor has to live on the target page.
+        assert!(check_placement(&section, &chapter_a, Some(&chapter_b)).is_err());
+
+        let blog_node = ChainNode { uid: 3, doc_id: 1, parent_id: Some(2), identity: None, page_id: None };
+        let other_doc = ChainNode { uid: 4, doc_id: 2, parent_id: Some(1), identity: None, page_id: None };
+        assert!(check_placement(&blog_node, &other_doc, None).is_err());
+    }
+
+    /// Check and plan a move the way `move_doc_node` does, then apply it.
+    fn move_in(chain: &mut [ChainNode], uid: i32, after: i32, page: Option<i32>) -> Result<(), AppError> {
+        let find = |uid: i32| chain.iter().find(|n| n.uid == uid).cloned().unwrap();
+        let (node, predecessor) = (find(uid), find(after));
+        let page = page.or(node.page_id).map(find);
+        check_placement(&node, &predecessor, page.as_ref())?;
+        for change in plan_move(chain, &node, &predecessor, page.as_ref())? {
+            let at = chain.iter().position(|n| n.uid == change.uid).unwrap();
+            chain[at] = change;
+        }
+        Ok(())
+    }
+
+    /// `uid`, then the `identity` chain hanging off it.
+    fn order(chain: &[ChainNode], uid: i32, identity: Option<i16>) -> Vec<i32> {
+        let mut order = vec![uid];
+        while let Some(next) = successor(chain, *order.last().unwrap(), identity, 0) {
+            order.push(chain[next].uid);
+        }
+        order
+    }
+
+    #[test]
+    fn test_move_node() {
+        let blog = |uid: i32, parent_id: Option<i32>| ChainNode { uid, doc_id: 1, parent_id, identity: None, page_id: None };
+        let mut chain = vec![blog(1, None), blog(2, Some(1)), blog(3, Some(2)), blog(4, Some(3))];
+
+        // To the head, then to the tail.
+        move_in(&mut chain, 4, 1, None).unwrap();
+        assert_eq!(order(&chain, 1, None), vec![1, 4, 2, 3]);
+        move_in(&mut chain, 4, 3, None).unwrap();
+        assert_eq!(order(&chain, 1, None), vec![1, 2, 3, 4]);
+        move_in(&mut chain, 2, 4, None).unwrap();
+        assert_eq!(order(&chain, 1, None), vec![1, 3, 4, 2]);
+
+        // A node cannot follow itself, and a move never closes a loop.
+        assert!(move_in(&mut chain, 3, 3, None).is_err());
+        let mut looped = vec![blog(1, None), blog(2, Some(1)), blog(3, Some(4)), blog(4, Some(3))];
+        assert!(move_in(&mut looped, 2, 3, None).is_err());
+
+        // Book chains are per identity: sections of 11 stay put when 12 moves ahead of it.
+        let mut chain = vec![
+            node(10, None, 100, None),
+            node(11, Some(10), 101, Some(10)),
+            node(20, Some(11), 102, Some(11)),
+            node(21, Some(20), 102, Some(11)),
+            node(12, Some(11), 101, Some(10)),
+        ];
+        move_in(&mut chain, 12, 10, None).unwrap();
+        assert_eq!(order(&chain, 10, Some(101)), vec![10, 12, 11]);
+        assert_eq!(order(&chain, 11, Some(102)), vec![11, 20, 21]);
+        // Section 20 moves to the end of chapter 12; 21 closes the gap.
+        move_in(&mut chain, 20, 12, Some(12)).unwrap();
+        assert_eq!(order(&chain, 11, Some(102)), vec![11, 21]);
+        assert_eq!(order(&chain, 12, Some(102)), vec![12, 20]);
+        assert!(move_in(&mut chain, 12, 20, Some(12)).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! Content types of blog and book nodes, stored in the node's `content_type`
+//! column with the codes from docs/permissions.md:
+//!
+//! - `basic` (11): text for the basic renderer; only the size is checked.
+//! - `markdown` (13): code fences must be closed and links well-formed.
+//! - `maths` (17): markdown whose `$…$`, `$$…$$`, `\(…\)` and `\[…\]`
+//!   delimiters must balance. Math is rendered as escaped TeX for the client
+//!   to typeset.
+
+use crate::error::AppError;
+use crate::lifecycle::ensure_visible;
+use crate::utils::doc::TagTable;
+use crate::utils::markdown::{escape_html, render_html, strip_renderer_prefix};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::Client;
+
+/// Largest `content` accepted for a single node, in bytes.
+pub const MAX_CONTENT_BYTES: usize = 256 * 1024;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    #[default]
+    Basic,
+    Markdown,
+    Maths,
+}
+
+impl ContentType {
+    pub fn code(&self) -> i16 {
+        match self {
+            ContentType::Basic => 11,
+            ContentType::Markdown => 13,
+            ContentType::Maths => 17,
+        }
+    }
+
+    /// Unknown codes read as `basic`, the column default.
+    pub fn from_code(code: i16) -> Self {
+        match code {
+            13 => ContentType::Markdown,
+            17 => ContentType::Maths,
+            _ => ContentType::Basic,
+        }
+    }
+
+    /// The type a client sent, or the one implied by the `<basic>` / `<math>`
+    /// prefix of `content` when it sent none. Unprefixed content is markdown.
+    pub fn resolve(explicit: Option<ContentType>, content: &str) -> Self {
+        explicit.unwrap_or_else(|| {
+            if content.starts_with("<math>") {
+                ContentType::Maths
+            } else if content.starts_with("<basic>") {
+                ContentType::Basic
+            } else {
+                ContentType::Markdown
+            }
+        })
+    }
+
+    /// Check `content` against this type. Errors are `BadRequest`s that name
+    /// the offending line.
+    pub fn validate(&self, content: &str) -> Result<(), AppError> {
+        if content.len() > MAX_CONTENT_BYTES {
+            return Err(AppError::BadRequest(format!(
+                "content is {} bytes, the limit is {MAX_CONTENT_BYTES}",
+                content.len()
+            )));
+        }
+        let text = strip_renderer_prefix(content);
+        let checked = match self {
+            ContentType::Basic => Ok(()),
+            ContentType::Markdown => check_markdown(text),
+            ContentType::Maths => check_markdown(text).and_then(|_| math_spans(text).map(|_| ())),
+        };
+        checked.map_err(|message| AppError::BadRequest(format!("invalid {} content: {message}", self.name())))
+    }
+
+    /// Sanitised HTML for `content`.
+    pub fn render(&self, content: &str) -> String {
+        let text = strip_renderer_prefix(content);
+        let spans = match self {
+            ContentType::Maths => math_spans(text).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        if spans.is_empty() {
+            return render_html(text);
+        }
+        // Swap math out for placeholders the renderer leaves alone, so `_`
+        // and `*` inside TeX are not read as emphasis.
+        let mut source = String::with_capacity(text.len());
+        let mut last = 0;
+        for (i, span) in spans.iter().enumerate() {
+            source.push_str(&text[last..span.start]);
+            source.push_str(&format!("\u{e000}{i}\u{e001}"));
+            last = span.end;
+        }
+        source.push_str(&text[last..]);
+        let mut html = render_html(&source);
+        for (i, span) in spans.iter().enumerate() {
+            let class = if span.display { "math display" } else { "math inline" };
+            html = html.replace(
+                &format!("\u{e000}{i}\u{e001}"),
+                &format!("<span class=\"{class}\">{}</span>", escape_html(&text[span.start..span.end])),
+            );
+        }
+        html
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ContentType::Basic => "basic",
+            ContentType::Markdown => "markdown",
+            ContentType::Maths => "maths",
+        }
+    }
+}
+
+/// `{uid, doc_id, title, content_type, html}` for a live node of a document
+/// `viewer` may read; `NotFound` otherwise.
+pub async fn node_html(
+    conn: &Client,
+    table: TagTable,
+    uid: i32,
+    viewer: Option<i32>,
+) -> Result<serde_json::Value, AppError> {
+    let row = conn
+        .query_opt(
+            &format!(
+                "SELECT doc_id, title, content, content_type FROM {} WHERE uid = $1 AND deleted_at IS NULL",
+                table.node_table()
+            ),
+            &[&uid],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Node {uid} not found")))?;
+    let doc_id: i32 = row.get(0);
+    ensure_visible(conn, table, doc_id, viewer).await?;
+
+    let title: String = row.get(1);
+    let content: String = row.get(2);
+    let content_type = ContentType::from_code(row.get(3));
+    Ok(json!({
+        "uid": uid,
+        "doc_id": doc_id,
+        "title": title,
+        "content_type": content_type,
+        "html": content_type.render(&content),
+    }))
+}
+
+fn fence(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    (trimmed.starts_with("```") || trimmed.starts_with("~~~")).then(|| &trimmed[..3])
+}
+
+/// Fences are closed and every `](` link target has its `)`.
+fn check_markdown(text: &str) -> Result<(), String> {
+    let mut open: Option<(&str, usize)> = None;
+    for (n, line) in text.lines().enumerate() {
+        if let Some((marker, _)) = open {
+            if line.trim_start().starts_with(marker) {
+                open = None;
+            }
+            continue;
+        }
+        if let Some(marker) = fence(line) {
+            open = Some((marker, n + 1));
+            continue;
+        }
+        let mut rest = line;
+        while let Some(at) = rest.find("](") {
+            let target = &rest[at + 2..];
+            let mut depth = 1;
+            let close = target.char_indices().find(|&(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            });
+            let Some((end, _)) = close else {
+                return Err(format!("link on line {} is missing its closing `)`", n + 1));
+            };
+            rest = &target[end..];
+        }
+    }
+    match open {
+        Some((_, line)) => Err(format!("code fence opened on line {line} is never closed")),
+        None => Ok(()),
+    }
+}
+
+/// Byte range of one math span in the text, delimiters included.
+#[derive(Debug, PartialEq)]
+struct MathSpan {
+    start: usize,
+    end: usize,
+    display: bool,
+}
+
+/// Every math span outside code. `$…$` and `\(…\)` must close on the line
+/// they open; `$$…$$` and `\[…\]` may run over several lines. `\$` is a
+/// literal dollar.
+fn math_spans(text: &str) -> Result<Vec<MathSpan>, String> {
+    let mut spans = Vec::new();
+    // (start, closing delimiter, line)
+    let mut open: Option<(usize, &str, usize)> = None;
+    let mut fenced: Option<&str> = None;
+    let mut offset = 0;
+    for (n, line) in text.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += line.len();
+        if open.is_none() {
+            if let Some(marker) = fenced {
+                if line.trim_start().starts_with(marker) {
+                    fenced = None;
+                }
+                continue;
+            }
+            if let Some(marker) = fence(line) {
+                fenced = Some(marker);
+                continue;
+            }
+        }
+
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            let step = rest.chars().next().map_or(1, char::len_utf8);
+            if let Some((start, close, _)) = open {
+                // An inline `$` closes only right after non-space.
+                let closes = rest.starts_with(close)
+                    && (close != "$" || !line[..i].ends_with(char::is_whitespace));
+                if closes {
+                    spans.push(MathSpan {
+                        start,
+                        end: line_start + i + close.len(),
+                        display: matches!(close, "$$" | "\\]"),
+                    });
+                    open = None;
+                    i += close.len();
+                } else if rest.starts_with('\\') {
+                    i += 1 + line[i + 1..].chars().next().map_or(0, char::len_utf8);
+                } else {
+                    i += step;
+                }
+                continue;
+            }
+            let delimiter = [("$$", "$$"), ("\\[", "\\]"), ("\\(", "\\)")]
+                .into_iter()
+                .find(|(opener, _)| rest.starts_with(opener));
+            if let Some((opener, close)) = delimiter {
+                open = Some((line_start + i, close, n + 1));
+                i += opener.len();
+            } else if rest.starts_with('$') && rest[1..].starts_with(|c: char| !c.is_whitespace()) {
+                open = Some((line_start + i, "$", n + 1));
+                i += 1;
+            } else if rest.starts_with('\\') {
+                i += 1 + line[i + 1..].chars().next().map_or(0, char::len_utf8);
+            } else if rest.starts_with('`') {
+                // Skip a code span; an unmatched run is literal.
+                let run = rest.chars().take_while(|&c| c == '`').count();
+                let ticks = &rest[..run];
+                i += run + rest[run..].find(ticks).map_or(0, |end| end + run);
+            } else {
+                i += step;
+            }
+        }
+        if let Some((_, close @ ("$" | "\\)"), line)) = open {
+            let opener = if close == "$" { "$" } else { "\\(" };
+            return Err(format!(
+                "`{opener}` on line {line} is not closed on the same line (write `\\$` for a literal dollar)"
+            ));
+        }
+    }
+    match open {
+        Some((_, close, line)) => {
+            let opener = if close == "$$" { "$$" } else { "\\[" };
+            Err(format!("`{opener}` opened on line {line} is never closed"))
+        }
+        None => Ok(spans),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(content_type: ContentType, content: &str) -> String {
+        match content_type.validate(content) {
+            Err(AppError::BadRequest(message)) => message,
+            _ => panic!("expected a BadRequest for {content:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validate_and_render() {
+        assert_eq!(ContentType::resolve(None, "<math> $x$"), ContentType::Maths);
+        assert_eq!(ContentType::resolve(None, "# Hi"), ContentType::Markdown);
+        assert_eq!(ContentType::resolve(Some(ContentType::Basic), "# Hi"), ContentType::Basic);
+        assert_eq!(ContentType::from_code(ContentType::Maths.code()), ContentType::Maths);
+
+        // Basic only checks the size.
+        assert!(ContentType::Basic.validate("```\n[a](b").is_ok());
+        assert!(error(ContentType::Basic, &"x".repeat(MAX_CONTENT_BYTES + 1)).contains("the limit is"));
+        assert_eq!(
+            error(ContentType::Markdown, "text\n```rust\nfn main() {}\n"),
+            "invalid markdown content: code fence opened on line 2 is never closed"
+        );
+        assert!(error(ContentType::Markdown, "ok\n[a](http://x").contains("line 2"));
+
+        let maths = "Let $a_1 * b_2$ and `$not math` cost \\$5.\n\n$$\n\\sum_i x_i\n$$\n\n```\n$ in code\n```";
+        assert!(ContentType::Maths.validate(maths).is_ok());
+        assert!(error(ContentType::Maths, "costs $5 and $ 10").contains("`$` on line 1 is not closed"));
+        assert!(error(ContentType::Maths, "a\n\\[ x").contains("`\\[` opened on line 2 is never closed"));
+
+        assert_eq!(
+            ContentType::Maths.render("<math> If $a_1 < b_2$ then *so*."),
+            "<p>If <span class=\"math inline\">$a_1 &lt; b_2$</span> then <em>so</em>.</p>\n"
+        );
+        assert_eq!(
+            ContentType::Markdown.render("<script>x</script>"),
+            "<p>&lt;script&gt;x&lt;/script&gt;</p>\n"
+        );
+    }
+}
@@ -1,12 +1,153 @@
-#[cfg(test)]
-pub fn insert_tags(table_name: &str, columns: &str, tags: Vec<(i32, i32, &str, i32)>) -> String {
-    let mut query = format!("INSERT INTO {} {} VALUES ", table_name, columns);
-    let mut values = Vec::new();
+use crate::blog::utils::remove_stopwords;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{Client, Transaction};
 
-    for (doc_id, user_id, name, score) in tags.iter() {
-        values.push(format!("({}, {}, '{}', {})", doc_id, user_id, name, score));
+/// Maximum number of tags stored per request.
+const MAX_TAGS: usize = 20;
+/// Maximum length (in characters) of a single normalised tag.
+const MAX_TAG_LEN: usize = 64;
+/// Default and upper bound for the number of entries in a tag cloud.
+const DEFAULT_TAG_CLOUD_LIMIT: i64 = 50;
+const MAX_TAG_CLOUD_LIMIT: i64 = 200;
+
+/// Tag tables keyed by the document kind they belong to.
+#[derive(Clone, Copy)]
+pub enum TagTable {
+    Blog,
+    Book,
+}
+
+impl TagTable {
+    pub(crate) fn table(&self) -> &'static str {
+        match self {
+            TagTable::Blog => "blog_tags",
+            TagTable::Book => "book_tags",
+        }
+    }
+
+    pub(crate) fn doc_table(&self) -> &'static str {
+        match self {
+            TagTable::Blog => "blogs",
+            TagTable::Book => "books",
+        }
+    }
+
+    /// Node table holding a document's main node and its children.
+    pub(crate) fn node_table(&self) -> &'static str {
+        match self {
+            TagTable::Blog => "blog",
+            TagTable::Book => "book",
+        }
+    }
+
+    /// `doc_type` stored alongside doc ids in shared tables (1 = blog, 2 = book).
+    pub(crate) fn doc_type(&self) -> i16 {
+        match self {
+            TagTable::Blog => 1,
+            TagTable::Book => 2,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TagCloudRequest {
+    limit: Option<i64>,
+}
+
+impl TagCloudRequest {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_TAG_CLOUD_LIMIT)
+            .clamp(1, MAX_TAG_CLOUD_LIMIT)
     }
+}
+
+#[derive(Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+/// Lowercase each tag, strip stop words and punctuation, then drop empties
+/// and duplicates. Order of first appearance is preserved.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let cleaned: String = tag
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric() || c.is_whitespace() || matches!(c, '-' | '_' | '\'' | '+' | '#' | '.'))
+            .collect();
+        let tag: String = remove_stopwords(&cleaned)
+            .join(" ")
+            .chars()
+            .take(MAX_TAG_LEN)
+            .collect();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+        if normalized.len() == MAX_TAGS {
+            break;
+        }
+    }
+    normalized
+}
+
+/// Normalise a single tag taken from a request path.
+pub fn normalize_tag(raw: &str) -> Result<String, AppError> {
+    normalize_tags(&[raw.to_string()])
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::BadRequest(format!("`{raw}` is not a valid tag")))
+}
+
+/// Insert tags for a document, skipping any already attached to it.
+pub async fn insert_tags(
+    tx: &Transaction<'_>,
+    table: TagTable,
+    doc_id: i32,
+    user_id: i32,
+    tags: &[String],
+) -> Result<u64, AppError> {
+    if tags.is_empty() {
+        return Ok(0);
+    }
+    let score: i32 = 1;
+    let query = format!(
+        "INSERT INTO {table} (doc_id, user_id, tag, score) \
+         SELECT $1, $2, t, $4 FROM UNNEST($3::text[]) AS t \
+         WHERE NOT EXISTS (SELECT 1 FROM {table} WHERE doc_id = $1 AND tag = t)",
+        table = table.table()
+    );
+    Ok(tx.execute(&query, &[&doc_id, &user_id, &tags, &score]).await?)
+}
+
+/// All tags attached to a document, alphabetically.
+pub async fn fetch_tags(conn: &Client, table: TagTable, doc_id: i32) -> Result<Vec<String>, AppError> {
+    let query = format!(
+        "SELECT DISTINCT tag FROM {} WHERE doc_id = $1 ORDER BY tag",
+        table.table()
+    );
+    let rows = conn.query(&query, &[&doc_id]).await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
 
-    query.push_str(&values.join(", "));
-    query
+/// Tags ranked by the number of live documents carrying them.
+pub async fn tag_cloud(conn: &Client, table: TagTable, limit: i64) -> Result<Vec<TagCount>, AppError> {
+    let query = format!(
+        "SELECT t.tag, COUNT(DISTINCT t.doc_id) AS count FROM {} t \
+         JOIN {} d ON d.uid = t.doc_id AND d.deleted_at IS NULL AND d.status = 'published' \
+         GROUP BY t.tag ORDER BY count DESC, t.tag LIMIT $1",
+        table.table(),
+        table.doc_table()
+    );
+    let rows = conn.query(&query, &[&limit]).await?;
+    Ok(rows
+        .iter()
+        .map(|row| TagCount {
+            tag: row.get(0),
+            count: row.get(1),
+        })
+        .collect())
 }
@@ -0,0 +1,258 @@
+//! YAML front matter for imported markdown files:
+//!
+//! ```text
+//! ---
+//! title: "Installing on Linux"
+//! order: 3
+//! tags: [postgres, linux]
+//! draft: false
+//! slug: install-linux
+//! ---
+//! ```
+//!
+//! Only the subset these keys need is understood: `key: value` pairs with
+//! plain, single- or double-quoted scalars, and lists written either inline
+//! (`[a, b]`) or as `- item` lines. Unknown keys are ignored so files coming
+//! from static site generators import as-is.
+
+use crate::error::AppError;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub order: Option<u32>,
+    pub tags: Vec<String>,
+    pub draft: bool,
+    pub slug: Option<String>,
+}
+
+enum Value {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+/// Split `content` into its front matter and the rest of the file. A file
+/// without a leading `---` line has default front matter. Errors are
+/// `BadRequest`s naming `file`.
+pub fn split<'a>(file: &str, content: &'a str) -> Result<(FrontMatter, &'a str), AppError> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) else {
+        return Ok((FrontMatter::default(), content));
+    };
+    let error = |line: usize, message: String| {
+        AppError::BadRequest(format!("{file}: invalid front matter on line {line}: {message}"))
+    };
+
+    let mut lines: Vec<&str> = Vec::new();
+    let mut body = None;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            body = Some(&rest[offset..]);
+            break;
+        }
+        lines.push(trimmed);
+    }
+    let body = body.ok_or_else(|| {
+        AppError::BadRequest(format!("{file}: front matter is not closed with `---`"))
+    })?;
+
+    let mut front = FrontMatter::default();
+    let mut seen: Vec<&str> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        // Line numbers count the opening `---` as line 1.
+        let number = i + 2;
+        let line = lines[i];
+        i += 1;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            return Err(error(number, "unexpected indentation".into()));
+        }
+        let (key, raw) = line
+            .split_once(':')
+            .ok_or_else(|| error(number, format!("expected `key: value`, found `{line}`")))?;
+        let key = key.trim();
+        if seen.contains(&key) {
+            return Err(error(number, format!("`{key}` is set twice")));
+        }
+        seen.push(key);
+
+        let raw = strip_comment(raw).trim();
+        let value = if raw.is_empty() {
+            // A block list: the following `- item` lines.
+            let mut items = Vec::new();
+            while i < lines.len() {
+                let Some(item) = lines[i].trim_start().strip_prefix('-') else { break };
+                if !lines[i].starts_with([' ', '\t', '-']) {
+                    break;
+                }
+                items.push(scalar(strip_comment(item).trim()).map_err(|e| error(i + 2, e))?);
+                i += 1;
+            }
+            Value::List(items)
+        } else if let Some(inner) = raw.strip_prefix('[') {
+            let inner = inner
+                .strip_suffix(']')
+                .ok_or_else(|| error(number, "list is missing its closing `]`".into()))?;
+            Value::List(flow_items(inner).map_err(|e| error(number, e))?)
+        } else {
+            Value::Scalar(scalar(raw).map_err(|e| error(number, e))?)
+        };
+
+        match (key, value) {
+            ("title", Value::Scalar(title)) if !title.trim().is_empty() => {
+                front.title = Some(title.trim().to_string());
+            }
+            ("title", _) => return Err(error(number, "`title` must be a non-empty string".into())),
+            ("order", Value::Scalar(order)) => {
+                front.order = Some(order.parse().map_err(|_| {
+                    error(number, format!("`order` must be a non-negative integer, found `{order}`"))
+                })?);
+            }
+            ("order", Value::List(_)) => {
+                return Err(error(number, "`order` must be a non-negative integer".into()))
+            }
+            ("tags", Value::List(tags)) => front.tags = tags,
+            ("tags", Value::Scalar(tag)) => front.tags = vec![tag],
+            ("draft", Value::Scalar(draft)) => {
+                front.draft = match draft.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    other => {
+                        return Err(error(number, format!("`draft` must be true or false, found `{other}`")))
+                    }
+                };
+            }
+            ("draft", Value::List(_)) => return Err(error(number, "`draft` must be true or false".into())),
+            ("slug", Value::Scalar(slug)) if is_slug(&slug) => front.slug = Some(slug),
+            ("slug", _) => {
+                return Err(error(
+                    number,
+                    "`slug` must be lowercase letters, digits and single dashes".into(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok((front, body))
+}
+
+/// Drop a trailing ` # comment` outside quotes.
+fn strip_comment(raw: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (at, c) in raw.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if prev.is_whitespace() => return &raw[..at],
+            _ => {}
+        }
+        prev = c;
+    }
+    raw
+}
+
+fn scalar(raw: &str) -> Result<String, String> {
+    if let Some(inner) = raw.strip_prefix('"') {
+        let inner = inner.strip_suffix('"').ok_or("unterminated `\"` string")?;
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(c @ ('"' | '\\')) => out.push(c),
+                Some(c) => return Err(format!("unknown escape `\\{c}`")),
+                None => return Err("unterminated `\"` string".into()),
+            }
+        }
+        return Ok(out);
+    }
+    if let Some(inner) = raw.strip_prefix('\'') {
+        let inner = inner.strip_suffix('\'').ok_or("unterminated `'` string")?;
+        return Ok(inner.replace("''", "'"));
+    }
+    Ok(raw.to_string())
+}
+
+/// Items of an inline `[a, "b, c"]` list, without the brackets.
+fn flow_items(inner: &str) -> Result<Vec<String>, String> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (at, c) in inner.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, ',') => {
+                items.push(scalar(inner[start..at].trim())?);
+                start = at + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = inner[start..].trim();
+    if !last.is_empty() {
+        items.push(scalar(last)?);
+    }
+    Ok(items)
+}
+
+fn is_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.split('-').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let (front, body) = split(
+            "a.md",
+            "---\ntitle: \"Say \\\"hi\\\"\" # comment\norder: 2\ntags: [rust, 'web, http']\ndraft: true\nslug: say-hi\nauthor: me\n---\n# Body\n",
+        )
+        .unwrap();
+        assert_eq!(
+            front,
+            FrontMatter {
+                title: Some("Say \"hi\"".into()),
+                order: Some(2),
+                tags: vec!["rust".into(), "web, http".into()],
+                draft: true,
+                slug: Some("say-hi".into()),
+            }
+        );
+        assert_eq!(body, "# Body\n");
+
+        let (front, _) = split("a.md", "---\ntags:\n  - one\n  - \"two\"\n---\n").unwrap();
+        assert_eq!(front.tags, vec!["one".to_string(), "two".to_string()]);
+        assert_eq!(split("a.md", "# No front matter").unwrap().1, "# No front matter");
+
+        let message = |content: &str| match split("ch/_index.md", content) {
+            Err(AppError::BadRequest(message)) => message,
+            _ => panic!("expected a BadRequest for {content:?}"),
+        };
+        assert_eq!(
+            message("---\norder: first\n---\n"),
+            "ch/_index.md: invalid front matter on line 2: `order` must be a non-negative integer, found `first`"
+        );
+        assert_eq!(message("---\ntitle: x\n"), "ch/_index.md: front matter is not closed with `---`");
+        assert!(message("---\nslug: Not A Slug\n---\n").contains("line 2: `slug`"));
+        assert!(message("---\ndraft: yes\n---\n").contains("`draft` must be true or false"));
+        assert!(message("---\ntitle: a\ntitle: b\n---\n").contains("line 3: `title` is set twice"));
+    }
+}
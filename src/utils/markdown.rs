@@ -0,0 +1,361 @@
+//! A small Markdown → XHTML renderer for server-side output (EPUB, node
+//! previews). It covers the subset our editors produce — headings, paragraphs,
+//! fenced code, lists, block quotes, rules, emphasis, code spans, links and
+//! images — and is safe by construction: every piece of source text is
+//! escaped, raw HTML is never passed through, and only `http(s)`, `mailto`
+//! and relative URLs survive. Output is well-formed XML.
+
+/// Escape text for use in HTML/XML content and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Strip the `<basic>` / `<math>` renderer prefix stored in `content`.
+pub fn strip_renderer_prefix(content: &str) -> &str {
+    for prefix in ["<basic>", "<math>"] {
+        if let Some(rest) = content.strip_prefix(prefix) {
+            return rest.trim_start();
+        }
+    }
+    content
+}
+
+/// Render `markdown` to XHTML, keeping image sources as written.
+pub fn render_html(markdown: &str) -> String {
+    render_html_with(markdown, &|src| src.to_string())
+}
+
+/// Render `markdown` to XHTML, passing every image source through
+/// `image_src` first (e.g. to point at files bundled next to the page).
+pub fn render_html_with(markdown: &str, image_src: &dyn Fn(&str) -> String) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut out = String::new();
+    render_blocks(&lines, image_src, &mut out);
+    out
+}
+
+enum ListKind {
+    Unordered,
+    Ordered,
+}
+
+fn list_item(line: &str) -> Option<(ListKind, &str)> {
+    let trimmed = line.trim_start();
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = trimmed.strip_prefix(marker) {
+            return Some((ListKind::Unordered, rest));
+        }
+    }
+    let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0
+        && digits <= 9
+        && let Some(rest) = trimmed[digits..].strip_prefix(". ")
+    {
+        return Some((ListKind::Ordered, rest));
+    }
+    None
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&level)
+        && let Some(rest) = line[level..].strip_prefix(' ')
+    {
+        return Some((level, rest.trim().trim_end_matches('#').trim_end()));
+    }
+    None
+}
+
+fn is_rule(line: &str) -> bool {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&m| compact.chars().all(|c| c == m))
+}
+
+fn fence(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    trimmed
+        .strip_prefix("```")
+        .or_else(|| trimmed.strip_prefix("~~~"))
+}
+
+fn starts_block(line: &str) -> bool {
+    heading(line).is_some()
+        || fence(line).is_some()
+        || line.trim_start().starts_with('>')
+        || list_item(line).is_some()
+        || is_rule(line)
+}
+
+fn render_blocks(lines: &[&str], image_src: &dyn Fn(&str) -> String, out: &mut String) {
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.trim().is_empty() {
+            i += 1;
+            continue;
+        }
+        if let Some(info) = fence(line) {
+            let marker = &line.trim_start()[..3];
+            let lang = info.split_whitespace().next().unwrap_or("");
+            let mut code: Vec<&str> = Vec::new();
+            i += 1;
+            while i < lines.len() && !lines[i].trim_start().starts_with(marker) {
+                code.push(lines[i]);
+                i += 1;
+            }
+            i += 1; // closing fence (or end of input)
+            if lang.is_empty() {
+                out.push_str("<pre><code>");
+            } else {
+                out.push_str(&format!("<pre><code class=\"language-{}\">", escape_html(lang)));
+            }
+            out.push_str(&escape_html(&code.join("\n")));
+            out.push_str("</code></pre>\n");
+            continue;
+        }
+        if let Some((level, text)) = heading(line) {
+            out.push_str(&format!("<h{level}>{}</h{level}>\n", render_inline(text, image_src)));
+            i += 1;
+            continue;
+        }
+        if is_rule(line) {
+            out.push_str("<hr />\n");
+            i += 1;
+            continue;
+        }
+        if line.trim_start().starts_with('>') {
+            let mut quoted: Vec<&str> = Vec::new();
+            while i < lines.len() && lines[i].trim_start().starts_with('>') {
+                let rest = &lines[i].trim_start()[1..];
+                quoted.push(rest.strip_prefix(' ').unwrap_or(rest));
+                i += 1;
+            }
+            out.push_str("<blockquote>\n");
+            render_blocks(&quoted, image_src, out);
+            out.push_str("</blockquote>\n");
+            continue;
+        }
+        if let Some((kind, _)) = list_item(line) {
+            let tag = match kind {
+                ListKind::Unordered => "ul",
+                ListKind::Ordered => "ol",
+            };
+            out.push_str(&format!("<{tag}>\n"));
+            while i < lines.len() {
+                let Some((_, first)) = list_item(lines[i]) else { break };
+                let mut item = vec![first];
+                i += 1;
+                // Indented continuation lines belong to the item.
+                while i < lines.len()
+                    && !lines[i].trim().is_empty()
+                    && lines[i].starts_with([' ', '\t'])
+                    && list_item(lines[i]).is_none()
+                {
+                    item.push(lines[i].trim());
+                    i += 1;
+                }
+                out.push_str(&format!("<li>{}</li>\n", render_inline(&item.join("\n"), image_src)));
+            }
+            out.push_str(&format!("</{tag}>\n"));
+            continue;
+        }
+        let mut paragraph = vec![line.trim()];
+        i += 1;
+        while i < lines.len() && !lines[i].trim().is_empty() && !starts_block(lines[i]) {
+            paragraph.push(lines[i].trim());
+            i += 1;
+        }
+        out.push_str(&format!("<p>{}</p>\n", render_inline(&paragraph.join("\n"), image_src)));
+    }
+}
+
+/// Only keep URLs that cannot run script.
+fn safe_url(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let lower = url.to_ascii_lowercase();
+    match lower.split_once(':') {
+        Some((scheme, _)) if !scheme.contains('/') => {
+            matches!(scheme, "http" | "https" | "mailto").then_some(url)
+        }
+        _ => Some(url),
+    }
+}
+
+/// `[text](url)` starting at `chars[start]` (the `[`): returns text, url and
+/// the index just past the closing `)`.
+fn bracketed(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let mut depth = 0;
+    let mut close = None;
+    for (j, &c) in chars.iter().enumerate().skip(start) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(j);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let close = close?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut end = None;
+    for (j, &c) in chars.iter().enumerate().skip(close + 1) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(j);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let end = end?;
+    let text: String = chars[start + 1..close].iter().collect();
+    let url: String = chars[close + 2..end].iter().collect();
+    // Drop an optional "title" after the URL.
+    let url = url.split_whitespace().next().unwrap_or("").to_string();
+    Some((text, url, end + 1))
+}
+
+fn render_inline(text: &str, image_src: &dyn Fn(&str) -> String) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if i + 1 < chars.len() && chars[i + 1].is_ascii_punctuation() => {
+                out.push_str(&escape_html(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let fence: String = "`".repeat(run);
+                let rest: String = chars[i + run..].iter().collect();
+                match rest.find(&fence) {
+                    Some(end) => {
+                        out.push_str(&format!("<code>{}</code>", escape_html(rest[..end].trim())));
+                        i += run + rest[..end].chars().count() + run;
+                    }
+                    None => {
+                        out.push_str(&fence);
+                        i += run;
+                    }
+                }
+            }
+            '!' if chars.get(i + 1) == Some(&'[') => match bracketed(&chars, i + 1) {
+                Some((alt, url, next)) => {
+                    match safe_url(&url) {
+                        Some(url) => out.push_str(&format!(
+                            "<img src=\"{}\" alt=\"{}\" />",
+                            escape_html(&image_src(url)),
+                            escape_html(&alt)
+                        )),
+                        None => out.push_str(&escape_html(&alt)),
+                    }
+                    i = next;
+                }
+                None => {
+                    out.push('!');
+                    i += 1;
+                }
+            },
+            '[' => match bracketed(&chars, i) {
+                Some((label, url, next)) => {
+                    let label = render_inline(&label, image_src);
+                    match safe_url(&url) {
+                        Some(url) => out.push_str(&format!("<a href=\"{}\">{label}</a>", escape_html(url))),
+                        None => out.push_str(&label),
+                    }
+                    i = next;
+                }
+                None => {
+                    out.push('[');
+                    i += 1;
+                }
+            },
+            '*' | '_' => {
+                let strong = chars.get(i + 1) == Some(&c);
+                let width = if strong { 2 } else { 1 };
+                let marker: String = std::iter::repeat_n(c, width).collect();
+                let rest: String = chars[i + width..].iter().collect();
+                let opens = rest.chars().next().is_some_and(|n| !n.is_whitespace());
+                // `snake_case` stays literal.
+                let intraword = c == '_' && i > 0 && chars[i - 1].is_alphanumeric();
+                match rest.find(&marker).filter(|&end| opens && !intraword && end > 0) {
+                    Some(end) => {
+                        let tag = if strong { "strong" } else { "em" };
+                        out.push_str(&format!("<{tag}>{}</{tag}>", render_inline(&rest[..end], image_src)));
+                        i += width + rest[..end].chars().count() + width;
+                    }
+                    None => {
+                        out.push_str(&marker);
+                        i += width;
+                    }
+                }
+            }
+            '\n' => {
+                out.push('\n');
+                i += 1;
+            }
+            _ => {
+                out.push_str(&escape_html(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html() {
+        let html = render_html(
+            "# Title\n\nSome **bold** and *em* with `a<b>`.\n\n- one\n- [two](https://x.dev)\n\n```rust\nfn main() {}\n```\n\n> quoted",
+        );
+        assert_eq!(
+            html,
+            "<h1>Title</h1>\n\
+             <p>Some <strong>bold</strong> and <em>em</em> with <code>a&lt;b&gt;</code>.</p>\n\
+             <ul>\n<li>one</li>\n<li><a href=\"https://x.dev\">two</a></li>\n</ul>\n\
+             <pre><code class=\"language-rust\">fn main() {}</code></pre>\n\
+             <blockquote>\n<p>quoted</p>\n</blockquote>\n"
+        );
+
+        // Raw HTML and script URLs never make it through.
+        assert_eq!(
+            render_html("<script>x</script> [a](javascript:alert(1)) snake_case_name"),
+            "<p>&lt;script&gt;x&lt;/script&gt; a snake_case_name</p>\n"
+        );
+        assert_eq!(
+            render_html_with("![fig](a.png)", &|src| format!("images/{src}")),
+            "<p><img src=\"images/a.png\" alt=\"fig\" /></p>\n"
+        );
+    }
+}
@@ -1,4 +1,9 @@
+pub mod chain;
+pub mod content;
 pub mod doc;
+pub mod front_matter;
+pub mod markdown;
+pub mod page;
 
 /// User ID injected into request extensions by the `require_auth` middleware.
 #[derive(Clone, Copy)]
@@ -0,0 +1,166 @@
+use crate::error::AppError;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Hard upper bound on `?limit=` for every listing route.
+pub const MAX_PAGE_SIZE: i64 = 50;
+/// Page size used by listing routes when the client does not ask for one.
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+/// Page size for the home feed routes, which historically showed five rows.
+pub const HOME_PAGE_SIZE: i64 = 5;
+
+/// `?after=<created_at>,<uid>&limit=N&sort=latest|popular` as accepted by
+/// listing routes.
+#[derive(Deserialize)]
+pub struct PageRequest {
+    after: Option<String>,
+    limit: Option<i64>,
+    sort: Option<String>,
+}
+
+/// Resolved keyset bounds, ready to bind into a
+/// `(created_at, uid) < ($n, $n+1) ORDER BY created_at DESC, uid DESC` query,
+/// or the `after` / `order_by` clauses when the route honours `sort`.
+pub struct Keyset {
+    pub created_at: Option<DateTime<Utc>>,
+    pub uid: Option<i32>,
+    pub limit: i64,
+    /// `?sort=popular`: most liked first, newest first among equals.
+    pub popular: bool,
+}
+
+/// Listing envelope returned by every paginated route.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl PageRequest {
+    /// Parse the cursor and clamp the limit, falling back to `default_limit`.
+    pub fn keyset(&self, default_limit: i64) -> Result<Keyset, AppError> {
+        let (created_at, uid) = match self.after.as_deref() {
+            Some(raw) if !raw.is_empty() => {
+                let (created_at, uid) = decode_cursor(raw)?;
+                (Some(created_at), Some(uid))
+            }
+            _ => (None, None),
+        };
+        let popular = match self.sort.as_deref() {
+            None | Some("latest") => false,
+            Some("popular") => true,
+            Some(other) => return Err(AppError::BadRequest(format!("Unknown sort `{other}`"))),
+        };
+        Ok(Keyset {
+            created_at,
+            uid,
+            limit: self.limit.unwrap_or(default_limit).clamp(1, MAX_PAGE_SIZE),
+            popular,
+        })
+    }
+}
+
+impl Keyset {
+    /// The first `limit` rows, newest first, for previews that never page.
+    pub fn first(limit: i64) -> Self {
+        Keyset { created_at: None, uid: None, limit, popular: false }
+    }
+
+    /// Rows to request from Postgres: one extra to learn whether a next page exists.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Condition keeping rows after the cursor bound as `$at` (created_at) and
+    /// `$at+1` (uid). `prefix` qualifies columns of `table`, e.g. `"b."`.
+    /// Popular pages compare against the cursor row's current `like_count`,
+    /// so a row whose count changes between requests may move pages.
+    pub fn after(&self, table: &str, prefix: &str, at: usize) -> String {
+        let uid = at + 1;
+        if self.popular {
+            format!(
+                "(${at}::timestamptz IS NULL OR ({prefix}like_count, {prefix}created_at, {prefix}uid) < \
+                 ((SELECT k.like_count FROM {table} k WHERE k.uid = ${uid}::int4), ${at}::timestamptz, ${uid}::int4))"
+            )
+        } else {
+            format!(
+                "(${at}::timestamptz IS NULL OR ({prefix}created_at, {prefix}uid) < (${at}::timestamptz, ${uid}::int4))"
+            )
+        }
+    }
+
+    /// `ORDER BY` matching `after`.
+    pub fn order_by(&self, prefix: &str) -> String {
+        if self.popular {
+            format!("ORDER BY {prefix}like_count DESC, {prefix}created_at DESC, {prefix}uid DESC")
+        } else {
+            format!("ORDER BY {prefix}created_at DESC, {prefix}uid DESC")
+        }
+    }
+
+    /// Trim the look-ahead row and derive `next_cursor` from the last item kept.
+    pub fn page<T>(&self, mut items: Vec<T>, key: impl Fn(&T) -> (DateTime<Utc>, i32)) -> Page<T> {
+        let has_more = items.len() as i64 > self.limit;
+        items.truncate(self.limit as usize);
+        let next_cursor = if has_more {
+            items.last().map(|item| {
+                let (created_at, uid) = key(item);
+                encode_cursor(created_at, uid)
+            })
+        } else {
+            None
+        };
+        Page { items, next_cursor }
+    }
+}
+
+/// `2024-01-15T10:00:00.000000Z,42` — microsecond precision matches Postgres
+/// `timestamptz`, so the cursor round-trips exactly.
+pub fn encode_cursor(created_at: DateTime<Utc>, uid: i32) -> String {
+    format!(
+        "{},{}",
+        created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        uid
+    )
+}
+
+pub fn decode_cursor(raw: &str) -> Result<(DateTime<Utc>, i32), AppError> {
+    let invalid = || AppError::BadRequest(format!("Invalid cursor `{raw}`"));
+    let (created_at, uid) = raw.rsplit_once(',').ok_or_else(invalid)?;
+    let created_at = DateTime::parse_from_rfc3339(created_at.trim())
+        .map_err(|_| invalid())?
+        .with_timezone(&Utc);
+    let uid = uid.trim().parse::<i32>().map_err(|_| invalid())?;
+    Ok((created_at, uid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let created_at = Utc.timestamp_micros(1_705_312_800_123_456).unwrap();
+        let raw = encode_cursor(created_at, 42);
+        assert_eq!(raw, "2024-01-15T10:00:00.123456Z,42");
+        assert_eq!(decode_cursor(&raw).unwrap(), (created_at, 42));
+        assert!(decode_cursor("yesterday,42").is_err());
+        assert!(decode_cursor("2024-01-15T10:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_page_sets_next_cursor_only_when_more_rows() {
+        let request = PageRequest { after: None, limit: Some(2), sort: None };
+        let keyset = request.keyset(DEFAULT_PAGE_SIZE).unwrap();
+        assert_eq!(keyset.fetch_limit(), 3);
+
+        let at = Utc.timestamp_opt(1_705_312_800, 0).unwrap();
+        let page = keyset.page(vec![3, 2, 1], |uid| (at, *uid));
+        assert_eq!(page.items, vec![3, 2]);
+        assert_eq!(page.next_cursor, Some(encode_cursor(at, 2)));
+
+        let page = keyset.page(vec![3, 2], |uid| (at, *uid));
+        assert_eq!(page.next_cursor, None);
+    }
+}